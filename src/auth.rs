@@ -0,0 +1,182 @@
+use std::fs::{File, OpenOptions, Permissions};
+use std::io::{BufReader, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use log::info;
+use ring::digest::{digest, SHA256};
+use rustix::process::getuid;
+use serde::{Deserialize, Serialize};
+
+const TOKEN_LENGTH: usize = 32;
+
+/// What a token lets its client do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    // list tasks, contexts and history
+    Read,
+    // anything, like a local client
+    Full,
+}
+
+/// A bearer token clients of tcp endpoints send with their hello. Only its
+/// hash is kept; the token itself is shown once, when it is created.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Token {
+    pub name: String,
+    // the sha-256 of the token, in hex
+    #[serde(default)]
+    pub hash: String,
+    pub scope: Scope,
+    // the token itself, as kept by older releases; hashed when loaded
+    #[serde(default, skip_serializing)]
+    token: Option<String>,
+}
+
+fn hash(token: &str) -> String {
+    digest(&SHA256, token.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// The tokens in `$FMN_DIR/tokens.json`, readable by the owner only.
+#[derive(Debug, Clone, Default)]
+pub struct Tokens {
+    path: PathBuf,
+    tokens: Vec<Token>,
+}
+
+impl Tokens {
+    pub fn load<P: AsRef<Path>>(fmn_dir: P) -> Result<Self> {
+        let path = fmn_dir.as_ref().join("tokens.json");
+        if !path.exists() {
+            return Ok(Self {
+                path,
+                tokens: vec![],
+            });
+        }
+        let file = File::open(&path).context(format!("fail to open tokens {path:?}"))?;
+        let mut tokens: Vec<Token> = serde_json::from_reader(BufReader::new(file))
+            .context(format!("fail to parse tokens {path:?}"))?;
+        let mut plain = false;
+        for token in tokens.iter_mut() {
+            if let Some(secret) = token.token.take() {
+                token.hash = hash(&secret);
+                plain = true;
+            }
+        }
+        let tokens = Self { path, tokens };
+        if plain {
+            info!("hash the tokens kept in plain text in {:?}", tokens.path);
+            tokens.save()?;
+        }
+        Ok(tokens)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&self.path)
+            .context(format!("fail to open tokens {:?}", self.path))?;
+        // the mode above only applies to a new file
+        file.set_permissions(Permissions::from_mode(0o600))
+            .context(format!("fail to restrict tokens {:?}", self.path))?;
+        file.write_all(serde_json::to_string_pretty(&self.tokens)?.as_bytes())
+            .context(format!("fail to write tokens {:?}", self.path))
+    }
+
+    // a new random token, returned to be handed to the client
+    pub fn create(&mut self, name: &str, scope: Scope) -> Result<String> {
+        if self.tokens.iter().any(|t| t.name == name) {
+            return Err(anyhow!("token {name:?} already exists"));
+        }
+        let token = nanoid::nanoid!(TOKEN_LENGTH);
+        self.tokens.push(Token {
+            name: name.to_owned(),
+            hash: hash(&token),
+            scope,
+            token: None,
+        });
+        Ok(token)
+    }
+
+    pub fn revoke(&mut self, name: &str) -> Result<()> {
+        let count = self.tokens.len();
+        self.tokens.retain(|t| t.name != name);
+        if self.tokens.len() == count {
+            return Err(anyhow!("no token named {name:?}"));
+        }
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Token> {
+        self.tokens.iter()
+    }
+
+    pub fn find(&self, token: &str) -> Option<&Token> {
+        let hash = hash(token);
+        self.tokens
+            .iter()
+            .find(|t| same_secret(t.hash.as_bytes(), hash.as_bytes()))
+    }
+}
+
+// compares in a time that doesn't depend on where they differ
+fn same_secret(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// How the clients of an endpoint are let in.
+#[derive(Debug, Clone)]
+pub enum Auth {
+    // clients of a unix socket running as the user of the daemon
+    Trusted,
+    // clients of a unix socket running as another allowed user; they may
+    // not make the daemon run commands as its user
+    Peer,
+    // clients that have to present one of the tokens
+    Token(Tokens),
+    // clients turned away for the reason
+    Denied(String),
+}
+
+impl Auth {
+    /// How a client of a unix socket running as `uid` is let in, once its
+    /// uid is allowed: only the user of the daemon is trusted.
+    pub fn of_peer(uid: u32) -> Self {
+        if uid == getuid().as_raw() {
+            Auth::Trusted
+        } else {
+            Auth::Peer
+        }
+    }
+
+    // whether its clients may send requests that run shell commands
+    pub fn may_run_commands(&self) -> bool {
+        matches!(self, Auth::Trusted)
+    }
+
+    // the scope of a client presenting `token`, or why it is turned away
+    pub fn authorize(&self, token: Option<&str>) -> std::result::Result<Scope, String> {
+        let tokens = match self {
+            Auth::Trusted | Auth::Peer => return Ok(Scope::Full),
+            Auth::Token(tokens) => tokens,
+            Auth::Denied(reason) => return Err(reason.clone()),
+        };
+        let Some(token) = token else {
+            return Err("this endpoint requires a token; set FMN_TOKEN".to_owned());
+        };
+        match tokens.find(token) {
+            Some(token) => Ok(token.scope),
+            None => Err("the token is unknown or revoked".to_owned()),
+        }
+    }
+}
@@ -1,50 +1,136 @@
 use std::env;
-use std::io::{BufReader, BufWriter};
-#[cfg(feature = "tcp")]
-use std::net::TcpListener;
-use std::os::unix::net::UnixListener;
+use std::sync::{Arc, Mutex};
 
-use anyhow::{Context, Result};
-use task_reminder::daemon::serve;
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use log::info;
+use task_reminder::auth::{Scope, Tokens};
+use task_reminder::config::Config;
+use task_reminder::daemon::{forward_actions, listen};
+use task_reminder::endpoint::Endpoint;
+use task_reminder::history::History;
+use task_reminder::http::serve_http;
+use task_reminder::notify::{action_receiver, NotifierRegistry};
 use task_reminder::scheduler::Scheduler;
 use task_reminder::task_manager::TaskManager;
 
+#[derive(Parser)]
+#[command(author, version, about, long_about=None)]
+struct Cli {
+    // where to take requests, e.g. unix:/run/user/1000/fmn/fmn.sock or
+    // tcp:127.0.0.1:8082; repeat it to listen on several endpoints.
+    // $FMN_DAEMON_ADDR or the private socket of the user if not given
+    #[arg(short, long)]
+    listen: Vec<Endpoint>,
+
+    // also serve the REST API at host:port, e.g. 127.0.0.1:8083; see
+    // GET /openapi.json there
+    #[arg(long)]
+    http: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    // manage the tokens clients of tcp and tls endpoints present
+    Token {
+        #[command(subcommand)]
+        command: TokenCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum TokenCommand {
+    // prints a new token for the client to set as FMN_TOKEN
+    Create {
+        name: String,
+        #[arg(short, long, value_enum, default_value_t = Scope::Full)]
+        scope: Scope,
+    },
+    List,
+    Revoke {
+        name: String,
+    },
+}
+
 fn main() -> Result<()> {
     task_reminder::setup_logger();
-    #[cfg(feature = "unix_socket")]
-    let addr = env::var("FMN_DAEMON_UNIX_ADDR").unwrap_or_else(|_| "/tmp/fmn.sock".to_owned());
-
-    #[cfg(feature = "tcp")]
-    let addr = env::var("FMN_DAEMON_ADDR").unwrap_or_else(|_| "127.0.0.1:8082".to_owned());
+    let cli = Cli::parse();
     let fmn_dir =
         env::var("FMN_DIR").unwrap_or_else(|_| format!("{}/.fmn", env::var("HOME").unwrap()));
-    spawn_daemon(addr, fmn_dir)
+    if let Some(Command::Token { command }) = cli.command {
+        return manage_tokens(command, &fmn_dir);
+    }
+    let mut endpoints = cli.listen;
+    if endpoints.is_empty() {
+        // FMN_DAEMON_UNIX_ADDR is the name of older releases
+        let addr = env::var("FMN_DAEMON_ADDR").or_else(|_| env::var("FMN_DAEMON_UNIX_ADDR"));
+        endpoints.push(match addr {
+            Ok(addr) => addr.parse()?,
+            Err(_) => Endpoint::default(),
+        });
+    }
+    spawn_daemon(endpoints, cli.http, fmn_dir)
 }
 
-pub fn spawn_daemon(addr: String, fmn_dir: String) -> Result<()> {
-    std::fs::create_dir_all(&fmn_dir)?;
-    let scheduler = Scheduler::new();
-    let tm = TaskManager::new(&fmn_dir, scheduler)?;
-    start_listen(&addr, tm)?;
+fn manage_tokens(command: TokenCommand, fmn_dir: &str) -> Result<()> {
+    std::fs::create_dir_all(fmn_dir)?;
+    let mut tokens = Tokens::load(fmn_dir)?;
+    match command {
+        TokenCommand::Create { name, scope } => {
+            let token = tokens.create(&name, scope)?;
+            tokens.save()?;
+            println!("{token}");
+        }
+        TokenCommand::List => {
+            for token in tokens.iter() {
+                println!("{} ({:?})", token.name, token.scope);
+            }
+        }
+        TokenCommand::Revoke { name } => {
+            tokens.revoke(&name)?;
+            tokens.save()?;
+        }
+    }
     Ok(())
 }
 
-fn start_listen(addr: &str, mut tm: TaskManager) -> Result<()> {
-    #[cfg(feature = "tcp")]
-    let listener = TcpListener::bind(addr).context("fail to create tcp socket")?;
-
-    #[cfg(feature = "unix_socket")]
-    {
-        if std::path::Path::new(&addr).exists() {
-            std::fs::remove_file(addr)?;
-        }
+pub fn spawn_daemon(endpoints: Vec<Endpoint>, http: Option<String>, fmn_dir: String) -> Result<()> {
+    std::fs::create_dir_all(&fmn_dir)?;
+    let config = Config::load(&fmn_dir)?;
+    let notifiers = NotifierRegistry::from_config(&config.notifiers)?
+        .with_hook(config.hook)
+        .with_sound(config.sound)
+        .with_digest(config.digest)
+        .with_retry(config.retry)
+        .with_history(History::new(&fmn_dir));
+    info!("deliver reminders via: {}", notifiers.names().join(", "));
+    let scheduler = Scheduler::with_notifiers(notifiers);
+    let tm = Arc::new(Mutex::new(TaskManager::new(&fmn_dir, scheduler)?));
+    let mut listeners = vec![];
+    for endpoint in endpoints.iter() {
+        listeners.push(listen(
+            endpoint,
+            tm.clone(),
+            config.server.clone(),
+            fmn_dir.as_ref(),
+        )?);
     }
-    #[cfg(feature = "unix_socket")]
-    let listener = UnixListener::bind(addr).context("fail to create unix socket")?;
-    for stream in listener.incoming() {
-        let stream = stream?;
-        serve(BufReader::new(&stream), BufWriter::new(&stream), &mut tm)?;
+    if let Some(addr) = http {
+        listeners.push(serve_http(
+            &addr,
+            tm.clone(),
+            config.server.max_connections,
+            fmn_dir.as_ref(),
+        )?);
+    }
+    if let Some(actions) = action_receiver() {
+        forward_actions(actions, tm);
+    }
+    for listener in listeners {
+        let _ = listener.join();
     }
-
     Ok(())
 }
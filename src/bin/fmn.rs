@@ -1,21 +1,32 @@
 #![forbid(unsafe_code)]
 
 use std::env;
+use std::io::Write;
 
 use anyhow::{anyhow, Result};
 use clap::{Parser, Subcommand};
-use task_reminder::client::send_request;
+use task_reminder::client::{send_request, subscribe};
 use task_reminder::comm::{
-    get_local_now, parse_at, parse_date, parse_duration, ContextCommand, Request, Response,
+    get_local_now, parse_at, parse_date, parse_duration, validate_clock_type, AddRequest,
+    ContextCommand, Request, Response,
 };
-use task_reminder::format::tabular_output;
-use task_reminder::task_manager::ClockType;
+use task_reminder::endpoint::Endpoint;
+use task_reminder::events::Subscription;
+use task_reminder::format::{context_output, history_output, tabular_output};
+use task_reminder::task_manager::{ClockType, NotificationOptions, Urgency};
+use task_reminder::template::validate_template;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about=None)]
 struct Cli {
     #[command(subcommand)]
     command: Command,
+
+    // the daemon to talk to, e.g. unix:/run/user/1000/fmn/fmn.sock or
+    // tcp:127.0.0.1:8082; $FMN_DAEMON_ADDR or the private socket of the user
+    // if not given
+    #[arg(long, global = true)]
+    addr: Option<Endpoint>,
 }
 
 #[derive(Subcommand)]
@@ -25,20 +36,68 @@ enum Command {
         #[command(subcommand)]
         command: AddCommand,
 
-        #[arg(short, long)]
+        // the options below may also follow the clock, e.g.
+        // `fmn add "backup" at 2:00 --per-day --exec "~/bin/backup.sh"`
+        #[arg(short, long, global = true)]
         image_path: Option<String>,
 
-        #[arg(short, long)]
+        #[arg(short, long, global = true)]
         sound_path: Option<String>,
+
+        // a shell command run by fmn-daemon when the reminder fires
+        #[arg(short, long, global = true)]
+        exec: Option<String>,
+
+        #[arg(short, long, value_enum, global = true)]
+        urgency: Option<Urgency>,
+
+        // how long the notification stays on screen, e.g. 30s; 0s keeps it
+        // until dismissed
+        #[arg(long, global = true)]
+        timeout: Option<String>,
+
+        #[arg(long, global = true)]
+        icon: Option<String>,
+
+        // the title of the notification instead of "forget-me-not"
+        #[arg(long, global = true)]
+        summary: Option<String>,
+
+        // update the previous notification of the task instead of stacking
+        // a new one
+        #[arg(long, global = true)]
+        replace: bool,
+
+        // the volume of the sound in percent
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100), global = true)]
+        volume: Option<u8>,
+
+        // how many times the sound is played
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..), global = true)]
+        repeat: Option<u32>,
+
+        // deliver to these notifiers of the daemon config instead of the
+        // ones of the context or the config's routing
+        #[arg(long = "notifier", global = true)]
+        notifiers: Vec<String>,
     },
     Rm {
         task_id: String,
     },
     List,
+    History {
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
     Context {
         #[command(subcommand)]
         command: ContextCommand,
     },
+    // print the events of the daemon as they happen, a json object per line
+    Watch {
+        #[command(flatten)]
+        subscription: Subscription,
+    },
 }
 
 #[derive(Subcommand)]
@@ -61,13 +120,34 @@ enum AddCommand {
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    let dest = match cli.addr {
+        Some(endpoint) => endpoint,
+        None => match env::var("FMN_DAEMON_ADDR") {
+            Ok(addr) => addr.parse()?,
+            Err(_) => Endpoint::default(),
+        },
+    }
+    .to_string();
     let request = match cli.command {
         Command::Add {
             description,
             command,
-            mut image_path,
-            mut sound_path,
+            image_path,
+            sound_path,
+            exec,
+            urgency,
+            timeout,
+            icon,
+            summary,
+            replace,
+            volume,
+            repeat,
+            notifiers,
         } => {
+            validate_template(&description)?;
+            if let Some(summary) = &summary {
+                validate_template(summary)?;
+            }
             let clock_type = match command {
                 AddCommand::At { time, per_day } => {
                     let next_fire = parse_at(&time)?;
@@ -86,48 +166,69 @@ fn main() -> Result<()> {
                     ClockType::Once(next_fire)
                 }
                 AddCommand::Per { duration } => {
-                    let _ = parse_duration(&duration)?;
-                    ClockType::Period(duration)
+                    let clock_type = ClockType::Period(duration);
+                    validate_clock_type(&clock_type)?;
+                    clock_type
                 }
                 AddCommand::On { date } => {
                     let next_fire = parse_date(&date)?;
                     ClockType::Once(next_fire)
                 }
             };
-            if image_path.is_none() {
-                if let Ok(system_image_path) = env::var("FMN_IMAGE_PATH") {
-                    image_path = Some(system_image_path);
-                }
-            }
-            if sound_path.is_none() {
-                if let Ok(system_sound_path) = env::var("FMN_SOUND_PATH") {
-                    sound_path = Some(system_sound_path);
-                }
-            }
-            Request::Add(description, clock_type, image_path, sound_path)
+            let timeout_ms = match timeout {
+                Some(timeout) => Some(
+                    u32::try_from(parse_duration(&timeout)?.as_millis())
+                        .map_err(|_| anyhow!("timeout {timeout} is too long"))?,
+                ),
+                None => None,
+            };
+            let notification = NotificationOptions {
+                urgency,
+                timeout_ms,
+                icon,
+                summary,
+                replace,
+                volume,
+                repeat,
+                notifiers,
+            };
+            Request::Add(AddRequest {
+                description,
+                clock_type,
+                image_path,
+                sound_path,
+                exec,
+                notification,
+            })
         }
         Command::Rm { task_id } => Request::Cancel(task_id),
         Command::List => Request::Show,
+        Command::History { limit } => Request::History(limit),
         Command::Context { command } => Request::ContextRequest(command),
+        Command::Watch { subscription } => return watch(subscription, &dest),
     };
 
     //println!("request is {:?}", request);
-    #[cfg(feature = "tcp")]
-    let dest = env::var("FMN_DAEMON_ADDR").unwrap_or_else(|_| "127.0.0.1:8082".to_owned());
-
-    #[cfg(feature = "unix_socket")]
-    let dest = env::var("FMN_DAEMON_ADDR").unwrap_or_else(|_| "/tmp/fmn.sock".to_owned());
     match send_request(request.clone(), &dest) {
         Ok(response) => match response {
             Response::GetTasks(tasks) => {
                 println!("{}", tabular_output(&tasks));
             }
+            Response::GetHistory(records) => {
+                println!("{}", history_output(&records));
+            }
             Response::GetContexts(contexts) => {
                 println!(" * {}", contexts.join("\n   "));
             }
+            Response::GetContextSettings(context, settings) => {
+                println!("{}", context_output(&context, &settings));
+            }
             Response::Fail(error_string) => {
                 eprintln!("request \"{request:?}\" failed: {error_string}");
             }
+            Response::Unauthorized(reason) => {
+                eprintln!("request \"{request:?}\" is not authorized: {reason}");
+            }
             _ => println!("success: {response:?}"),
         },
         Err(e) => {
@@ -136,3 +237,12 @@ fn main() -> Result<()> {
     }
     Ok(())
 }
+
+fn watch(subscription: Subscription, dest: &str) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    for event in subscribe(subscription, dest)? {
+        writeln!(stdout, "{}", serde_json::to_string(&event?)?)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
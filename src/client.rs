@@ -1,30 +1,138 @@
-use std::io::{BufReader, Write};
-#[cfg(feature = "tcp")]
+use std::io::{BufReader, Read, Write};
 use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
 
-use anyhow::{Context, Result};
-use serde::Deserialize;
-use serde_json::{to_string, Deserializer};
+use anyhow::{anyhow, Context, Result};
+use rustls::ClientConnection;
+use serde::{Deserialize, Serialize};
+use serde_json::de::IoRead;
+use serde_json::{from_value, to_string, Deserializer, Value};
 
-use crate::comm::{Request, Response};
+use crate::comm::{Hello, Request, Response};
+use crate::endpoint::{Endpoint, Stream, TlsStream};
+use crate::events::{Event, Subscription};
+use crate::tls::{client_config, server_name, Credentials};
 
+// `dest` is an endpoint like unix:/run/user/1000/fmn/fmn.sock or
+// tcp:127.0.0.1:8082; the credentials come from the environment
 pub fn send_request(request: Request, dest: &str) -> Result<Response> {
-    #[cfg(feature = "tcp")]
-    let mut stream = TcpStream::connect(dest)
-        .context(format!("fail to connect to fmn-deamon using tcp: {dest}"))?;
-
-    #[cfg(feature = "unix_socket")]
-    let mut stream = std::os::unix::net::UnixStream::connect(dest).context(format!(
-        "fail to connect to fmn-deamon using unix socket: {dest}"
-    ))?;
-
-    let serialized = to_string(&request).expect("fail to serialize request");
-    stream
-        .write_all(serialized.as_bytes())
-        .context("fail to send requests to fmn-daemon")?;
-
-    let mut reader = Deserializer::from_reader(BufReader::new(stream.try_clone()?));
-    let response: Response =
-        Response::deserialize(&mut reader).context("fail to deserialize response")?;
-    Ok(response)
+    send_request_as(request, dest, &Credentials::from_env())
+}
+
+pub fn send_request_as(
+    request: Request,
+    dest: &str,
+    credentials: &Credentials,
+) -> Result<Response> {
+    let mut connection = match connect(dest, credentials)? {
+        Ok(connection) => connection,
+        Err(response) => return Ok(response),
+    };
+    connection.send(&request)?;
+    Response::deserialize(&mut connection.reader).context("fail to deserialize response")
+}
+
+/// The events the daemon at `dest` streams for the subscription, as they
+/// happen; it ends when the daemon goes away.
+pub fn subscribe(
+    subscription: Subscription,
+    dest: &str,
+) -> Result<impl Iterator<Item = Result<Event>>> {
+    subscribe_as(subscription, dest, &Credentials::from_env())
+}
+
+pub fn subscribe_as(
+    subscription: Subscription,
+    dest: &str,
+    credentials: &Credentials,
+) -> Result<impl Iterator<Item = Result<Event>>> {
+    let mut connection = match connect(dest, credentials)? {
+        Ok(connection) => connection,
+        Err(response) => return Err(refusal(response)),
+    };
+    connection.send(&Request::Subscribe(subscription))?;
+    match Response::deserialize(&mut connection.reader).context("fail to deserialize response")? {
+        Response::Subscribed => {}
+        response => return Err(refusal(response)),
+    }
+    Ok(connection
+        .reader
+        .into_iter::<Event>()
+        .map(|event| event.context("fail to read event")))
+}
+
+fn refusal(response: Response) -> anyhow::Error {
+    match response {
+        Response::Fail(reason) | Response::Unauthorized(reason) => {
+            anyhow!("fmn-daemon refuses the subscription: {reason}")
+        }
+        response => anyhow!("unexpected response to the subscription: {response:?}"),
+    }
+}
+
+// a connection past the handshake
+struct Connection {
+    writer: Box<dyn Write + Send>,
+    reader: Deserializer<IoRead<BufReader<Box<dyn Read + Send>>>>,
+}
+
+impl Connection {
+    fn send<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        let serialized = to_string(message).expect("fail to serialize request");
+        self.writer
+            .write_all(serialized.as_bytes())
+            .context("fail to send requests to fmn-daemon")
+    }
+}
+
+// the connection, or the answer of a daemon that doesn't let the client in
+fn connect(dest: &str, credentials: &Credentials) -> Result<Result<Connection, Response>> {
+    let endpoint: Endpoint = dest.parse()?;
+    let connect_error = || format!("fail to connect to fmn-deamon at {endpoint}");
+    let (writer, reader) = match &endpoint {
+        Endpoint::Unix(path) => split(UnixStream::connect(path).with_context(connect_error)?)?,
+        Endpoint::Tcp(addr) => split(TcpStream::connect(addr).with_context(connect_error)?)?,
+        Endpoint::Tls(addr) => {
+            let connection = ClientConnection::new(client_config(credentials)?, server_name(addr)?)
+                .context("fail to set up tls")?;
+            let sock = TcpStream::connect(addr).with_context(connect_error)?;
+            split(TlsStream::new(connection, sock))?
+        }
+    };
+    let mut connection = Connection {
+        writer,
+        reader: Deserializer::from_reader(BufReader::new(reader)),
+    };
+
+    let mut hello = Hello::current();
+    hello.token = credentials.token.clone();
+    connection
+        .send(&hello)
+        .context("fail to greet fmn-daemon")?;
+    let answer = Value::deserialize(&mut connection.reader)
+        .context("fail to read the greeting of fmn-daemon")?;
+    let daemon_hello = match from_value::<Hello>(answer.clone()) {
+        Ok(daemon_hello) => daemon_hello,
+        // a client the daemon doesn't let in is told why
+        Err(_) => match from_value::<Response>(answer) {
+            Ok(response @ Response::Unauthorized(_)) => return Ok(Err(response)),
+            // a daemon from before the handshake answers with a failure instead
+            _ => {
+                return Err(anyhow!(
+                    "fmn-daemon doesn't answer the protocol handshake of fmn {}; \
+                     run the same release of fmn and fmn-daemon",
+                    hello.version
+                ))
+            }
+        },
+    };
+    hello.check("fmn", &daemon_hello, "fmn-daemon")?;
+    Ok(Ok(connection))
+}
+
+type Halves = (Box<dyn Write + Send>, Box<dyn Read + Send>);
+
+fn split<S: Stream>(stream: S) -> Result<Halves> {
+    let reader = stream.try_clone()?;
+    Ok((Box::new(stream), Box::new(reader)))
 }
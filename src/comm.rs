@@ -1,32 +1,165 @@
-use std::time::Duration;
-
 use anyhow::{anyhow, Context, Result};
 use clap::Subcommand;
 use log::warn;
 use once_cell::sync::OnceCell;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use time::{format_description::well_known::Iso8601, OffsetDateTime, PrimitiveDateTime, UtcOffset};
+use time::format_description::well_known::{Iso8601, Rfc3339};
+use time::macros::{format_description, time};
+use time::{Date, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
 
-use crate::task_manager::{ClockType, Task, TaskContext, TaskID};
+pub use crate::duration::{parse_duration, DurationError};
+use crate::events::Subscription;
+use crate::history::FiringRecord;
+use crate::task_manager::{
+    ClockType, ContextSettings, NotificationOptions, Task, TaskContext, TaskID,
+};
 
 static TZDIFF: OnceCell<UtcOffset> = OnceCell::new();
+const DEFAULT_TIME_OF_DAY: Time = time!(9:00);
+// shorter periods would keep the notifiers, hooks and history busy
+const MIN_PERIOD: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// The version of the requests and responses below. It is bumped whenever
+/// one side could no longer read what the other sends: a new request or
+/// response, or a new field without a default. New optional fields keep the
+/// version, since both sides ignore fields they don't know.
+pub const PROTOCOL_VERSION: u32 = 5;
+
+/// The first message on a connection: the client sends its own and the
+/// daemon answers with its own, closing the connection if they don't match.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Hello {
+    pub protocol: u32,
+    // the package version of the sender
+    pub version: String,
+    // the bearer token of a client of a tcp endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl Hello {
+    pub fn current() -> Self {
+        Self {
+            protocol: PROTOCOL_VERSION,
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            token: None,
+        }
+    }
+
+    // fails with a message for the user unless `peer` speaks our protocol
+    pub fn check(&self, name: &str, peer: &Hello, peer_name: &str) -> Result<()> {
+        if peer.protocol == self.protocol {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "{peer_name} {} speaks protocol version {} but {name} {} speaks version {}; \
+             run the same release of fmn and fmn-daemon, e.g. restart fmn-daemon after an upgrade",
+            peer.version,
+            peer.protocol,
+            self.version,
+            self.protocol
+        ))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AddRequest {
+    pub description: String,
+    pub clock_type: ClockType,
+    pub image_path: Option<String>,
+    pub sound_path: Option<String>,
+    // a shell command run when the task fires
+    pub exec: Option<String>,
+    #[serde(default)]
+    pub notification: NotificationOptions,
+}
+
+impl AddRequest {
+    pub fn new(description: String, clock_type: ClockType) -> Self {
+        Self {
+            description,
+            clock_type,
+            image_path: None,
+            sound_path: None,
+            exec: None,
+            notification: NotificationOptions::default(),
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Request {
-    // name, clock_type, image_path, sound_path
-    Add(String, ClockType, Option<String>, Option<String>),
+    Add(AddRequest),
     Cancel(TaskID),
     Show,
     ContextRequest(ContextCommand),
+    // the latest n firings
+    History(usize),
+    // from the "Done" button of a notification
+    Acknowledge(TaskID),
+    // remind of the task of the current context again after the duration
+    Snooze {
+        task_id: TaskID,
+        after: std::time::Duration,
+    },
+    // answered with `Subscribed`, after which the connection carries the
+    // events, one json object per line, until the client hangs up
+    Subscribe(Subscription),
+    // puts the task in place of the task of the current context with its id
+    Replace(Task),
+}
+
+impl Request {
+    // whether a client with a read-only token may send it
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            Request::Show | Request::History(_) | Request::Subscribe(_) => true,
+            Request::ContextRequest(command) => {
+                matches!(command, ContextCommand::List | ContextCommand::Show { .. })
+            }
+            _ => false,
+        }
+    }
+
+    // whether it makes the daemon run a shell command; only clients of the
+    // unix socket running as the user of the daemon may send those
+    pub fn runs_commands(&self) -> bool {
+        match self {
+            Request::Add(request) => request.exec.is_some(),
+            Request::Replace(task) => task.get_exec().is_some(),
+            _ => false,
+        }
+    }
 }
 
 #[derive(Subcommand, Debug, Serialize, Deserialize, Clone)]
 pub enum ContextCommand {
-    Set { context: TaskContext },
+    Set {
+        context: TaskContext,
+    },
     List,
-    Rm { context: TaskContext },
-    Define { context: TaskContext },
+    Rm {
+        context: TaskContext,
+    },
+    Define {
+        context: TaskContext,
+        #[command(flatten)]
+        settings: ContextSettings,
+    },
+    // changes the given defaults of the tasks of the context
+    Configure {
+        context: TaskContext,
+        #[command(flatten)]
+        settings: ContextSettings,
+        // drop the current defaults first
+        #[arg(long)]
+        clear: bool,
+    },
+    // the defaults of a context, the current one if not given
+    Show {
+        context: Option<TaskContext>,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -37,38 +170,31 @@ pub enum Response {
     GetTasks(Vec<Task>),
     GetContexts(Vec<TaskContext>), // for list context
     SetContextSuccess,             // for set context
+    GetHistory(Vec<FiringRecord>),
+    AcknowledgeSuccess,
+    ConfigureContextSuccess,
+    GetContextSettings(TaskContext, ContextSettings), // for show context
+    // the token is missing, unknown or not allowed to make the request
+    Unauthorized(String),
+    Subscribed,
 }
 
-pub fn parse_duration(duration: &str) -> Result<Duration> {
-    let re = Regex::new(
-        r"^(?:(?P<day>\d+)d)?(?:(?P<hour>\d+)h)?(?:(?P<minute>\d+)m)?(?:(?P<second>\d+)s)?$",
-    )
-    .unwrap();
-    if !re.is_match(duration) {
-        return Err(anyhow!(
-            "invalid duration format; valid examples: 1d1h1m1s, 2h, 30s, 55m"
-        ));
-    }
-    if let Some(captures) = re.captures(duration) {
-        let mut components = [0_u64; 4];
-        for (i, component) in ["day", "hour", "minute", "second"].into_iter().enumerate() {
-            components[i] = captures
-                .name(component)
-                .map(|m| {
-                    // dbg!(component, m.as_str());
-                    m.as_str()
-                })
-                .unwrap_or_else(|| "0")
-                .parse()
-                .context(format!("invalid {component}"))?;
+// shared by fmn and fmn-daemon so both reject the same clocks
+pub fn validate_clock_type(clock_type: &ClockType) -> Result<()> {
+    match clock_type {
+        ClockType::Period(period) => {
+            if parse_duration(period)? < MIN_PERIOD {
+                return Err(anyhow!("per <duration> should be at least 1s"));
+            }
         }
-
-        let secs =
-            components[0] * 3600 * 24 + components[1] * 3600 + components[2] * 60 + components[3];
-        Ok(Duration::from_secs(secs))
-    } else {
-        Ok(Duration::from_secs(0))
+        ClockType::Once(next_fire) => {
+            if *next_fire <= OffsetDateTime::now_utc() {
+                return Err(anyhow!("date {next_fire} is in the past"));
+            }
+        }
+        ClockType::OncePerDay(..) => {}
     }
+    Ok(())
 }
 
 pub fn get_local_utc_offset() -> UtcOffset {
@@ -83,11 +209,46 @@ pub fn get_local_now() -> OffsetDateTime {
     OffsetDateTime::now_utc().to_offset(get_local_utc_offset())
 }
 
+// the time of day used for date-only input like `fmn add foo on 2023-11-12`
+pub fn default_time_of_day() -> Result<Time> {
+    match std::env::var("FMN_DEFAULT_TIME") {
+        Ok(time) => Time::parse(&time, format_description!("[hour padding:none]:[minute]"))
+            .context(format!("invalid FMN_DEFAULT_TIME {time:?}; expected HH:MM")),
+        Err(_) => Ok(DEFAULT_TIME_OF_DAY),
+    }
+}
+
 // parse user's date input
 pub fn parse_date(date: &str) -> Result<OffsetDateTime> {
-    PrimitiveDateTime::parse(date, &Iso8601::DEFAULT)
-        .map(|x| x.assume_offset(get_local_utc_offset()))
-        .context("fail to parse Iso8601 date")
+    parse_date_with_default(date, default_time_of_day()?)
+}
+
+// accepts RFC 3339 and ISO 8601 (calendar, week and ordinal dates) with or without
+// an offset; input without an offset is taken as local time and date-only input
+// fires at `default_time`
+pub fn parse_date_with_default(date: &str, default_time: Time) -> Result<OffsetDateTime> {
+    let date = date.trim();
+    let local_offset = get_local_utc_offset();
+    let next_fire = OffsetDateTime::parse(date, &Rfc3339)
+        .or_else(|_| OffsetDateTime::parse(date, &Iso8601::DEFAULT))
+        .or_else(|_| {
+            PrimitiveDateTime::parse(date, &Iso8601::DEFAULT).map(|x| x.assume_offset(local_offset))
+        })
+        .or_else(|_| {
+            Date::parse(date, &Iso8601::DEFAULT)
+                .map(|x| x.with_time(default_time).assume_offset(local_offset))
+        })
+        .map_err(|_| {
+            anyhow!(
+                "fail to parse date {date:?}; valid examples: 2023-11-12T09:20, \
+                 2023-11-12T09:20+09:00, 2023-11-12T01:20Z, 2023-11-12, 2023-W46-7T09:20"
+            )
+        })?
+        .to_offset(local_offset);
+    if next_fire <= get_local_now() {
+        return Err(anyhow!("date {date:?} ({next_fire}) is in the past"));
+    }
+    Ok(next_fire)
 }
 
 // only used for at
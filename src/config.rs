@@ -0,0 +1,42 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::daemon::ServerConfig;
+use crate::notify::{DigestConfig, HookConfig, NotifierConfig, RetryPolicy, SoundConfig};
+
+/// Daemon settings read from `$FMN_DIR/config.json`; every field is optional.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // where firings are delivered; the desktop notifier if empty
+    pub notifiers: Vec<NotifierConfig>,
+    // a command run for every firing
+    pub hook: HookConfig,
+    // how the sounds of tasks are played
+    pub sound: SoundConfig,
+    // how firings close to each other are coalesced
+    pub digest: DigestConfig,
+    // how firings no notifier delivered are tried again
+    pub retry: RetryPolicy,
+    // timeouts of client connections
+    pub server: ServerConfig,
+}
+
+impl Config {
+    pub fn load<P>(fmn_dir: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = fmn_dir.as_ref().join("config.json");
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file = File::open(&path).context(format!("fail to open config {path:?}"))?;
+        serde_json::from_reader(BufReader::new(file))
+            .context(format!("fail to parse config {path:?}"))
+    }
+}
@@ -1,77 +1,561 @@
-use std::io::{BufReader, BufWriter, Read, Write};
+use std::cell::Cell;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::TcpListener;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-use anyhow::{Context, Result};
-use log::{error, info};
-use serde_json::{to_string, Deserializer};
+use anyhow::{anyhow, Context, Result};
+use log::{error, info, warn};
+use rustls::ServerConnection;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_value, to_string, Deserializer, Value};
 
-use crate::comm::{ContextCommand, Request, Response};
+use crate::auth::{Auth, Scope, Tokens};
+use crate::comm::{validate_clock_type, AddRequest, ContextCommand, Hello, Request, Response};
+use crate::endpoint::{Endpoint, Stream, TlsStream};
+use crate::events::{Event, Subscription};
+use crate::notify::TaskAction;
+use crate::socket::{bind_private, check_peer};
 use crate::task_manager::{Task, TaskManager};
+use crate::template::validate_template;
+use crate::tls::{server_config, TlsConfig};
 
-pub fn serve<S>(reader: BufReader<S>, mut writer: BufWriter<S>, tm: &mut TaskManager) -> Result<()>
-where
-    S: Read + Write,
-{
-    let requests = Deserializer::from_reader(reader).into_iter::<Request>();
-    for request in requests {
-        let request = request?;
-        info!("receive a request: {:?}", request);
-        tm.refresh_before();
-        let response = {
-            match request {
-                Request::Add(description, clock_type, image_path, sound_path) => {
-                    let mut task =
-                        Task::new(description, clock_type).with_context(tm.current_context());
-                    if let Some(image_path) = image_path {
-                        task.add_image(image_path);
-                    }
-                    if let Some(sound_path) = sound_path {
-                        task.add_sound(sound_path);
-                    }
-                    match tm.add_task(task) {
-                        Err(e) => {
-                            error!("fail to add new task in udp server: {}", e);
-                            Response::Fail(e.to_string())
-                        }
-                        Ok(_) => Response::AddSuccess,
-                    }
-                }
-                Request::Cancel(task_id) => {
-                    if let Err(e) = tm.cancel_task(task_id) {
-                        error!("fail to cancel task with index %d: {}", e);
-                        Response::Fail(e.to_string())
-                    } else {
-                        Response::RemoveSuccess
-                    }
-                }
-                Request::Show => Response::GetTasks(tm.get_tasks()),
-                Request::ContextRequest(command) => handle_context_command(command, tm),
+/// Daemon settings of client connections, `server` in `$FMN_DIR/config.json`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    // a connection without requests for this long is closed
+    pub idle_timeout_secs: u64,
+    // a connection taking longer than this to send a message is closed
+    pub read_timeout_secs: u64,
+    // further connections of an endpoint, or requests of the http api, are
+    // turned away while this many are open
+    pub max_connections: usize,
+    // the certificate of tls endpoints
+    pub tls: Option<TlsConfig>,
+    // the users who may connect to unix sockets; only the user of the
+    // daemon if not given. Other users make the socket open to anyone, with
+    // the peers checked by their uid
+    pub allowed_uids: Option<Vec<u32>>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: 600,
+            read_timeout_secs: 10,
+            max_connections: 64,
+            tls: None,
+            allowed_uids: None,
+        }
+    }
+}
+
+// reads with the idle timeout while waiting for a message; once one has
+// begun, the whole of it has to arrive within the read timeout
+struct TimedReader<S: Stream> {
+    stream: S,
+    idle: Duration,
+    read: Duration,
+    // set by `serve` after every message; cleared by the first byte read
+    between: Rc<Cell<bool>>,
+    // when the message being read is due
+    deadline: Option<Instant>,
+}
+
+impl<S: Stream> Read for TimedReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let timeout = if self.between.get() {
+            self.deadline = None;
+            self.idle
+        } else {
+            let read = self.read;
+            let deadline = *self.deadline.get_or_insert_with(|| Instant::now() + read);
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("the message isn't complete after {read:?}"),
+                ));
             }
+            left
         };
-        if let Err(e) = tm.refresh_after() {
-            error!("fail to flush changes to persistent storage: {e}");
+        self.stream.set_read_timeout(Some(timeout))?;
+        let n = self.stream.read(buf)?;
+        if n > 0 && self.between.get() {
+            self.between.set(false);
+            self.deadline = Some(Instant::now() + self.read);
         }
-        let serialized = to_string(&response).expect("fail to serialize response");
-        match writer.write_all(serialized.as_bytes()) {
-            Ok(_) => {
-                info!("successful response: {}", serialized);
+        Ok(n)
+    }
+}
+
+/// Counts the open connections of an endpoint against
+/// `ServerConfig::max_connections`.
+#[derive(Clone)]
+pub(crate) struct ConnectionSlots {
+    open: Arc<AtomicUsize>,
+    max: usize,
+}
+
+// the place of an open connection, given back when it is dropped
+pub(crate) struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlots {
+    pub(crate) fn new(max: usize) -> Self {
+        Self {
+            open: Arc::new(AtomicUsize::new(0)),
+            max: max.max(1),
+        }
+    }
+
+    // None while all of them are taken
+    pub(crate) fn take(&self) -> Option<ConnectionSlot> {
+        let taken = self.open.fetch_add(1, Ordering::SeqCst);
+        let slot = ConnectionSlot(self.open.clone());
+        (taken < self.max).then_some(slot)
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Serves the connection in a thread of its own, sharing the task manager
+/// with the other connections.
+pub fn spawn_serve<S: Stream>(
+    stream: S,
+    tm: Arc<Mutex<TaskManager>>,
+    config: ServerConfig,
+    auth: Auth,
+) {
+    spawn_serve_in(stream, tm, config, auth, None);
+}
+
+fn spawn_serve_in<S: Stream>(
+    stream: S,
+    tm: Arc<Mutex<TaskManager>>,
+    config: ServerConfig,
+    auth: Auth,
+    slot: Option<ConnectionSlot>,
+) {
+    std::thread::spawn(move || {
+        let _slot = slot;
+        if let Err(e) = serve(stream, &tm, &config, &auth) {
+            error!("error processing stream: {}", e);
+        }
+    });
+}
+
+/// Binds the endpoint and serves its connections in the background. Clients
+/// of unix sockets are let in by their uid, see `socket::bind_private` and
+/// `socket::check_peer`. Clients of tcp and tls endpoints need one of the
+/// tokens in `fmn_dir`, read anew for every connection so that revoked
+/// tokens stop working right away.
+pub fn listen(
+    endpoint: &Endpoint,
+    tm: Arc<Mutex<TaskManager>>,
+    config: ServerConfig,
+    fmn_dir: &Path,
+) -> Result<JoinHandle<()>> {
+    // a broken tokens file is reported right away rather than per client
+    Tokens::load(fmn_dir)?;
+    let fmn_dir = fmn_dir.to_owned();
+    let tokens = move || Tokens::load(&fmn_dir).map(Auth::Token);
+    let handle = match endpoint {
+        Endpoint::Unix(path) => {
+            let allowed_uids = config.allowed_uids.clone();
+            let listener = bind_private(path, &allowed_uids)
+                .context(format!("fail to listen on {endpoint}"))?;
+            let open = move |stream: UnixStream| {
+                let auth = match check_peer(&stream, &allowed_uids) {
+                    Ok(uid) => Auth::of_peer(uid),
+                    Err(reason) => Auth::Denied(reason),
+                };
+                Ok((stream, auth))
+            };
+            std::thread::spawn(move || accept(listener.incoming(), open, tm, config))
+        }
+        Endpoint::Tcp(addr) => {
+            let listener =
+                TcpListener::bind(addr).context(format!("fail to listen on {endpoint}"))?;
+            if !listener.local_addr()?.ip().is_loopback() {
+                warn!("{endpoint} is plaintext, tokens included; prefer tls: or localhost");
+            }
+            std::thread::spawn(move || {
+                accept(listener.incoming(), |s| Ok((s, tokens()?)), tm, config)
+            })
+        }
+        Endpoint::Tls(addr) => {
+            let tls = config
+                .tls
+                .as_ref()
+                .ok_or_else(|| anyhow!("{endpoint} needs server.tls in config.json"))?;
+            let tls = server_config(tls)?;
+            let listener =
+                TcpListener::bind(addr).context(format!("fail to listen on {endpoint}"))?;
+            // the tls handshake happens on the first read, in the thread of
+            // the connection
+            let open = move |sock| {
+                let connection =
+                    ServerConnection::new(tls.clone()).context("fail to set up tls")?;
+                Ok((TlsStream::new(connection, sock), tokens()?))
+            };
+            std::thread::spawn(move || accept(listener.incoming(), open, tm, config))
+        }
+    };
+    info!("listen on {}", endpoint);
+    Ok(handle)
+}
+
+fn accept<T, S: Stream>(
+    incoming: impl Iterator<Item = io::Result<T>>,
+    open: impl Fn(T) -> Result<(S, Auth)>,
+    tm: Arc<Mutex<TaskManager>>,
+    config: ServerConfig,
+) {
+    let slots = ConnectionSlots::new(config.max_connections);
+    for stream in incoming {
+        let Some(slot) = slots.take() else {
+            // the refused connection is closed right away
+            warn!(
+                "refuse a connection: {} are open already",
+                config.max_connections
+            );
+            continue;
+        };
+        match stream.map_err(anyhow::Error::from).and_then(&open) {
+            Ok((stream, auth)) => {
+                spawn_serve_in(stream, tm.clone(), config.clone(), auth, Some(slot))
+            }
+            Err(e) => error!("fail to accept connection: {}", e),
+        }
+    }
+}
+
+pub fn serve<S: Stream>(
+    stream: S,
+    tm: &Mutex<TaskManager>,
+    config: &ServerConfig,
+    auth: &Auth,
+) -> Result<()> {
+    let mut writer = BufWriter::new(stream.try_clone()?);
+    // the handshake is expected right away
+    let between = Rc::new(Cell::new(false));
+    let reader = TimedReader {
+        stream,
+        idle: Duration::from_secs(config.idle_timeout_secs.max(1)),
+        read: Duration::from_secs(config.read_timeout_secs.max(1)),
+        between: between.clone(),
+        deadline: None,
+    };
+    // read as plain json first, so that a request this daemon doesn't know
+    // is answered with an error instead of ending the connection
+    let mut messages = Deserializer::from_reader(BufReader::new(reader)).into_iter::<Value>();
+    let Some(hello) = messages.next() else {
+        return Ok(());
+    };
+    let Some(scope) = handshake(hello?, &mut writer, auth)? else {
+        return Ok(());
+    };
+    between.set(true);
+    for message in messages {
+        let message = match message {
+            Ok(message) => message,
+            Err(e) if e.is_io() && between.get() => {
+                info!(
+                    "close the connection idle for {}s",
+                    config.idle_timeout_secs
+                );
+                return Ok(());
+            }
+            Err(e) => return Err(e).context("fail to read request"),
+        };
+        let response = match from_value::<Request>(message) {
+            Ok(request) if scope == Scope::Read && !request.is_read_only() => {
+                warn!("reject a request of a read-only token: {:?}", request);
+                Response::Unauthorized("the token is read-only".to_owned())
+            }
+            Ok(request) if !auth.may_run_commands() && request.runs_commands() => {
+                warn!(
+                    "reject a command of a client of another user: {:?}",
+                    request
+                );
+                Response::Unauthorized(
+                    "exec hooks are only accepted from the user of fmn-daemon over the unix socket"
+                        .to_owned(),
+                )
+            }
+            Ok(Request::Subscribe(subscription)) => {
+                info!("receive a subscription: {:?}", subscription);
+                let events = tm
+                    .lock()
+                    .expect("task manager lock is poisoned")
+                    .events()
+                    .subscribe();
+                write_message(&mut writer, &Response::Subscribed)?;
+                // a client that stops reading is let go
+                writer
+                    .get_ref()
+                    .set_write_timeout(Some(Duration::from_secs(
+                        config.read_timeout_secs.max(1),
+                    )))?;
+                let keepalive = Duration::from_secs(config.idle_timeout_secs.max(1));
+                return stream_events(events, &subscription, &mut writer, keepalive);
+            }
+            Ok(request) => {
+                info!("receive a request: {:?}", request);
+                let mut tm = tm.lock().expect("task manager lock is poisoned");
+                match handle_request(request, &mut tm) {
+                    Response::GetTasks(tasks) if scope == Scope::Read => {
+                        Response::GetTasks(tasks.into_iter().map(Task::without_exec).collect())
+                    }
+                    response => response,
+                }
             }
             Err(e) => {
-                error!("fail to send back response: {}", e);
+                error!("fail to read request: {}", e);
+                Response::Fail(format!(
+                    "fmn-daemon {} can't read the request: {e}",
+                    env!("CARGO_PKG_VERSION")
+                ))
+            }
+        };
+        write_message(&mut writer, &response)?;
+        between.set(true);
+    }
+    Ok(())
+}
+
+// writes the events the subscription accepts, a line each, until the client
+// is gone or the bus drops it; an empty line after `keepalive` without
+// events finds out about clients gone quietly
+fn stream_events<W: Write>(
+    events: Receiver<Event>,
+    subscription: &Subscription,
+    writer: &mut W,
+    keepalive: Duration,
+) -> Result<()> {
+    writer.write_all(b"\n")?;
+    loop {
+        let line = match events.recv_timeout(keepalive) {
+            Ok(event) if subscription.accepts(&event) => {
+                to_string(&event).expect("fail to serialize event") + "\n"
+            }
+            Ok(_) => continue,
+            Err(RecvTimeoutError::Timeout) => "\n".to_owned(),
+            Err(RecvTimeoutError::Disconnected) => {
+                info!("end the subscription the daemon dropped");
+                break;
             }
+        };
+        if let Err(e) = writer
+            .write_all(line.as_bytes())
+            .and_then(|_| writer.flush())
+        {
+            info!("end the subscription: {}", e);
+            break;
         }
-        writer
-            .flush()
-            .context("fail to flush fmn-daemon tcp writer")?;
     }
     Ok(())
 }
 
+// answers the hello of the client with ours; the scope of the client, or
+// None if they can't talk
+fn handshake<W: Write>(message: Value, writer: &mut W, auth: &Auth) -> Result<Option<Scope>> {
+    let hello = Hello::current();
+    let Ok(client_hello) = from_value::<Hello>(message) else {
+        // a client from before the handshake reads this as a failure
+        let response = Response::Fail(format!(
+            "fmn-daemon {} expects a protocol handshake first; \
+             run the same release of fmn and fmn-daemon",
+            hello.version
+        ));
+        write_message(writer, &response)?;
+        return Ok(None);
+    };
+    if let Err(e) = hello.check("fmn-daemon", &client_hello, "fmn") {
+        // the client tells its user about the mismatch
+        write_message(writer, &hello)?;
+        error!("reject client: {}", e);
+        return Ok(None);
+    }
+    match auth.authorize(client_hello.token.as_deref()) {
+        Ok(scope) => {
+            write_message(writer, &hello)?;
+            Ok(Some(scope))
+        }
+        Err(reason) => {
+            warn!("reject client: {}", reason);
+            write_message(writer, &Response::Unauthorized(reason))?;
+            Ok(None)
+        }
+    }
+}
+
+pub(crate) fn handle_request(request: Request, tm: &mut TaskManager) -> Response {
+    tm.refresh_before();
+    let response = match request {
+        Request::Add(request) => handle_add(request, tm),
+        Request::Cancel(task_id) => {
+            if let Err(e) = tm.cancel_task(task_id) {
+                error!("fail to cancel task with index %d: {}", e);
+                Response::Fail(e.to_string())
+            } else {
+                Response::RemoveSuccess
+            }
+        }
+        Request::Show => Response::GetTasks(tm.get_tasks()),
+        Request::ContextRequest(command) => handle_context_command(command, tm),
+        Request::History(limit) => match tm.history(limit) {
+            Ok(records) => Response::GetHistory(records),
+            Err(e) => Response::Fail(e.to_string()),
+        },
+        Request::Acknowledge(task_id) => match tm.acknowledge(&task_id) {
+            Ok(()) => Response::AcknowledgeSuccess,
+            Err(e) => Response::Fail(e.to_string()),
+        },
+        Request::Snooze { task_id, after } => match tm.snooze(&task_id, after) {
+            Ok(()) => Response::AddSuccess,
+            Err(e) => Response::Fail(e.to_string()),
+        },
+        Request::Replace(task) => handle_replace(task, tm),
+        // `serve` streams the events on the connection instead
+        Request::Subscribe(_) => {
+            Response::Fail("a subscription needs a connection of its own".to_owned())
+        }
+    };
+    if let Err(e) = tm.refresh_after() {
+        error!("fail to flush changes to persistent storage: {e}");
+    }
+    response
+}
+
+fn write_message<W: Write, T: Serialize>(writer: &mut W, message: &T) -> Result<()> {
+    let serialized = to_string(message).expect("fail to serialize response");
+    match writer.write_all(serialized.as_bytes()) {
+        Ok(_) => {
+            info!("successful response: {}", serialized);
+        }
+        Err(e) => {
+            error!("fail to send back response: {}", e);
+        }
+    }
+    writer
+        .flush()
+        .context("fail to flush fmn-daemon tcp writer")?;
+    Ok(())
+}
+
+fn handle_add(request: AddRequest, tm: &mut TaskManager) -> Response {
+    let AddRequest {
+        description,
+        clock_type,
+        image_path,
+        sound_path,
+        exec,
+        notification,
+    } = request;
+    let templates = std::iter::once(&description).chain(notification.summary.as_ref());
+    for template in templates {
+        if let Err(e) = validate_template(template) {
+            error!("reject task with invalid template: {}", e);
+            return Response::Fail(e.to_string());
+        }
+    }
+    if let Err(e) = validate_clock_type(&clock_type) {
+        error!("reject task with invalid clock: {}", e);
+        return Response::Fail(e.to_string());
+    }
+    let mut task = Task::new(description, clock_type).with_context(tm.current_context());
+    if let Some(image_path) = image_path {
+        task.add_image(image_path);
+    }
+    if let Some(sound_path) = sound_path {
+        task.add_sound(sound_path);
+    }
+    if let Some(command) = exec {
+        task.add_exec(command);
+    }
+    task.notification = notification;
+    match tm.add_task(task) {
+        Err(e) => {
+            error!("fail to add new task in udp server: {}", e);
+            Response::Fail(e.to_string())
+        }
+        Ok(_) => Response::AddSuccess,
+    }
+}
+
+fn handle_replace(task: Task, tm: &mut TaskManager) -> Response {
+    let templates = std::iter::once(&task.description).chain(task.notification.summary.as_ref());
+    for template in templates {
+        if let Err(e) = validate_template(template) {
+            error!("reject task with invalid template: {}", e);
+            return Response::Fail(e.to_string());
+        }
+    }
+    if let Err(e) = validate_clock_type(&task.clock_type) {
+        error!("reject task with invalid clock: {}", e);
+        return Response::Fail(e.to_string());
+    }
+    match tm.replace_task(task) {
+        Err(e) => {
+            error!("fail to replace task: {}", e);
+            Response::Fail(e.to_string())
+        }
+        Ok(()) => Response::AddSuccess,
+    }
+}
+
+// turns the buttons clicked on notifications into requests to the task
+// manager
+pub fn forward_actions(actions: Receiver<TaskAction>, tm: Arc<Mutex<TaskManager>>) {
+    std::thread::spawn(move || {
+        for action in actions {
+            let request = match action {
+                TaskAction::Done(firing) => Request::Acknowledge(firing.task.task_id),
+                TaskAction::Snooze(firing, after) => {
+                    let task_id = firing.task.task_id.clone();
+                    let mut tm = tm.lock().expect("task manager lock is poisoned");
+                    if let Response::Fail(_) =
+                        handle_request(Request::Snooze { task_id, after }, &mut tm)
+                    {
+                        // the task fired here, even if a once task is gone by
+                        // now; it comes back as it was stored
+                        let snoozed = tm
+                            .snooze_task(firing.stored, after)
+                            .and_then(|()| tm.refresh_after());
+                        if let Err(e) = snoozed {
+                            error!("fail to handle notification action: {}", e);
+                        }
+                    }
+                    continue;
+                }
+                TaskAction::Acknowledge(task_id) => Request::Acknowledge(task_id),
+            };
+            let mut tm = tm.lock().expect("task manager lock is poisoned");
+            if let Response::Fail(e) = handle_request(request, &mut tm) {
+                error!("fail to handle notification action: {}", e);
+            }
+        }
+    });
+}
+
 // pub fn serveUnixStream(stream: UnixStream, tm: &mut TaskManager)
 
 fn handle_context_command(command: ContextCommand, tm: &mut TaskManager) -> Response {
     match command {
-        ContextCommand::Define { context } => {
-            if let Err(e) = tm.define_context(context) {
+        ContextCommand::Define { context, settings } => {
+            if let Err(e) = tm.define_context(context, settings) {
                 Response::Fail(e.to_string())
             } else {
                 Response::AddSuccess
@@ -92,5 +576,25 @@ fn handle_context_command(command: ContextCommand, tm: &mut TaskManager) -> Resp
                 Response::SetContextSuccess
             }
         }
+        ContextCommand::Configure {
+            context,
+            settings,
+            clear,
+        } => {
+            if let Err(e) = tm.configure_context(context, settings, clear) {
+                Response::Fail(e.to_string())
+            } else {
+                Response::ConfigureContextSuccess
+            }
+        }
+        ContextCommand::Show { context } => {
+            let context = context.unwrap_or_else(|| tm.current_context());
+            if tm.list_context().contains(&context) {
+                let settings = tm.context_settings(&context);
+                Response::GetContextSettings(context, settings)
+            } else {
+                Response::Fail(format!("no such context: {context}"))
+            }
+        }
     }
 }
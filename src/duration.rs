@@ -0,0 +1,281 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+// spellings accepted by the human-readable form, matched against the whole
+// alphabetic word following a number
+const UNITS: [(&[&str], Unit); 5] = [
+    (&["weeks", "week", "wks", "wk", "w"], Unit::Week),
+    (&["days", "day", "d"], Unit::Day),
+    (&["hours", "hour", "hrs", "hr", "h"], Unit::Hour),
+    (&["minutes", "minute", "mins", "min", "m"], Unit::Minute),
+    (&["seconds", "second", "secs", "sec", "s"], Unit::Second),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Unit {
+    Week,
+    Day,
+    Hour,
+    Minute,
+    Second,
+}
+
+impl Unit {
+    fn secs(self) -> u128 {
+        match self {
+            Unit::Week => 7 * 24 * 3600,
+            Unit::Day => 24 * 3600,
+            Unit::Hour => 3600,
+            Unit::Minute => 60,
+            Unit::Second => 1,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Unit::Week => "weeks",
+            Unit::Day => "days",
+            Unit::Hour => "hours",
+            Unit::Minute => "minutes",
+            Unit::Second => "seconds",
+        }
+    }
+}
+
+/// The error returned by [`parse_duration`]; `position` is the char offset in
+/// `input` where parsing stopped making sense.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DurationError {
+    pub input: String,
+    pub position: usize,
+    pub reason: String,
+}
+
+impl Display for DurationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "invalid duration {:?}: {} at position {}",
+            self.input, self.reason, self.position
+        )?;
+        writeln!(f, "  {}", self.input)?;
+        write!(f, "  {}^", " ".repeat(self.position))?;
+        write!(
+            f,
+            "\nvalid examples: 1d1h1m1s, 2h, 1.5h, 90min, 1 hour 30 minutes, 2w, PT1H30M"
+        )
+    }
+}
+
+impl std::error::Error for DurationError {}
+
+/// Parses a duration written either in a human-readable form (`1d2h`, `1.5h`,
+/// `90min`, `1 hour 30 minutes`, `2w`) or as an ISO 8601 duration (`PT1H30M`,
+/// `P1W`, `P1DT12H`). Each unit may appear at most once.
+pub fn parse_duration(duration: &str) -> Result<Duration, DurationError> {
+    let mut parser = Parser::new(duration);
+    let nanos = if matches!(parser.peek(), Some('P' | 'p')) {
+        parser.iso8601()?
+    } else {
+        parser.human()?
+    };
+    let secs = nanos / NANOS_PER_SEC;
+    let secs = u64::try_from(secs).map_err(|_| parser.error_at(0, "duration is too long"))?;
+    Ok(Duration::new(secs, (nanos % NANOS_PER_SEC) as u32))
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+    seen: Vec<Unit>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            chars: input.chars().collect(),
+            pos: 0,
+            seen: vec![],
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn error_at(&self, position: usize, reason: impl Into<String>) -> DurationError {
+        DurationError {
+            input: self.input.to_owned(),
+            position,
+            reason: reason.into(),
+        }
+    }
+
+    fn error(&self, reason: impl Into<String>) -> DurationError {
+        self.error_at(self.pos, reason)
+    }
+
+    fn skip_separators(&mut self) {
+        loop {
+            while matches!(self.peek(), Some(c) if c.is_whitespace() || c == ',') {
+                self.pos += 1;
+            }
+            // "1 hour and 30 minutes"
+            if self.chars[self.pos..].starts_with(&['a', 'n', 'd'])
+                && self
+                    .chars
+                    .get(self.pos + 3)
+                    .is_some_and(|c| c.is_whitespace())
+            {
+                self.pos += 3;
+                continue;
+            }
+            return;
+        }
+    }
+
+    // returns the number as (integer part, fraction digits)
+    fn number(&mut self) -> Result<(u128, String), DurationError> {
+        let start = self.pos;
+        let mut integer = String::new();
+        while let Some(c @ '0'..='9') = self.peek() {
+            integer.push(c);
+            self.pos += 1;
+        }
+        let mut fraction = String::new();
+        if matches!(self.peek(), Some('.' | ',')) {
+            self.pos += 1;
+            while let Some(c @ '0'..='9') = self.peek() {
+                fraction.push(c);
+                self.pos += 1;
+            }
+            if fraction.is_empty() {
+                return Err(self.error("expected digits after the decimal point"));
+            }
+        }
+        if integer.is_empty() {
+            if fraction.is_empty() {
+                return Err(self.error_at(start, "expected a number"));
+            }
+            integer.push('0');
+        }
+        let integer = integer
+            .parse()
+            .map_err(|_| self.error_at(start, "number is too large"))?;
+        Ok((integer, fraction))
+    }
+
+    fn add(
+        &mut self,
+        total: u128,
+        (integer, fraction): (u128, String),
+        unit: Unit,
+        unit_pos: usize,
+    ) -> Result<u128, DurationError> {
+        if self.seen.contains(&unit) {
+            return Err(self.error_at(unit_pos, format!("{} given more than once", unit.name())));
+        }
+        self.seen.push(unit);
+        let unit_nanos = unit.secs() * NANOS_PER_SEC;
+        let mut nanos = integer.checked_mul(unit_nanos);
+        if !fraction.is_empty() {
+            // anything finer than a nanosecond is dropped
+            let digits = fraction.len().min(18) as u32;
+            let fraction: u128 = fraction[..digits as usize].parse().unwrap();
+            nanos = nanos.and_then(|n| n.checked_add(fraction * unit_nanos / 10u128.pow(digits)));
+        }
+        nanos
+            .and_then(|n| n.checked_add(total))
+            .ok_or_else(|| self.error_at(unit_pos, "duration is too long"))
+    }
+
+    fn human(&mut self) -> Result<u128, DurationError> {
+        let mut total = 0;
+        self.skip_separators();
+        if self.peek().is_none() {
+            return Err(self.error("empty duration"));
+        }
+        while self.peek().is_some() {
+            let number = self.number()?;
+            while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+                self.pos += 1;
+            }
+            let unit_pos = self.pos;
+            let unit = self.unit()?;
+            total = self.add(total, number, unit, unit_pos)?;
+            self.skip_separators();
+        }
+        Ok(total)
+    }
+
+    fn unit(&mut self) -> Result<Unit, DurationError> {
+        let word: String = self
+            .chars
+            .iter()
+            .skip(self.pos)
+            .take_while(|c| c.is_alphabetic())
+            .collect();
+        if word.is_empty() {
+            return Err(match self.peek() {
+                None => self.error("missing unit (one of w, d, h, m, s)"),
+                Some(c) => self.error(format!("unexpected character {c:?}")),
+            });
+        }
+        let lowercase = word.to_lowercase();
+        for (spellings, unit) in UNITS {
+            if spellings.contains(&lowercase.as_str()) {
+                self.pos += word.chars().count();
+                return Ok(unit);
+            }
+        }
+        Err(self.error(format!("unknown unit {word:?}")))
+    }
+
+    fn iso8601(&mut self) -> Result<u128, DurationError> {
+        // skip the leading 'P'
+        self.pos += 1;
+        let mut total = 0;
+        let mut in_time = false;
+        if self.peek().is_none() {
+            return Err(self.error("expected at least one component after 'P'"));
+        }
+        while let Some(c) = self.peek() {
+            if matches!(c, 'T' | 't') {
+                if in_time {
+                    return Err(self.error("'T' given more than once"));
+                }
+                in_time = true;
+                self.pos += 1;
+                if self.peek().is_none() {
+                    return Err(self.error("expected at least one component after 'T'"));
+                }
+                continue;
+            }
+            let number = self.number()?;
+            let unit_pos = self.pos;
+            let designator = self
+                .peek()
+                .ok_or_else(|| self.error("missing designator"))?
+                .to_ascii_uppercase();
+            let unit = match (in_time, designator) {
+                (false, 'W') => Unit::Week,
+                (false, 'D') => Unit::Day,
+                (true, 'H') => Unit::Hour,
+                (true, 'M') => Unit::Minute,
+                (true, 'S') => Unit::Second,
+                (false, 'Y' | 'M') => {
+                    return Err(self.error("years and months have no fixed length; use W or D"))
+                }
+                (false, 'H' | 'S') => return Err(self.error("expected 'T' before time components")),
+                _ => return Err(self.error(format!("unknown designator {designator:?}"))),
+            };
+            self.pos += 1;
+            total = self.add(total, number, unit, unit_pos)?;
+        }
+        Ok(total)
+    }
+}
@@ -0,0 +1,194 @@
+use std::fmt::Display;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::ops::{Deref, DerefMut};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Error};
+use rustls::{ConnectionCommon, SideData, StreamOwned};
+
+use crate::socket::default_socket;
+
+/// Where fmn-daemon listens and fmn connects to, written like
+/// `unix:/run/user/1000/fmn.sock`, `tcp:127.0.0.1:8082` or
+/// `tls:fmn.example.com:8083`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Unix(PathBuf),
+    // host:port
+    Tcp(String),
+    // host:port, with tcp connections wrapped in tls
+    Tls(String),
+}
+
+// the private socket of the user
+impl Default for Endpoint {
+    fn default() -> Self {
+        Endpoint::Unix(default_socket())
+    }
+}
+
+impl FromStr for Endpoint {
+    type Err = Error;
+
+    // also takes `unix:///path` and `tcp://host:port`, and the plain paths
+    // and host:port addresses of older releases
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            anyhow!(
+                "invalid address {s:?}, expect unix:/path/to/fmn.sock, tcp:host:port or tls:host:port"
+            )
+        };
+        if let Some(path) = s.strip_prefix("unix:") {
+            let path = path.strip_prefix("//").unwrap_or(path);
+            if path.is_empty() {
+                return Err(invalid());
+            }
+            return Ok(Endpoint::Unix(PathBuf::from(path)));
+        }
+        if let Some(addr) = s.strip_prefix("tcp:") {
+            let addr = addr.strip_prefix("//").unwrap_or(addr);
+            if !is_host_port(addr) {
+                return Err(invalid());
+            }
+            return Ok(Endpoint::Tcp(addr.to_owned()));
+        }
+        if let Some(addr) = s.strip_prefix("tls:") {
+            let addr = addr.strip_prefix("//").unwrap_or(addr);
+            if !is_host_port(addr) {
+                return Err(invalid());
+            }
+            return Ok(Endpoint::Tls(addr.to_owned()));
+        }
+        // an unknown scheme is no path either
+        if s.contains("://") {
+            Err(invalid())
+        } else if s.contains('/') {
+            Ok(Endpoint::Unix(PathBuf::from(s)))
+        } else if is_host_port(s) {
+            Ok(Endpoint::Tcp(s.to_owned()))
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+fn is_host_port(addr: &str) -> bool {
+    addr.rsplit_once(':')
+        .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+}
+
+impl Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+            Endpoint::Tcp(addr) => write!(f, "tcp:{addr}"),
+            Endpoint::Tls(addr) => write!(f, "tls:{addr}"),
+        }
+    }
+}
+
+/// A connection between fmn and fmn-daemon.
+pub trait Stream: Read + Write + Send + Sized + 'static {
+    fn try_clone(&self) -> io::Result<Self>;
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Stream for UnixStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        UnixStream::try_clone(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+}
+
+impl Stream for TcpStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        TcpStream::try_clone(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
+
+/// A tls connection over tcp. Its clones share the connection, which is fine
+/// as long as reads and writes take turns, as requests and responses do.
+pub struct TlsStream<C>(Arc<Mutex<StreamOwned<C, TcpStream>>>);
+
+impl<C, S> TlsStream<C>
+where
+    C: DerefMut + Deref<Target = ConnectionCommon<S>>,
+    S: SideData,
+{
+    pub fn new(connection: C, sock: TcpStream) -> Self {
+        Self(Arc::new(Mutex::new(StreamOwned::new(connection, sock))))
+    }
+}
+
+impl<C, S> Read for TlsStream<C>
+where
+    C: DerefMut + Deref<Target = ConnectionCommon<S>>,
+    S: SideData,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .expect("tls stream lock is poisoned")
+            .read(buf)
+    }
+}
+
+impl<C, S> Write for TlsStream<C>
+where
+    C: DerefMut + Deref<Target = ConnectionCommon<S>>,
+    S: SideData,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .expect("tls stream lock is poisoned")
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().expect("tls stream lock is poisoned").flush()
+    }
+}
+
+impl<C, S> Stream for TlsStream<C>
+where
+    C: DerefMut + Deref<Target = ConnectionCommon<S>> + Send + 'static,
+    S: SideData,
+{
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self(self.0.clone()))
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let stream = self.0.lock().expect("tls stream lock is poisoned");
+        stream.sock.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let stream = self.0.lock().expect("tls stream lock is poisoned");
+        stream.sock.set_write_timeout(timeout)
+    }
+}
@@ -0,0 +1,149 @@
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+
+use clap::{Args, ValueEnum};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::notify::FiringPayload;
+use crate::task_manager::{Task, TaskContext, TaskID};
+
+// the events a subscriber may lag behind before it is dropped
+const BACKLOG: usize = 256;
+
+/// What happened in the daemon, streamed to subscribers as a json object per
+/// line, e.g. `{"event":"context_switched","from":"default","to":"work"}`.
+/// Snoozing is the closest fmn comes to pausing a task.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    TaskAdded {
+        task: Task,
+    },
+    TaskRemoved {
+        task: Task,
+    },
+    // the task is reminded of again at its new clock
+    TaskSnoozed {
+        task: Task,
+    },
+    ContextSwitched {
+        from: TaskContext,
+        to: TaskContext,
+    },
+    ReminderFired {
+        firing: FiringPayload,
+    },
+    ReminderAcknowledged {
+        task_id: TaskID,
+        context: TaskContext,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum EventKind {
+    TaskAdded,
+    TaskRemoved,
+    TaskSnoozed,
+    ContextSwitched,
+    ReminderFired,
+    ReminderAcknowledged,
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::TaskAdded { .. } => EventKind::TaskAdded,
+            Event::TaskRemoved { .. } => EventKind::TaskRemoved,
+            Event::TaskSnoozed { .. } => EventKind::TaskSnoozed,
+            Event::ContextSwitched { .. } => EventKind::ContextSwitched,
+            Event::ReminderFired { .. } => EventKind::ReminderFired,
+            Event::ReminderAcknowledged { .. } => EventKind::ReminderAcknowledged,
+        }
+    }
+
+    // subscribers don't get to see the commands tasks run
+    fn without_exec(self) -> Self {
+        match self {
+            Event::TaskAdded { task } => Event::TaskAdded {
+                task: task.without_exec(),
+            },
+            Event::TaskRemoved { task } => Event::TaskRemoved {
+                task: task.without_exec(),
+            },
+            Event::TaskSnoozed { task } => Event::TaskSnoozed {
+                task: task.without_exec(),
+            },
+            event => event,
+        }
+    }
+
+    // whether it concerns the context; a switch concerns both sides
+    fn concerns(&self, context: &TaskContext) -> bool {
+        match self {
+            Event::TaskAdded { task }
+            | Event::TaskRemoved { task }
+            | Event::TaskSnoozed { task } => &task.context == context,
+            Event::ContextSwitched { from, to } => from == context || to == context,
+            Event::ReminderFired { firing } => &firing.context == context,
+            Event::ReminderAcknowledged { context: c, .. } => c == context,
+        }
+    }
+}
+
+/// The events a subscriber wants; all of them by default.
+#[derive(Args, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Subscription {
+    // only events of these kinds
+    #[arg(short, long = "event", value_enum)]
+    pub kinds: Vec<EventKind>,
+    // only events concerning this context
+    #[arg(short, long)]
+    pub context: Option<TaskContext>,
+}
+
+impl Subscription {
+    pub fn accepts(&self, event: &Event) -> bool {
+        (self.kinds.is_empty() || self.kinds.contains(&event.kind()))
+            && self.context.as_ref().is_none_or(|c| event.concerns(c))
+    }
+}
+
+/// Hands every event published to the subscribers of the moment. A
+/// subscriber that falls too far behind is dropped, which ends its stream,
+/// rather than have the events pile up.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<SyncSender<Event>>>>,
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = sync_channel(BACKLOG);
+        self.lock().push(sender);
+        receiver
+    }
+
+    // subscribers that are gone or lagging are dropped on the way
+    pub fn publish(&self, event: Event) {
+        let event = event.without_exec();
+        self.lock()
+            .retain(|subscriber| match subscriber.try_send(event.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    warn!("drop a subscriber {} events behind", BACKLOG);
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<SyncSender<Event>>> {
+        self.subscribers
+            .lock()
+            .expect("event subscribers lock is poisoned")
+    }
+}
@@ -1,6 +1,8 @@
 use prettytable::{row, Table};
+use time::macros::format_description;
 
-use crate::task_manager::Task;
+use crate::history::{FiringRecord, HookRun};
+use crate::task_manager::{ContextSettings, Task, TaskContext};
 
 pub fn tabular_output(tasks: &Vec<Task>) -> String {
     let mut table = Table::new();
@@ -10,3 +12,88 @@ pub fn tabular_output(tasks: &Vec<Task>) -> String {
     }
     table.to_string()
 }
+
+pub fn context_output(context: &TaskContext, settings: &ContextSettings) -> String {
+    let unset = || "-".to_owned();
+    let mut table = Table::new();
+    table.add_row(row!["CONTEXT", context]);
+    table.add_row(row![
+        "IMAGE",
+        settings.image_path.clone().unwrap_or_else(unset)
+    ]);
+    table.add_row(row![
+        "SOUND",
+        settings.sound_path.clone().unwrap_or_else(unset)
+    ]);
+    table.add_row(row![
+        "URGENCY",
+        settings
+            .urgency
+            .map(|u| format!("{u:?}").to_lowercase())
+            .unwrap_or_else(unset)
+    ]);
+    let notifiers = match settings.notifiers.is_empty() {
+        true => "all".to_owned(),
+        false => settings.notifiers.join(", "),
+    };
+    table.add_row(row!["NOTIFIERS", notifiers]);
+    table.to_string()
+}
+
+pub fn history_output(records: &Vec<FiringRecord>) -> String {
+    let format = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+    let mut table = Table::new();
+    table.add_row(row![
+        "FIRED AT",
+        "ID",
+        "DESCRIPTION",
+        "DELIVERIES",
+        "HOOKS",
+        "ACKNOWLEDGED AT"
+    ]);
+    for record in records {
+        let mut deliveries: Vec<String> = record
+            .deliveries
+            .iter()
+            .map(|d| match &d.error {
+                None => d.notifier.clone(),
+                Some(e) => format!("{} failed: {}", d.notifier, e),
+            })
+            .collect();
+        if let Some(sound) = &record.sound {
+            deliveries.push(match (&sound.player, &sound.error) {
+                (Some(player), _) => format!("sound via {player}"),
+                (None, Some(e)) => format!("sound failed: {e}"),
+                (None, None) => "sound failed".to_owned(),
+            });
+        }
+        let hooks: Vec<String> = record.hooks.iter().map(hook_outcome).collect();
+        table.add_row(row![
+            record
+                .fired_at
+                .format(&format)
+                .expect("fail to display custom OffsetDatetime format"),
+            record.task_id,
+            record.description,
+            deliveries.join("\n"),
+            hooks.join("\n"),
+            record
+                .acknowledged_at
+                .map(|at| at
+                    .format(&format)
+                    .expect("fail to display custom OffsetDatetime format"))
+                .unwrap_or_default()
+        ]);
+    }
+    table.to_string()
+}
+
+fn hook_outcome(hook: &HookRun) -> String {
+    let outcome = match (&hook.error, hook.timed_out, hook.exit_status) {
+        (Some(e), _, _) => format!("failed: {e}"),
+        (None, true, _) => "timed out".to_owned(),
+        (None, false, Some(status)) => format!("exit {status}"),
+        (None, false, None) => "killed".to_owned(),
+    };
+    format!("{}: {}", hook.command, outcome)
+}
@@ -0,0 +1,238 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime};
+use utoipa::ToSchema;
+
+use crate::comm::{get_local_now, get_local_utc_offset};
+use crate::notify::Firing;
+use crate::task_manager::{read_items, TaskContext, TaskID};
+
+// serializes the writers of the history file
+static LOCK: Mutex<()> = Mutex::new(());
+// once the history grows past this, its older half goes
+const MAX_HISTORY_BYTES: u64 = 4 << 20;
+
+/// What happened when a reminder fired, appended to `$FMN_DIR/history.data`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FiringRecord {
+    pub task_id: TaskID,
+    pub description: String,
+    pub context: TaskContext,
+    #[serde(with = "time::serde::rfc3339")]
+    pub scheduled_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub fired_at: OffsetDateTime,
+    pub deliveries: Vec<Delivery>,
+    #[serde(default)]
+    pub hooks: Vec<HookRun>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub acknowledged_at: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub sound: Option<SoundRun>,
+}
+
+impl FiringRecord {
+    pub fn new(firing: &Firing) -> Self {
+        Self {
+            task_id: firing.task.task_id.clone(),
+            description: firing.task.description.clone(),
+            context: firing.task.context.clone(),
+            scheduled_at: firing.scheduled_at,
+            fired_at: firing.fired_at,
+            deliveries: vec![],
+            hooks: vec![],
+            acknowledged_at: None,
+            sound: None,
+        }
+    }
+
+    // "notifier: error" for every failed delivery
+    pub fn delivery_errors(&self) -> Vec<String> {
+        self.deliveries
+            .iter()
+            .filter_map(|d| d.error.as_ref().map(|e| format!("{}: {}", d.notifier, e)))
+            .collect()
+    }
+}
+
+// the outcome of handing a firing to one notifier
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Delivery {
+    pub notifier: String,
+    pub error: Option<String>,
+    // how many times it was tried again in the background
+    #[serde(default)]
+    pub retries: u32,
+}
+
+// the outcome of a command run for a firing
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct HookRun {
+    pub command: String,
+    // None if the command couldn't be spawned or was killed
+    pub exit_status: Option<i32>,
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+    pub error: Option<String>,
+}
+
+// the outcome of playing the task's sound
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct SoundRun {
+    pub path: String,
+    // the player that played it, None if none could
+    pub player: Option<String>,
+    pub plays: u32,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct History {
+    path: PathBuf,
+    max_bytes: u64,
+}
+
+impl History {
+    pub fn new<P>(fmn_dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: fmn_dir.as_ref().join("history.data"),
+            max_bytes: MAX_HISTORY_BYTES,
+        }
+    }
+
+    // keeps the file under about `max_bytes`, dropping the oldest records
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn record(&self, record: &FiringRecord) -> Result<()> {
+        let _guard = LOCK.lock().expect("history lock is poisoned");
+        let mut writer = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)
+            .context(format!("fail to open history {:?}", self.path))?;
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        writer.write_all(&line)?;
+        if writer.metadata()?.len() > self.max_bytes {
+            self.trim()?;
+        }
+        Ok(())
+    }
+
+    // drops the older half of the records; the caller holds the lock
+    fn trim(&self) -> Result<()> {
+        let content =
+            fs::read(&self.path).context(format!("fail to read history {:?}", self.path))?;
+        let half = content.len() / 2;
+        let keep = content[half..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(content.len(), |end| half + end + 1);
+        // written aside and moved over, so that a crash leaves either file whole
+        let trimmed = self.path.with_extension("data.tmp");
+        fs::write(&trimmed, &content[keep..])
+            .and_then(|()| fs::rename(&trimmed, &self.path))
+            .context(format!("fail to trim history {:?}", self.path))
+    }
+
+    // marks the latest firing of the task acknowledged and returns it
+    pub fn acknowledge(&self, task_id: &TaskID) -> Result<FiringRecord> {
+        self.rewrite(
+            |r| r.task_id.starts_with(task_id.as_str()),
+            |r| r.acknowledged_at = Some(get_local_now()),
+        )?
+        .ok_or_else(|| anyhow!("no firing of task {task_id} found"))
+    }
+
+    // changes the record of a firing, e.g. once its hooks ran or a retry
+    // settled how it was delivered
+    pub fn update<F>(&self, firing: &Firing, change: F) -> Result<()>
+    where
+        F: FnOnce(&mut FiringRecord),
+    {
+        let found = self.rewrite(
+            |r| r.task_id == firing.task.task_id && r.fired_at == firing.fired_at,
+            change,
+        )?;
+        match found {
+            Some(_) => Ok(()),
+            None => Err(anyhow!(
+                "no firing of task {} at {} found",
+                firing.task.task_id,
+                firing.fired_at
+            )),
+        }
+    }
+
+    // applies the change to the latest record matching, if any, and returns
+    // it changed. Records are searched from the end, where the ones that
+    // still change are, and only the record and those after it are written
+    // again.
+    fn rewrite<P, F>(&self, matches: P, change: F) -> Result<Option<FiringRecord>>
+    where
+        P: Fn(&FiringRecord) -> bool,
+        F: FnOnce(&mut FiringRecord),
+    {
+        let _guard = LOCK.lock().expect("history lock is poisoned");
+        let content = match fs::read(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(format!("fail to read history {:?}", self.path)),
+        };
+        let mut start = content.len();
+        for line in content.split_inclusive(|&b| b == b'\n').rev() {
+            start -= line.len();
+            let text = line.strip_suffix(b"\n").unwrap_or(line);
+            if text.is_empty() {
+                continue;
+            }
+            let mut record: FiringRecord = serde_json::from_slice(text)?;
+            if !matches(&record) {
+                continue;
+            }
+            change(&mut record);
+            let mut tail = serde_json::to_vec(&record)?;
+            tail.push(b'\n');
+            tail.extend_from_slice(&content[start + line.len()..]);
+            let file = OpenOptions::new()
+                .write(true)
+                .open(&self.path)
+                .context(format!("fail to open history {:?}", self.path))?;
+            file.set_len(start as u64)
+                .and_then(|()| file.write_all_at(&tail, start as u64))
+                .context(format!("fail to rewrite history {:?}", self.path))?;
+            return Ok(Some(record));
+        }
+        Ok(None)
+    }
+
+    // how many times the task fired on the local `date`
+    pub fn fired_on(&self, task_id: &TaskID, date: Date) -> Result<u32> {
+        let records: Vec<FiringRecord> = read_items(&self.path)?;
+        let offset = get_local_utc_offset();
+        Ok(records
+            .iter()
+            .filter(|r| &r.task_id == task_id && r.fired_at.to_offset(offset).date() == date)
+            .count() as u32)
+    }
+
+    // the latest `limit` records, oldest first
+    pub fn latest(&self, limit: usize) -> Result<Vec<FiringRecord>> {
+        let mut records: Vec<FiringRecord> = read_items(&self.path)?;
+        let skip = records.len().saturating_sub(limit);
+        Ok(records.split_off(skip))
+    }
+}
@@ -0,0 +1,808 @@
+use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use percent_encoding::percent_decode_str;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tiny_http::{Header, Method, Server};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+
+use crate::auth::{Auth, Scope, Tokens};
+use crate::comm::{
+    get_local_now, parse_at, parse_date, parse_duration, AddRequest, ContextCommand, Request,
+    Response,
+};
+use crate::daemon::{handle_request, ConnectionSlots};
+use crate::history::FiringRecord;
+use crate::task_manager::{
+    ClockType, ContextSettings, NotificationOptions, Task, TaskContext, TaskID, TaskManager,
+};
+
+// a larger body is turned away before it is parsed
+const MAX_BODY: u64 = 64 * 1024;
+
+/// Serves the REST API of the daemon on `addr`, e.g. 127.0.0.1:8083, in the
+/// background. Its requests are turned into those of `comm::Request` and
+/// handled like the requests of the other endpoints; clients present one of
+/// the tokens in `fmn_dir` as `Authorization: Bearer <token>`, read anew for
+/// every request. `GET /openapi.json` describes the API. Requests beyond
+/// `max_requests` at a time are answered with 503.
+pub fn serve_http(
+    addr: &str,
+    tm: Arc<Mutex<TaskManager>>,
+    max_requests: usize,
+    fmn_dir: &Path,
+) -> Result<JoinHandle<()>> {
+    // a broken tokens file is reported right away rather than per request
+    Tokens::load(fmn_dir)?;
+    let addrs: Vec<SocketAddr> = addr
+        .to_socket_addrs()
+        .map_err(|e| anyhow!("fail to resolve http address {addr}: {e}"))?
+        .collect();
+    if addrs.iter().any(|a| !a.ip().is_loopback()) {
+        warn!("http on {addr} is plaintext, tokens included; prefer localhost");
+    }
+    let server =
+        Server::http(&addrs[..]).map_err(|e| anyhow!("fail to listen on http {addr}: {e}"))?;
+    let fmn_dir = fmn_dir.to_owned();
+    info!("serve http on {}", addr);
+    let slots = ConnectionSlots::new(max_requests);
+    Ok(std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let Some(slot) = slots.take() else {
+                warn!("refuse an http request: {} are in progress", max_requests);
+                let busy = ApiError::new(503, "too many requests at once; try again later");
+                send_reply(request, Reply::from(busy));
+                continue;
+            };
+            let tm = tm.clone();
+            let fmn_dir = fmn_dir.clone();
+            std::thread::spawn(move || {
+                let _slot = slot;
+                respond(request, &tm, &fmn_dir)
+            });
+        }
+    }))
+}
+
+/// The OpenAPI document of the REST API.
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    ApiDoc::openapi()
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "fmn-daemon",
+        description = "Tasks and contexts of forget-me-not, as the requests of fmn."
+    ),
+    paths(
+        list_tasks,
+        add_task,
+        get_task,
+        update_task,
+        delete_task,
+        acknowledge_task,
+        snooze_task,
+        list_contexts,
+        add_context,
+        get_context,
+        update_context,
+        delete_context,
+        list_history,
+    ),
+    modifiers(&BearerToken),
+    security(("token" = []))
+)]
+struct ApiDoc;
+
+struct BearerToken;
+
+impl Modify for BearerToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// A task of the current context.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TaskView {
+    pub task_id: TaskID,
+    pub description: String,
+    pub context: TaskContext,
+    pub clock: ClockView,
+    pub image_path: Option<String>,
+    pub sound_path: Option<String>,
+    // left out for read-only tokens
+    pub exec: Option<String>,
+    pub notification: NotificationOptions,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+impl From<&Task> for TaskView {
+    fn from(task: &Task) -> Self {
+        Self {
+            task_id: task.task_id.clone(),
+            description: task.description.clone(),
+            context: task.context.clone(),
+            clock: ClockView::from(&task.clock_type),
+            image_path: task.get_image().map(str::to_owned),
+            sound_path: task.get_sound().map(str::to_owned),
+            exec: task.get_exec().cloned(),
+            notification: task.notification.clone(),
+            created_at: task.get_created_at(),
+        }
+    }
+}
+
+/// When a task fires, e.g. `{"every": "1h"}`.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockView {
+    Once(#[serde(with = "time::serde::rfc3339")] OffsetDateTime),
+    // every day at HH:MM
+    Daily(String),
+    Every(String),
+}
+
+impl From<&ClockType> for ClockView {
+    fn from(clock_type: &ClockType) -> Self {
+        match clock_type {
+            ClockType::Once(next_fire) => ClockView::Once(*next_fire),
+            ClockType::OncePerDay(hour, minute) => {
+                ClockView::Daily(format!("{hour:02}:{minute:02}"))
+            }
+            ClockType::Period(period) => ClockView::Every(period.clone()),
+        }
+    }
+}
+
+/// A task to add to the current context, with exactly one of `after`, `at`,
+/// `per` and `on`, taken like the ones of `fmn add`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewTask {
+    pub description: String,
+    // a duration, e.g. 10m
+    pub after: Option<String>,
+    // a time of day, e.g. 19:30
+    pub at: Option<String>,
+    // with `at`: every day
+    #[serde(default)]
+    pub per_day: bool,
+    // a period, e.g. 1h
+    pub per: Option<String>,
+    // a date, e.g. 2023-11-12T09:20
+    pub on: Option<String>,
+    pub image_path: Option<String>,
+    pub sound_path: Option<String>,
+    // rejected with 403: exec hooks are only accepted over the unix socket
+    pub exec: Option<String>,
+    #[serde(default)]
+    pub notification: NotificationOptions,
+}
+
+impl NewTask {
+    fn clock_type(&self) -> Result<ClockType> {
+        clock_type(&self.after, &self.at, self.per_day, &self.per, &self.on)?
+            .ok_or_else(|| anyhow!("give exactly one of after, at, per and on"))
+    }
+}
+
+/// Changes to a task of the current context; what is left out stays as it
+/// is. A new clock takes at most one of `after`, `at`, `per` and `on`, like
+/// the ones of a new task.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TaskUpdate {
+    pub description: Option<String>,
+    pub after: Option<String>,
+    pub at: Option<String>,
+    #[serde(default)]
+    pub per_day: bool,
+    pub per: Option<String>,
+    pub on: Option<String>,
+    pub image_path: Option<String>,
+    pub sound_path: Option<String>,
+    // rejected with 403: exec hooks are only accepted over the unix socket
+    pub exec: Option<String>,
+    // replaces the options of the task as a whole
+    pub notification: Option<NotificationOptions>,
+}
+
+// the clock given by one of the fields, None if none is
+fn clock_type(
+    after: &Option<String>,
+    at: &Option<String>,
+    per_day: bool,
+    per: &Option<String>,
+    on: &Option<String>,
+) -> Result<Option<ClockType>> {
+    let clock_type = match (after, at, per, on) {
+        (None, None, None, None) => return Ok(None),
+        (Some(duration), None, None, None) => {
+            let duration = parse_duration(duration)?;
+            if duration.is_zero() {
+                return Err(anyhow!("after <duration> should not be 0"));
+            }
+            ClockType::Once(get_local_now() + duration)
+        }
+        (None, Some(time), None, None) => {
+            let next_fire = parse_at(time)?;
+            if per_day {
+                ClockType::OncePerDay(next_fire.hour(), next_fire.minute())
+            } else {
+                ClockType::Once(next_fire)
+            }
+        }
+        (None, None, Some(period), None) => ClockType::Period(period.clone()),
+        (None, None, None, Some(date)) => ClockType::Once(parse_date(date)?),
+        _ => return Err(anyhow!("give at most one of after, at, per and on")),
+    };
+    Ok(Some(clock_type))
+}
+
+/// When to remind of a task again.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct Snooze {
+    // a duration, e.g. 10m
+    pub after: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ContextView {
+    pub name: TaskContext,
+    // whether tasks are added to and fired from it
+    pub current: bool,
+    pub settings: ContextSettings,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewContext {
+    pub name: TaskContext,
+    #[serde(default)]
+    pub settings: ContextSettings,
+}
+
+/// Changes to a context; what is left out stays as it is.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct ContextUpdate {
+    // the defaults to change
+    pub settings: Option<ContextSettings>,
+    // drop the current defaults first
+    pub clear: bool,
+    // true switches to the context
+    pub current: Option<bool>,
+}
+
+/// What every failed request is answered with.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: u16,
+    pub error: String,
+}
+
+impl ApiError {
+    fn new(status: u16, error: impl Into<String>) -> Self {
+        Self {
+            status,
+            error: error.into(),
+        }
+    }
+
+    fn invalid(e: anyhow::Error) -> Self {
+        Self::new(400, e.to_string())
+    }
+}
+
+struct Reply {
+    status: u16,
+    // None for 204
+    body: Option<String>,
+}
+
+impl Reply {
+    fn json<T: Serialize>(status: u16, value: &T) -> Self {
+        let body = serde_json::to_string(value).expect("fail to serialize reply");
+        Self {
+            status,
+            body: Some(body),
+        }
+    }
+
+    fn no_content() -> Self {
+        Self {
+            status: 204,
+            body: None,
+        }
+    }
+}
+
+impl From<ApiError> for Reply {
+    fn from(e: ApiError) -> Self {
+        Reply::json(e.status, &e)
+    }
+}
+
+type Handled = std::result::Result<Reply, ApiError>;
+
+fn respond(mut request: tiny_http::Request, tm: &Mutex<TaskManager>, fmn_dir: &Path) {
+    info!(
+        "receive http request: {} {}",
+        request.method(),
+        request.url()
+    );
+    let reply = handle(&mut request, tm, fmn_dir).unwrap_or_else(Reply::from);
+    send_reply(request, reply);
+}
+
+fn send_reply(request: tiny_http::Request, reply: Reply) {
+    let response = match reply.body {
+        Some(body) => tiny_http::Response::from_string(body).with_header(
+            Header::from_bytes("Content-Type", "application/json")
+                .expect("fail to make content type header"),
+        ),
+        None => tiny_http::Response::from_string(""),
+    };
+    if let Err(e) = request.respond(response.with_status_code(reply.status)) {
+        error!("fail to send back http response: {}", e);
+    }
+}
+
+fn handle(request: &mut tiny_http::Request, tm: &Mutex<TaskManager>, fmn_dir: &Path) -> Handled {
+    let url = request.url().to_owned();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let method = request.method().clone();
+    // the document is open so that tools can find their way in
+    if method == Method::Get && path == "/openapi.json" {
+        return Ok(Reply::json(200, &openapi()));
+    }
+    let scope = authorize(request, fmn_dir)?;
+    if scope == Scope::Read && method != Method::Get {
+        warn!("reject a request of a read-only token: {} {}", method, path);
+        return Err(ApiError::new(403, "the token is read-only"));
+    }
+    let body = read_body(request)?;
+    let segments = path
+        .trim_matches('/')
+        .split('/')
+        .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+        .collect::<Vec<_>>();
+    let segments = segments.iter().map(String::as_str).collect::<Vec<_>>();
+
+    let mut tm = tm.lock().expect("task manager lock is poisoned");
+    let tm = &mut *tm;
+    match (&method, segments.as_slice()) {
+        (Method::Get, ["tasks"]) => list_tasks(tm, scope),
+        (Method::Post, ["tasks"]) => add_task(tm, parse(&body)?),
+        (Method::Get, ["tasks", id]) => get_task(tm, id, scope),
+        (Method::Patch, ["tasks", id]) => update_task(tm, id, parse(&body)?),
+        (Method::Delete, ["tasks", id]) => delete_task(tm, id),
+        (Method::Post, ["tasks", id, "acknowledge"]) => acknowledge_task(tm, id),
+        (Method::Post, ["tasks", id, "snooze"]) => snooze_task(tm, id, parse(&body)?),
+        (Method::Get, ["contexts"]) => list_contexts(tm),
+        (Method::Post, ["contexts"]) => add_context(tm, parse(&body)?),
+        (Method::Get, ["contexts", name]) => get_context(tm, name),
+        (Method::Patch, ["contexts", name]) => update_context(tm, name, parse(&body)?),
+        (Method::Delete, ["contexts", name]) => delete_context(tm, name),
+        (Method::Get, ["history"]) => list_history(tm, query),
+        (_, ["tasks" | "contexts", ..] | ["history"]) => Err(ApiError::new(
+            405,
+            format!("{method} is not allowed on {path}"),
+        )),
+        _ => Err(ApiError::new(404, format!("no such resource: {path}"))),
+    }
+}
+
+fn authorize(request: &tiny_http::Request, fmn_dir: &Path) -> std::result::Result<Scope, ApiError> {
+    let tokens = Tokens::load(fmn_dir).map_err(|e| {
+        error!("fail to load tokens: {:#}", e);
+        ApiError::new(500, "fail to load tokens")
+    })?;
+    let token = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .and_then(|h| h.value.as_str().strip_prefix("Bearer "));
+    Auth::Token(tokens).authorize(token).map_err(|reason| {
+        warn!("reject an http client: {}", reason);
+        ApiError::new(401, reason)
+    })
+}
+
+fn read_body(request: &mut tiny_http::Request) -> std::result::Result<Vec<u8>, ApiError> {
+    let mut body = vec![];
+    request
+        .as_reader()
+        .take(MAX_BODY + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| ApiError::new(400, format!("fail to read request body: {e}")))?;
+    if body.len() as u64 > MAX_BODY {
+        return Err(ApiError::new(413, "the request body is too large"));
+    }
+    Ok(body)
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8]) -> std::result::Result<T, ApiError> {
+    serde_json::from_slice(body)
+        .map_err(|e| ApiError::new(400, format!("fail to parse request body: {e}")))
+}
+
+// the response of the daemon, with its failures turned into 400
+fn run(tm: &mut TaskManager, request: Request) -> std::result::Result<Response, ApiError> {
+    match handle_request(request, tm) {
+        Response::Fail(reason) => Err(ApiError::new(400, reason)),
+        response => Ok(response),
+    }
+}
+
+fn unexpected(response: Response) -> ApiError {
+    error!("unexpected response to an http request: {:?}", response);
+    ApiError::new(500, "unexpected response of fmn-daemon")
+}
+
+// the task of the current context with the id, or a prefix of it like fmn
+// takes
+fn find_task(tm: &TaskManager, id: &str) -> std::result::Result<Task, ApiError> {
+    tm.get_tasks()
+        .into_iter()
+        .find(|t| t.task_id.starts_with(id))
+        .ok_or_else(|| ApiError::new(404, format!("no such task found: {id}")))
+}
+
+fn find_context(tm: &TaskManager, name: &str) -> std::result::Result<ContextView, ApiError> {
+    let contexts = tm.list_context();
+    let position = contexts
+        .iter()
+        .position(|c| c == name)
+        .ok_or_else(|| ApiError::new(404, format!("no such context: {name}")))?;
+    Ok(ContextView {
+        name: name.to_owned(),
+        current: position == 0,
+        settings: tm.context_settings(&contexts[position]),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/tasks",
+    tag = "tasks",
+    responses(
+        (status = 200, description = "The tasks of the current context", body = [TaskView]),
+        (status = 401, body = ApiError),
+    )
+)]
+fn list_tasks(tm: &mut TaskManager, scope: Scope) -> Handled {
+    match run(tm, Request::Show)? {
+        Response::GetTasks(tasks) => Ok(Reply::json(
+            200,
+            &tasks
+                .into_iter()
+                .map(|task| task_view(task, scope))
+                .collect::<Vec<_>>(),
+        )),
+        response => Err(unexpected(response)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/tasks",
+    tag = "tasks",
+    request_body = NewTask,
+    responses(
+        (status = 201, description = "The task added", body = TaskView),
+        (status = 400, body = ApiError),
+        (status = 403, description = "The task has an exec hook", body = ApiError),
+    )
+)]
+fn add_task(tm: &mut TaskManager, task: NewTask) -> Handled {
+    if task.exec.is_some() {
+        return Err(ApiError::new(
+            403,
+            "exec hooks are only accepted over the unix socket",
+        ));
+    }
+    let clock_type = task.clock_type().map_err(ApiError::invalid)?;
+    let request = AddRequest {
+        description: task.description,
+        clock_type,
+        image_path: task.image_path,
+        sound_path: task.sound_path,
+        exec: None,
+        notification: task.notification,
+    };
+    match run(tm, Request::Add(request))? {
+        // the newest task of the current context
+        Response::AddSuccess => match tm.get_tasks().last() {
+            Some(task) => Ok(Reply::json(201, &TaskView::from(task))),
+            None => Err(ApiError::new(500, "the task added is missing")),
+        },
+        response => Err(unexpected(response)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/tasks/{id}",
+    tag = "tasks",
+    params(("id" = String, Path, description = "The id of the task, or a prefix of it")),
+    responses(
+        (status = 200, body = TaskView),
+        (status = 404, body = ApiError),
+    )
+)]
+fn get_task(tm: &mut TaskManager, id: &str, scope: Scope) -> Handled {
+    Ok(Reply::json(200, &task_view(find_task(tm, id)?, scope)))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/tasks/{id}",
+    tag = "tasks",
+    params(("id" = String, Path, description = "The id of the task, or a prefix of it")),
+    request_body = TaskUpdate,
+    responses(
+        (status = 200, description = "The task changed, under the same id", body = TaskView),
+        (status = 400, body = ApiError),
+        (status = 403, description = "The update has an exec hook", body = ApiError),
+        (status = 404, body = ApiError),
+    )
+)]
+fn update_task(tm: &mut TaskManager, id: &str, update: TaskUpdate) -> Handled {
+    if update.exec.is_some() {
+        return Err(ApiError::new(
+            403,
+            "exec hooks are only accepted over the unix socket",
+        ));
+    }
+    let mut task = find_task(tm, id)?;
+    let clock = clock_type(
+        &update.after,
+        &update.at,
+        update.per_day,
+        &update.per,
+        &update.on,
+    );
+    if let Some(clock_type) = clock.map_err(ApiError::invalid)? {
+        task.clock_type = clock_type;
+    }
+    if let Some(description) = update.description {
+        task.description = description;
+    }
+    if let Some(image_path) = update.image_path {
+        task.add_image(image_path);
+    }
+    if let Some(sound_path) = update.sound_path {
+        task.add_sound(sound_path);
+    }
+    if let Some(notification) = update.notification {
+        task.notification = notification;
+    }
+    let task_id = task.task_id.clone();
+    match run(tm, Request::Replace(task))? {
+        Response::AddSuccess => Ok(Reply::json(200, &TaskView::from(&find_task(tm, &task_id)?))),
+        response => Err(unexpected(response)),
+    }
+}
+
+// read-only tokens don't get to see the commands tasks run
+fn task_view(task: Task, scope: Scope) -> TaskView {
+    match scope {
+        Scope::Read => TaskView::from(&task.without_exec()),
+        Scope::Full => TaskView::from(&task),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/tasks/{id}",
+    tag = "tasks",
+    params(("id" = String, Path, description = "The id of the task, or a prefix of it")),
+    responses(
+        (status = 204, description = "The task is cancelled"),
+        (status = 404, body = ApiError),
+    )
+)]
+fn delete_task(tm: &mut TaskManager, id: &str) -> Handled {
+    let task = find_task(tm, id)?;
+    match run(tm, Request::Cancel(task.task_id))? {
+        Response::RemoveSuccess => Ok(Reply::no_content()),
+        response => Err(unexpected(response)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/tasks/{id}/acknowledge",
+    tag = "tasks",
+    params(("id" = String, Path, description = "The id of a task that fired")),
+    responses(
+        (status = 204, description = "Its latest firing is acknowledged"),
+        (status = 400, body = ApiError),
+    )
+)]
+fn acknowledge_task(tm: &mut TaskManager, id: &str) -> Handled {
+    match run(tm, Request::Acknowledge(id.to_owned()))? {
+        Response::AcknowledgeSuccess => Ok(Reply::no_content()),
+        response => Err(unexpected(response)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/tasks/{id}/snooze",
+    tag = "tasks",
+    params(("id" = String, Path, description = "The id of the task, or a prefix of it")),
+    request_body = Snooze,
+    responses(
+        (status = 201, description = "The task reminded of again", body = TaskView),
+        (status = 404, body = ApiError),
+    )
+)]
+fn snooze_task(tm: &mut TaskManager, id: &str, snooze: Snooze) -> Handled {
+    let task = find_task(tm, id)?;
+    let after = parse_duration(&snooze.after).map_err(|e| ApiError::new(400, e.to_string()))?;
+    let task_id = task.task_id;
+    match run(tm, Request::Snooze { task_id, after })? {
+        Response::AddSuccess => match tm.get_tasks().last() {
+            Some(task) => Ok(Reply::json(201, &TaskView::from(task))),
+            None => Err(ApiError::new(500, "the task snoozed is missing")),
+        },
+        response => Err(unexpected(response)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/contexts",
+    tag = "contexts",
+    responses((status = 200, description = "The current context first", body = [ContextView]))
+)]
+fn list_contexts(tm: &mut TaskManager) -> Handled {
+    match run(tm, Request::ContextRequest(ContextCommand::List))? {
+        Response::GetContexts(contexts) => {
+            let views = contexts
+                .iter()
+                .map(|c| find_context(tm, c))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            Ok(Reply::json(200, &views))
+        }
+        response => Err(unexpected(response)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/contexts",
+    tag = "contexts",
+    request_body = NewContext,
+    responses(
+        (status = 201, description = "The context defined", body = ContextView),
+        (status = 400, description = "A notifier the daemon doesn't have", body = ApiError),
+        (status = 409, body = ApiError),
+    )
+)]
+fn add_context(tm: &mut TaskManager, context: NewContext) -> Handled {
+    if tm.list_context().contains(&context.name) {
+        return Err(ApiError::new(
+            409,
+            format!("context {} already exists", context.name),
+        ));
+    }
+    let command = ContextCommand::Define {
+        context: context.name.clone(),
+        settings: context.settings,
+    };
+    match run(tm, Request::ContextRequest(command))? {
+        Response::AddSuccess => Ok(Reply::json(201, &find_context(tm, &context.name)?)),
+        response => Err(unexpected(response)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/contexts/{name}",
+    tag = "contexts",
+    params(("name" = String, Path)),
+    responses(
+        (status = 200, body = ContextView),
+        (status = 404, body = ApiError),
+    )
+)]
+fn get_context(tm: &mut TaskManager, name: &str) -> Handled {
+    Ok(Reply::json(200, &find_context(tm, name)?))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/contexts/{name}",
+    tag = "contexts",
+    params(("name" = String, Path)),
+    request_body = ContextUpdate,
+    responses(
+        (status = 200, description = "The context changed", body = ContextView),
+        (status = 400, description = "A notifier the daemon doesn't have", body = ApiError),
+        (status = 404, body = ApiError),
+    )
+)]
+fn update_context(tm: &mut TaskManager, name: &str, update: ContextUpdate) -> Handled {
+    find_context(tm, name)?;
+    if update.current == Some(false) {
+        return Err(ApiError::new(400, "switch to another context instead"));
+    }
+    if update.settings.is_some() || update.clear {
+        let command = ContextCommand::Configure {
+            context: name.to_owned(),
+            settings: update.settings.unwrap_or_default(),
+            clear: update.clear,
+        };
+        match run(tm, Request::ContextRequest(command))? {
+            Response::ConfigureContextSuccess => {}
+            response => return Err(unexpected(response)),
+        }
+    }
+    if update.current == Some(true) {
+        let command = ContextCommand::Set {
+            context: name.to_owned(),
+        };
+        match run(tm, Request::ContextRequest(command))? {
+            Response::SetContextSuccess => {}
+            response => return Err(unexpected(response)),
+        }
+    }
+    Ok(Reply::json(200, &find_context(tm, name)?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/contexts/{name}",
+    tag = "contexts",
+    params(("name" = String, Path)),
+    responses(
+        (status = 204, description = "The context is removed with its tasks"),
+        (status = 404, body = ApiError),
+    )
+)]
+fn delete_context(tm: &mut TaskManager, name: &str) -> Handled {
+    find_context(tm, name)?;
+    let command = ContextCommand::Rm {
+        context: name.to_owned(),
+    };
+    match run(tm, Request::ContextRequest(command))? {
+        Response::RemoveSuccess => Ok(Reply::no_content()),
+        response => Err(unexpected(response)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/history",
+    tag = "history",
+    params(("limit" = Option<usize>, Query, description = "The latest n firings, 20 by default")),
+    responses((status = 200, body = [FiringRecord]))
+)]
+fn list_history(tm: &mut TaskManager, query: &str) -> Handled {
+    let limit = match query.split('&').find_map(|p| p.strip_prefix("limit=")) {
+        Some(limit) => limit
+            .parse()
+            .map_err(|_| ApiError::new(400, format!("invalid limit: {limit}")))?,
+        None => 20,
+    };
+    match run(tm, Request::History(limit))? {
+        Response::GetHistory(records) => Ok(Reply::json(200, &records)),
+        response => Err(unexpected(response)),
+    }
+}
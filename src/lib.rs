@@ -1,12 +1,22 @@
 #![forbid(unsafe_code)]
 
+pub mod auth;
 pub mod client;
 pub mod comm;
+pub mod config;
 pub mod daemon;
+pub mod duration;
+pub mod endpoint;
+pub mod events;
 pub mod format;
+pub mod history;
+pub mod http;
 pub mod notify;
 pub mod scheduler;
+pub mod socket;
 pub mod task_manager;
+pub mod template;
+pub mod tls;
 
 use comm::get_local_now;
 use log::{debug, LevelFilter};
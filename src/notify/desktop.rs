@@ -0,0 +1,245 @@
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::collections::HashMap;
+use std::process::Command;
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::{error, info};
+#[cfg(all(unix, not(target_os = "macos")))]
+use notify_rust::NotificationHandle;
+use notify_rust::{Notification, Timeout};
+#[cfg(all(unix, not(target_os = "macos")))]
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+#[cfg(all(unix, not(target_os = "macos")))]
+use super::first_url;
+use super::{send_action, summary, Firing, Notifier, TaskAction};
+use crate::comm::parse_duration;
+#[cfg(all(unix, not(target_os = "macos")))]
+use crate::task_manager::TaskID;
+use crate::task_manager::{Task, Urgency};
+
+// the notification last shown for each task with `replace`: servers only
+// replace a notification under the id they gave it, and report the actions of
+// the replacement under that id too, so the threads waiting on the older
+// ones have to ignore them
+#[cfg(all(unix, not(target_os = "macos")))]
+static SHOWN: Lazy<Mutex<HashMap<TaskID, Shown>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[cfg(all(unix, not(target_os = "macos")))]
+struct Shown {
+    id: u32,
+    generation: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DesktopConfig {
+    // add "Done" and "Snooze" buttons; only supported on linux
+    pub actions: bool,
+    pub snooze: String,
+}
+
+impl Default for DesktopConfig {
+    fn default() -> Self {
+        Self {
+            actions: true,
+            snooze: "10m".to_owned(),
+        }
+    }
+}
+
+pub struct DesktopNotifier {
+    name: String,
+    config: DesktopConfig,
+    snooze: Duration,
+}
+
+impl DesktopNotifier {
+    pub fn new(name: Option<String>, config: DesktopConfig) -> Result<Self> {
+        let snooze = parse_duration(&config.snooze)?;
+        Ok(Self {
+            name: name.unwrap_or_else(|| "desktop".to_owned()),
+            config,
+            snooze,
+        })
+    }
+}
+
+impl Notifier for DesktopNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, firing: &Firing) -> Result<()> {
+        if self.config.actions && firing.takes_actions {
+            return show_actionable(firing, &self.config.snooze, self.snooze);
+        }
+        show_plain(&firing.task)
+    }
+
+    fn shows_on_desktop(&self) -> bool {
+        true
+    }
+}
+
+// the notification of a task, styled by its notification options
+fn task_notification(task: &Task) -> Notification {
+    let options = &task.notification;
+    let mut notification = build_notification(summary(task), &task.description, task.get_image());
+    if let Some(urgency) = options.urgency {
+        set_urgency(&mut notification, urgency);
+    }
+    match options.timeout_ms {
+        Some(0) => {
+            notification.timeout(Timeout::Never);
+        }
+        Some(ms) => {
+            notification.timeout(Timeout::Milliseconds(ms));
+        }
+        None => {}
+    }
+    if let Some(icon) = &options.icon {
+        notification.icon(icon);
+    }
+    notification
+}
+
+// shows the notification of the task, in place of its previous one if it
+// has `replace`; returns the generation of a replaced notification
+#[cfg(all(unix, not(target_os = "macos")))]
+fn show(mut notification: Notification, task: &Task) -> Result<(NotificationHandle, Option<u64>)> {
+    let show = |notification: &Notification| {
+        notification
+            .show()
+            .map_err(|e| anyhow!("fail to show notification to de: {}", e))
+    };
+    if !task.notification.replace {
+        return Ok((show(&notification)?, None));
+    }
+    let mut shown = SHOWN.lock().expect("notification lock is poisoned");
+    if let Some(previous) = shown.get(&task.task_id) {
+        notification.id(previous.id);
+    }
+    let handle = show(&notification)?;
+    let entry = shown.entry(task.task_id.clone()).or_insert(Shown {
+        id: 0,
+        generation: 0,
+    });
+    entry.id = handle.id();
+    entry.generation += 1;
+    Ok((handle, Some(entry.generation)))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn show_plain(task: &Task) -> Result<()> {
+    show(task_notification(task), task).map(|_| ())
+}
+
+#[cfg(target_os = "macos")]
+fn show_plain(task: &Task) -> Result<()> {
+    task_notification(task)
+        .show()
+        .map_err(|e| anyhow!("fail to show notification to de: {}", e))
+        .map(|_| ())
+}
+
+fn build_notification(summary: &str, body: &str, image_path: Option<&str>) -> Notification {
+    let mut notification = Notification::new();
+    notification.summary(summary).body(body);
+
+    if let Some(image_path) = image_path {
+        info!("add image path hint: {}", &image_path);
+        add_image(&mut notification, image_path);
+    }
+    notification
+}
+
+pub fn desktop_notification(summary: &str, body: &str, image_path: Option<&str>) -> Result<()> {
+    build_notification(summary, body, image_path)
+        .show()
+        .map_err(|e| anyhow!("fail to show notification to de: {}", e))
+        .map(|_| ())
+}
+
+// shows the notification with "Done" and "Snooze" buttons and waits for the
+// user's choice in the background; clicking it opens the first url of the
+// description
+#[cfg(all(unix, not(target_os = "macos")))]
+fn show_actionable(firing: &Firing, snooze_label: &str, snooze: Duration) -> Result<()> {
+    let task = &firing.task;
+    let mut notification = task_notification(task);
+    notification
+        .action("done", "Done")
+        .action("snooze", &format!("Snooze {snooze_label}"));
+    let url = first_url(&task.description).map(str::to_owned);
+    if url.is_some() {
+        notification.action("default", "Open");
+    }
+    let (handle, generation) = show(notification, task)?;
+    let firing = firing.clone();
+    std::thread::spawn(move || {
+        handle.wait_for_action(|action| {
+            if let Some(generation) = generation {
+                let shown = SHOWN.lock().expect("notification lock is poisoned");
+                let latest = shown.get(&firing.task.task_id).map(|s| s.generation);
+                if latest != Some(generation) {
+                    return;
+                }
+            }
+            info!("user chooses {} for task {}", action, firing.task.task_id);
+            match action {
+                "done" => send_action(TaskAction::Done(firing)),
+                "snooze" => send_action(TaskAction::Snooze(firing, snooze)),
+                "default" => {
+                    if let Some(url) = url {
+                        if let Err(e) = open_url(&url) {
+                            error!("fail to open {}: {}", url, e);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        })
+    });
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn show_actionable(firing: &Firing, _snooze_label: &str, _snooze: Duration) -> Result<()> {
+    info!("macOS doesn't support notification actions");
+    show_plain(&firing.task)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn open_url(url: &str) -> Result<()> {
+    Command::new("xdg-open").arg(url).spawn()?;
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn add_image(notification: &mut Notification, image_path: &str) {
+    notification.image_path(image_path);
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn set_urgency(notification: &mut Notification, urgency: Urgency) {
+    notification.urgency(match urgency {
+        Urgency::Low => notify_rust::Urgency::Low,
+        Urgency::Normal => notify_rust::Urgency::Normal,
+        Urgency::Critical => notify_rust::Urgency::Critical,
+    });
+}
+
+#[cfg(target_os = "macos")]
+fn add_image(_notification: &mut Notification, _image_path: &str) {
+    info!("macOS doesn't support attach images to notifications");
+}
+
+#[cfg(target_os = "macos")]
+fn set_urgency(_notification: &mut Notification, _urgency: Urgency) {
+    info!("macOS doesn't support notification urgency");
+}
@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+use serde::Deserialize;
+
+use super::{Firing, NotifierRegistry};
+use crate::comm::get_local_now;
+use crate::task_manager::{ClockType, Task};
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DigestConfig {
+    // firings within this long after the first one are delivered together
+    pub window_ms: u64,
+    // once reached, firings wait and are merged into the next digest
+    pub max_per_minute: Option<usize>,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self {
+            window_ms: 1000,
+            max_per_minute: None,
+        }
+    }
+}
+
+// when the notifications of the last minute went out, shared by the digest
+// and the retries so that both keep to `max_per_minute`
+#[derive(Clone, Default)]
+pub(super) struct SentLog(Arc<Mutex<VecDeque<Instant>>>);
+
+impl SentLog {
+    // how long until another notification is within `max` per minute
+    pub(super) fn wait(&self, max: usize) -> Option<Duration> {
+        let mut sent = self.0.lock().expect("sent log lock is poisoned");
+        while sent.front().is_some_and(|at| at.elapsed() >= MINUTE) {
+            sent.pop_front();
+        }
+        match sent.front() {
+            Some(&oldest) if sent.len() >= max => {
+                Some((oldest + MINUTE).saturating_duration_since(Instant::now()))
+            }
+            _ => None,
+        }
+    }
+
+    pub(super) fn push(&self) {
+        let mut sent = self.0.lock().expect("sent log lock is poisoned");
+        sent.push_back(Instant::now());
+    }
+}
+
+/// A single firing standing for several ones, e.g. "3 reminders" listing
+/// their descriptions. It is no task of its own, so its notification offers
+/// no actions.
+pub fn digest_firing(firings: &[&Firing]) -> Firing {
+    let description = firings
+        .iter()
+        .map(|f| format!("- {}", f.task.description))
+        .collect::<Vec<String>>()
+        .join("\n");
+    let now = get_local_now();
+    let mut task = Task::new(description, ClockType::Once(now));
+    if let Some(first) = firings.first() {
+        if firings.iter().all(|f| f.task.context == first.task.context) {
+            task = task.with_context(first.task.context.clone());
+        }
+    }
+    task.notification.summary = Some(format!("{} reminders", firings.len()));
+    task.notification.urgency = firings
+        .iter()
+        .filter_map(|f| f.task.notification.urgency)
+        .max();
+    Firing {
+        stored: task.clone(),
+        task,
+        scheduled_at: firings.iter().map(|f| f.scheduled_at).min().unwrap_or(now),
+        fired_at: now,
+        takes_actions: false,
+    }
+}
+
+// gathers queued firings into batches and hands them to the registry, which
+// turns a batch into one notification per notifier
+pub(super) fn run_digest(
+    registry: NotifierRegistry,
+    config: DigestConfig,
+    receiver: Receiver<Firing>,
+) {
+    let window = Duration::from_millis(config.window_ms);
+    let mut batch: Vec<Firing> = vec![];
+    let mut closed = false;
+    while !closed {
+        if batch.is_empty() {
+            match receiver.recv() {
+                Ok(firing) => batch.push(firing),
+                Err(_) => return,
+            }
+        }
+        closed = !collect_until(&receiver, Instant::now() + window, &mut batch);
+        if let Some(max) = config.max_per_minute {
+            while let Some(wait) = registry.sent.wait(max).filter(|_| !closed) {
+                info!(
+                    "hold back {} firings for the limit of {} notifications per minute",
+                    batch.len(),
+                    max
+                );
+                closed = !collect_until(&receiver, Instant::now() + wait, &mut batch);
+            }
+            registry.sent.push();
+        }
+        if let Err(e) = registry.deliver(std::mem::take(&mut batch)) {
+            error!("fail to send notification: {}", e);
+        }
+    }
+}
+
+// adds what arrives until the deadline to the batch; false once the queue is
+// closed
+fn collect_until(receiver: &Receiver<Firing>, deadline: Instant, batch: &mut Vec<Firing>) -> bool {
+    loop {
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(firing) => batch.push(firing),
+            Err(RecvTimeoutError::Timeout) => return true,
+            Err(RecvTimeoutError::Disconnected) => return false,
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use serde::Deserialize;
+
+use super::{Firing, Notifier};
+use crate::template::Template;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpSecurity {
+    #[default]
+    Starttls,
+    // implicit tls, usually on port 465
+    Tls,
+    // no encryption at all; only for local relays
+    Plain,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmailConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    // a file with the username on the first line and the password on the second
+    pub credentials_file: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default = "default_subject")]
+    pub subject: String,
+    #[serde(default = "default_body")]
+    pub body: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_subject() -> String {
+    "[forget-me-not] {description}".to_owned()
+}
+
+fn default_body() -> String {
+    "{description}\n\ntask: {task_id} ({clock})\ncontext: {context}\nscheduled at: {scheduled_at}\n"
+        .to_owned()
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+pub struct EmailNotifier {
+    name: String,
+    config: EmailConfig,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    subject: Template,
+    body: Template,
+    transport: SmtpTransport,
+}
+
+impl EmailNotifier {
+    pub fn new(name: Option<String>, config: EmailConfig) -> Result<Self> {
+        let mut builder = match config.security {
+            SmtpSecurity::Starttls => SmtpTransport::starttls_relay(&config.host)?,
+            SmtpSecurity::Tls => SmtpTransport::relay(&config.host)?,
+            SmtpSecurity::Plain => SmtpTransport::builder_dangerous(&config.host),
+        };
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+        if let Some(path) = &config.credentials_file {
+            builder = builder.credentials(read_credentials(path)?);
+        }
+        let transport = builder
+            .timeout(Some(Duration::from_secs(config.timeout_secs)))
+            .build();
+        let from = config
+            .from
+            .parse()
+            .context(format!("invalid sender {}", config.from))?;
+        let to = config
+            .to
+            .iter()
+            .map(|to| to.parse().context(format!("invalid recipient {to}")))
+            .collect::<Result<Vec<Mailbox>>>()?;
+        if to.is_empty() {
+            return Err(anyhow!("email notifier needs at least one recipient"));
+        }
+        let subject = Template::parse_notifier_text(&config.subject)?;
+        let body = Template::parse_notifier_text(&config.body)?;
+        Ok(Self {
+            name: name.unwrap_or_else(|| "email".to_owned()),
+            config,
+            from,
+            to,
+            subject,
+            body,
+            transport,
+        })
+    }
+
+    // notifier templates can't use the occurrence, so it isn't passed on
+    fn message(&self, firing: &Firing) -> Result<Message> {
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(self.subject.render(firing, 0))
+            .header(ContentType::TEXT_PLAIN);
+        for to in self.to.iter() {
+            builder = builder.to(to.clone());
+        }
+        builder
+            .body(self.body.render(firing, 0))
+            .context("fail to build email")
+    }
+}
+
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, firing: &Firing) -> Result<()> {
+        self.transport
+            .send(&self.message(firing)?)
+            .map_err(|e| anyhow!("fail to send email via {}: {}", self.config.host, e))?;
+        Ok(())
+    }
+
+    fn retries_by_default(&self) -> bool {
+        true
+    }
+}
+
+fn read_credentials(path: &str) -> Result<Credentials> {
+    let (username, password) = read_login(path)?;
+    Ok(Credentials::new(username, password))
+}
+
+// the username and password lines of a credentials file
+pub(super) fn read_login(path: &str) -> Result<(String, String)> {
+    let content =
+        std::fs::read_to_string(path).context(format!("fail to read credentials {path}"))?;
+    let mut lines = content.lines();
+    match (lines.next(), lines.next()) {
+        (Some(username), Some(password)) => Ok((username.trim().to_owned(), password.to_owned())),
+        _ => Err(anyhow!(
+            "credentials file {path} should hold a username and a password line"
+        )),
+    }
+}
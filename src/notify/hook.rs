@@ -0,0 +1,151 @@
+use std::io::{ErrorKind, Read};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use rustix::process::{kill_process_group, Pid, Signal};
+use serde::Deserialize;
+
+use super::Firing;
+use crate::history::HookRun;
+
+// longer output is cut off before it goes into the history
+const MAX_OUTPUT_BYTES: usize = 4096;
+// how long the output of a process that is gone may take to drain; a
+// background process it left may hold the pipe open for good
+const DRAIN: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HookConfig {
+    // run for every firing, in addition to the task's own `--exec`
+    pub command: Option<String>,
+    pub timeout_secs: u64,
+}
+
+impl Default for HookConfig {
+    fn default() -> Self {
+        Self {
+            command: None,
+            timeout_secs: 60,
+        }
+    }
+}
+
+/// Runs `command` with `sh -c`, exposing the fired task in `FMN_*` environment
+/// variables, and kills it after `timeout`.
+pub fn run_hook(command: &str, firing: &Firing, timeout: Duration) -> HookRun {
+    let mut run = HookRun {
+        command: command.to_owned(),
+        ..Default::default()
+    };
+    let task = &firing.task;
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("FMN_TASK_ID", &task.task_id)
+        .env("FMN_DESCRIPTION", &task.description)
+        .env("FMN_CONTEXT", &task.context)
+        .env("FMN_CLOCK", task.clock_type.to_string())
+        .env("FMN_SCHEDULED_AT", firing.scheduled_at.to_string())
+        .env("FMN_FIRED_AT", firing.fired_at.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // its own process group, so that a timeout also kills the grandchildren
+        .process_group(0)
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("fail to spawn hook {}: {}", command, e);
+            run.error = Some(e.to_string());
+            return run;
+        }
+    };
+    let stdout = capture(child.stdout.take());
+    let stderr = capture(child.stderr.take());
+    match wait_timeout(&mut child, timeout) {
+        Ok(Some(status)) => run.exit_status = status.code(),
+        Ok(None) => {
+            warn!("hook {} timed out after {:?}", command, timeout);
+            run.timed_out = true;
+            let _ = kill_process_group(Pid::from_child(&child), Signal::KILL);
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        Err(e) => run.error = Some(e.to_string()),
+    }
+    run.stdout = stdout.output();
+    run.stderr = stderr.output();
+    info!(
+        "hook {} for task {} exits with {:?}",
+        command, task.task_id, run.exit_status
+    );
+    run
+}
+
+pub(super) fn wait_timeout(
+    child: &mut Child,
+    timeout: Duration,
+) -> std::io::Result<Option<std::process::ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        sleep(Duration::from_millis(20));
+    }
+}
+
+// the output of a child, read in the background so that it never blocks on
+// its pipe
+pub(super) struct Capture {
+    output: Arc<Mutex<Vec<u8>>>,
+    // disconnected once the pipe ends
+    ended: Receiver<()>,
+}
+
+impl Capture {
+    // what was read, once the child is gone; a pipe still open after a
+    // moment is left to the reader in the background
+    pub(super) fn output(self) -> String {
+        let _ = self.ended.recv_timeout(DRAIN);
+        let output = self.output.lock().expect("output lock is poisoned");
+        String::from_utf8_lossy(&output).into_owned()
+    }
+}
+
+pub(super) fn capture<R>(pipe: Option<R>) -> Capture
+where
+    R: Read + Send + 'static,
+{
+    let output = Arc::new(Mutex::new(vec![]));
+    let (sender, ended) = channel();
+    let read = output.clone();
+    std::thread::spawn(move || {
+        let _sender = sender;
+        let Some(mut pipe) = pipe else { return };
+        let mut chunk = [0; 1024];
+        loop {
+            let n = match pipe.read(&mut chunk) {
+                Ok(0) => return,
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => return,
+            };
+            // the rest is read all the same, so that the child can go on
+            let mut output = read.lock().expect("output lock is poisoned");
+            let room = MAX_OUTPUT_BYTES.saturating_sub(output.len());
+            output.extend_from_slice(&chunk[..n.min(room)]);
+        }
+    });
+    Capture { output, ended }
+}
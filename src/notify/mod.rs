@@ -0,0 +1,697 @@
+mod desktop;
+mod digest;
+mod email;
+mod hook;
+mod mqtt;
+mod push;
+mod retry;
+mod sound;
+mod syslog;
+mod terminal;
+mod tts;
+mod webhook;
+
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime};
+
+use crate::comm::get_local_now;
+use crate::history::{Delivery, FiringRecord, History, HookRun};
+use crate::task_manager::{Task, TaskContext, TaskID};
+use crate::template::render_firing;
+pub use desktop::{desktop_notification, DesktopConfig, DesktopNotifier};
+pub use digest::{digest_firing, DigestConfig};
+use digest::{run_digest, SentLog};
+pub use email::{EmailConfig, EmailNotifier, SmtpSecurity};
+pub use hook::{run_hook, HookConfig};
+pub use mqtt::{MqttConfig, MqttNotifier};
+pub use push::{GotifyConfig, GotifyNotifier, NtfyConfig, NtfyNotifier};
+pub use retry::RetryPolicy;
+use retry::{Retry, RetryQueue, DEFAULT_RETRY};
+pub use sound::{play_sound, SoundConfig};
+pub use syslog::{JournalConfig, JournalNotifier, SyslogConfig, SyslogNotifier};
+pub use terminal::{BellNotifier, TmuxConfig, TmuxMode, TmuxNotifier, TtyConfig, TtyNotifier};
+pub use tts::{QuietHours, TtsConfig, TtsNotifier};
+pub use webhook::{WebhookConfig, WebhookNotifier};
+
+pub const SUMMARY: &str = "forget-me-not";
+
+// the first http(s) url of a text, e.g. a meeting link in a description
+pub(crate) fn first_url(text: &str) -> Option<&str> {
+    static URL: Lazy<Regex> = Lazy::new(|| Regex::new(r"https?://\S+").unwrap());
+    URL.find(text).map(|m| m.as_str())
+}
+
+// the title of the task's reminders
+pub(crate) fn summary(task: &Task) -> &str {
+    task.notification.summary.as_deref().unwrap_or(SUMMARY)
+}
+
+static ACTIONS: OnceCell<Mutex<Sender<TaskAction>>> = OnceCell::new();
+
+/// What the user chose on an actionable notification, or an acknowledgement
+/// received from elsewhere.
+#[derive(Debug, Clone)]
+pub enum TaskAction {
+    Done(Firing),
+    Snooze(Firing, Duration),
+    // e.g. a message on the ack topic of an mqtt notifier
+    Acknowledge(TaskID),
+}
+
+// the daemon takes the actions chosen on notifications from here; only the
+// first call gets the receiver
+pub fn action_receiver() -> Option<Receiver<TaskAction>> {
+    let (sender, receiver) = channel();
+    ACTIONS.set(Mutex::new(sender)).ok()?;
+    Some(receiver)
+}
+
+pub(crate) fn send_action(action: TaskAction) {
+    match ACTIONS.get() {
+        Some(sender) => {
+            if let Err(e) = sender.lock().expect("action lock is poisoned").send(action) {
+                error!("fail to pass on notification action: {}", e);
+            }
+        }
+        None => warn!("nobody takes notification actions, drop {:?}", action),
+    }
+}
+
+/// A single reminder going off, handed to every configured [`Notifier`].
+#[derive(Debug, Clone)]
+pub struct Firing {
+    pub task: Task,
+    // the task as it is stored, before the settings of its context and the
+    // templates went in; what a snooze brings back once the task is gone
+    pub stored: Task,
+    pub scheduled_at: OffsetDateTime,
+    pub fired_at: OffsetDateTime,
+    // whether its notification may offer Done and Snooze; not a digest's,
+    // which stands for several tasks
+    pub takes_actions: bool,
+}
+
+impl Firing {
+    pub fn new(task: Task, scheduled_at: OffsetDateTime) -> Self {
+        Self {
+            stored: task.clone(),
+            task,
+            scheduled_at,
+            fired_at: get_local_now(),
+            takes_actions: true,
+        }
+    }
+}
+
+/// A delivery backend for firings, e.g. the desktop notification server.
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn notify(&self, firing: &Firing) -> Result<()>;
+
+    // whether it shows firings on the screen; the sound of a task goes with
+    // those notifications
+    fn shows_on_desktop(&self) -> bool {
+        false
+    }
+
+    // whether its failed deliveries are tried again in the background without
+    // a `retry` policy, even if other notifiers delivered the firing; for the
+    // ones sending over the network, where failures tend to pass
+    fn retries_by_default(&self) -> bool {
+        false
+    }
+}
+
+// writes firings into the daemon log; useful on headless hosts
+pub struct LogNotifier {
+    name: String,
+}
+
+impl LogNotifier {
+    pub fn new(name: Option<String>) -> Self {
+        Self {
+            name: name.unwrap_or_else(|| "log".to_owned()),
+        }
+    }
+}
+
+impl Notifier for LogNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, firing: &Firing) -> Result<()> {
+        info!(
+            "reminder {} fired: {}",
+            firing.task.task_id, firing.task.description
+        );
+        Ok(())
+    }
+}
+
+/// One entry of the `notifiers` list in `$FMN_DIR/config.json`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NotifierConfig {
+    // defaults to the kind of the notifier
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub routing: Routing,
+    // only used when the other notifiers fail, in the order of the config
+    #[serde(default)]
+    pub fallback: bool,
+    // its failed deliveries are tried again in the background, whether other
+    // notifiers delivered the firing or not
+    pub retry: Option<RetryPolicy>,
+    #[serde(flatten)]
+    pub kind: NotifierKind,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NotifierKind {
+    Desktop(DesktopConfig),
+    Log,
+    Webhook(WebhookConfig),
+    Email(EmailConfig),
+    Tty(TtyConfig),
+    Bell(TtyConfig),
+    Tmux(TmuxConfig),
+    Ntfy(NtfyConfig),
+    Gotify(GotifyConfig),
+    Mqtt(MqttConfig),
+    Tts(TtsConfig),
+    Journal(JournalConfig),
+    Syslog(SyslogConfig),
+}
+
+impl NotifierConfig {
+    pub fn build(&self) -> Result<Arc<dyn Notifier>> {
+        let notifier: Arc<dyn Notifier> = match &self.kind {
+            NotifierKind::Desktop(config) => {
+                Arc::new(DesktopNotifier::new(self.name.clone(), config.clone())?)
+            }
+            NotifierKind::Log => Arc::new(LogNotifier::new(self.name.clone())),
+            NotifierKind::Webhook(config) => {
+                Arc::new(WebhookNotifier::new(self.name.clone(), config.clone()))
+            }
+            NotifierKind::Email(config) => {
+                Arc::new(EmailNotifier::new(self.name.clone(), config.clone())?)
+            }
+            NotifierKind::Tty(config) => {
+                Arc::new(TtyNotifier::new(self.name.clone(), config.clone()))
+            }
+            NotifierKind::Bell(config) => {
+                Arc::new(BellNotifier::new(self.name.clone(), config.clone()))
+            }
+            NotifierKind::Tmux(config) => {
+                Arc::new(TmuxNotifier::new(self.name.clone(), config.clone()))
+            }
+            NotifierKind::Ntfy(config) => {
+                Arc::new(NtfyNotifier::new(self.name.clone(), config.clone())?)
+            }
+            NotifierKind::Gotify(config) => {
+                Arc::new(GotifyNotifier::new(self.name.clone(), config.clone())?)
+            }
+            NotifierKind::Mqtt(config) => {
+                Arc::new(MqttNotifier::new(self.name.clone(), config.clone())?)
+            }
+            NotifierKind::Tts(config) => {
+                Arc::new(TtsNotifier::new(self.name.clone(), config.clone())?)
+            }
+            NotifierKind::Journal(config) => {
+                Arc::new(JournalNotifier::new(self.name.clone(), config.clone()))
+            }
+            NotifierKind::Syslog(config) => {
+                Arc::new(SyslogNotifier::new(self.name.clone(), config.clone())?)
+            }
+        };
+        Ok(notifier)
+    }
+}
+
+/// Restricts a notifier to the firings of some contexts and/or tasks; a
+/// notifier with no routing receives every firing.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Routing {
+    pub contexts: Vec<TaskContext>,
+    // task id prefixes, like `fmn rm`
+    pub tasks: Vec<TaskID>,
+}
+
+impl Routing {
+    pub fn matches(&self, task: &Task) -> bool {
+        if self.contexts.is_empty() && self.tasks.is_empty() {
+            return true;
+        }
+        self.contexts.contains(&task.context)
+            || self.tasks.iter().any(|id| task.task_id.starts_with(id))
+    }
+}
+
+/// The JSON view of a firing posted by the network notifiers.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FiringPayload {
+    pub task_id: TaskID,
+    pub description: String,
+    pub context: TaskContext,
+    #[serde(with = "time::serde::rfc3339")]
+    pub scheduled_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub fired_at: OffsetDateTime,
+}
+
+impl From<&Firing> for FiringPayload {
+    fn from(firing: &Firing) -> Self {
+        Self {
+            task_id: firing.task.task_id.clone(),
+            description: firing.task.description.clone(),
+            context: firing.task.context.clone(),
+            scheduled_at: firing.scheduled_at,
+            fired_at: firing.fired_at,
+        }
+    }
+}
+
+struct Route {
+    routing: Routing,
+    notifier: Arc<dyn Notifier>,
+    fallback: bool,
+    retry: Option<RetryPolicy>,
+}
+
+// what became of a firing handed to the notifiers
+struct Outcome {
+    record: FiringRecord,
+    delivered: bool,
+    // delivered by a notifier showing it on the desktop
+    on_desktop: bool,
+    // the routes that failed it
+    failed: Vec<usize>,
+}
+
+impl Route {
+    // notifiers named by the task take it regardless of their routing
+    fn takes(&self, task: &Task) -> bool {
+        let named = &task.notification.notifiers;
+        if named.is_empty() || self.fallback {
+            return self.routing.matches(task);
+        }
+        named.iter().any(|name| name == self.notifier.name())
+    }
+}
+
+/// The set of notifiers a daemon delivers every firing to, along with the
+/// hooks run and the history kept for every firing.
+#[derive(Clone)]
+pub struct NotifierRegistry {
+    routes: Vec<Arc<Route>>,
+    hook: HookConfig,
+    sound: SoundConfig,
+    history: Option<History>,
+    // how many times each task fired on the given day, for `{occurrence}`
+    occurrences: Arc<Mutex<HashMap<TaskID, (Date, u32)>>>,
+    digest: Option<DigestConfig>,
+    // feeds the digest worker, started by the first firing
+    digest_queue: Arc<OnceCell<Mutex<Sender<Firing>>>>,
+    retry: Option<RetryPolicy>,
+    // tries failed deliveries again, started by the first of them
+    retry_queue: Arc<OnceCell<RetryQueue>>,
+    sent: SentLog,
+}
+
+impl NotifierRegistry {
+    pub fn new() -> Self {
+        Self {
+            routes: vec![],
+            hook: HookConfig::default(),
+            sound: SoundConfig::default(),
+            history: None,
+            occurrences: Arc::new(Mutex::new(HashMap::new())),
+            digest: None,
+            digest_queue: Arc::new(OnceCell::new()),
+            retry: None,
+            retry_queue: Arc::new(OnceCell::new()),
+            sent: SentLog::default(),
+        }
+    }
+
+    pub fn with_hook(mut self, hook: HookConfig) -> Self {
+        self.hook = hook;
+        self
+    }
+
+    pub fn with_sound(mut self, sound: SoundConfig) -> Self {
+        self.sound = sound;
+        self
+    }
+
+    // coalesces firings close to each other and limits the notifications per
+    // minute
+    pub fn with_digest(mut self, digest: DigestConfig) -> Self {
+        self.digest = Some(digest);
+        self
+    }
+
+    // a firing no notifier delivered is tried again later through the
+    // notifiers that failed it
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    pub fn with_history(mut self, history: History) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    pub fn from_config(configs: &[NotifierConfig]) -> Result<Self> {
+        if configs.is_empty() {
+            return Ok(Self::default());
+        }
+        let mut registry = Self::new();
+        for config in configs {
+            registry.add_route(Route {
+                routing: config.routing.clone(),
+                notifier: config.build()?,
+                fallback: config.fallback,
+                retry: config.retry.clone(),
+            });
+        }
+        Ok(registry)
+    }
+
+    fn add_route(&mut self, route: Route) {
+        self.routes.push(Arc::new(route));
+    }
+
+    pub fn register(&mut self, notifier: Arc<dyn Notifier>) {
+        self.register_routed(notifier, Routing::default());
+    }
+
+    pub fn register_routed(&mut self, notifier: Arc<dyn Notifier>, routing: Routing) {
+        self.add_route(Route {
+            routing,
+            notifier,
+            fallback: false,
+            retry: None,
+        });
+    }
+
+    // a fallback is only used when no regular notifier delivered a firing
+    pub fn register_fallback(&mut self, notifier: Arc<dyn Notifier>, routing: Routing) {
+        self.add_route(Route {
+            routing,
+            notifier,
+            fallback: true,
+            retry: None,
+        });
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.routes.iter().map(|r| r.notifier.name()).collect()
+    }
+
+    // fans the firing out to every notifier routed to it; the fallback
+    // notifiers are tried in order only if none of them delivered it. With a
+    // digest the firing is queued and always counts as delivered.
+    pub fn notify(&self, firing: &Firing) -> Result<()> {
+        let firing = render_firing(firing, self.occurrence(firing));
+        let Some(config) = &self.digest else {
+            return self.deliver(vec![firing]);
+        };
+        let queue = self.digest_queue.get_or_init(|| {
+            let (sender, receiver) = channel();
+            // its own queue, so that the worker doesn't keep itself alive
+            let registry = Self {
+                digest: None,
+                digest_queue: Arc::new(OnceCell::new()),
+                ..self.clone()
+            };
+            let config = config.clone();
+            std::thread::spawn(move || run_digest(registry, config, receiver));
+            Mutex::new(sender)
+        });
+        queue
+            .lock()
+            .expect("digest queue lock is poisoned")
+            .send(firing)?;
+        Ok(())
+    }
+
+    // delivers firings due at the same time; a notifier routed to more than
+    // one of them gets a single digest instead
+    pub(crate) fn deliver(&self, firings: Vec<Firing>) -> Result<()> {
+        let outcomes = self.dispatch(&firings);
+        // a batch shown as one notification rings once
+        let ringing = (0..firings.len())
+            .find(|&i| outcomes[i].on_desktop && firings[i].task.get_sound().is_some());
+        let mut errors = vec![];
+        for (i, (firing, outcome)) in firings.iter().zip(outcomes).enumerate() {
+            let record = outcome.record;
+            // a notifier with a policy of its own or retried by default tries
+            // again regardless; the registry's covers the firings nobody
+            // delivered
+            let retried: Vec<usize> = outcome
+                .failed
+                .into_iter()
+                .filter(|&r| self.retries_anyway(r) || !outcome.delivered)
+                .filter(|&r| self.retry_policy(r).is_some())
+                .collect();
+            if record.deliveries.is_empty() {
+                warn!("no notifier is routed to task {}", firing.task.task_id);
+            } else if !retried.is_empty() {
+                warn!(
+                    "queue task {} for retry: {}",
+                    firing.task.task_id,
+                    record.delivery_errors().join("; ")
+                );
+            } else if !outcome.delivered {
+                errors.push(record.delivery_errors().join("; "));
+            }
+            // saved before the retries, which record how they went
+            if let Some(history) = &self.history {
+                if let Err(e) = history.record(&record) {
+                    error!("fail to save firing of task {}: {}", record.task_id, e);
+                }
+            }
+            for route in retried {
+                if let Some(policy) = self.retry_policy(route) {
+                    self.retry_queue()
+                        .retry_later(Retry::new(route, firing, policy));
+                }
+            }
+            self.play_and_run_hooks(firing, ringing == Some(i));
+        }
+        if !errors.is_empty() {
+            return Err(anyhow!("no notifier delivered: {}", errors.join("; ")));
+        }
+        Ok(())
+    }
+
+    // hands the firings to the notifiers routed to them and tells how that
+    // went for each
+    fn dispatch(&self, firings: &[Firing]) -> Vec<Outcome> {
+        let mut outcomes: Vec<Outcome> = firings
+            .iter()
+            .map(|firing| Outcome {
+                record: FiringRecord::new(firing),
+                delivered: false,
+                on_desktop: false,
+                failed: vec![],
+            })
+            .collect();
+        let (fallbacks, primaries): (Vec<_>, Vec<_>) = self
+            .routes
+            .iter()
+            .enumerate()
+            .partition(|(_, r)| r.fallback);
+        for (index, route) in primaries.into_iter().chain(fallbacks) {
+            let routed: Vec<usize> = (0..firings.len())
+                .filter(|&i| route.takes(&firings[i].task))
+                .filter(|&i| !route.fallback || !outcomes[i].delivered)
+                .collect();
+            if routed.is_empty() {
+                continue;
+            }
+            let ok = match routed[..] {
+                [i] => deliver(&route.notifier, &firings[i]),
+                _ => {
+                    let batch: Vec<&Firing> = routed.iter().map(|&i| &firings[i]).collect();
+                    deliver(&route.notifier, &digest_firing(&batch))
+                }
+            };
+            for &i in routed.iter() {
+                let outcome = &mut outcomes[i];
+                outcome.record.deliveries.push(Delivery {
+                    notifier: route.notifier.name().to_owned(),
+                    error: ok.as_ref().err().cloned(),
+                    retries: 0,
+                });
+                outcome.delivered |= ok.is_ok();
+                outcome.on_desktop |= ok.is_ok() && route.notifier.shows_on_desktop();
+                if ok.is_err() {
+                    outcome.failed.push(index);
+                }
+            }
+        }
+        outcomes
+    }
+
+    // the policy failed deliveries of a route are tried again with, if any
+    // the policy of the route, else the registry's, else the default one of
+    // a notifier retried by default
+    fn retry_policy(&self, route: usize) -> Option<&RetryPolicy> {
+        let notifier = &self.routes[route].notifier;
+        self.routes[route]
+            .retry
+            .as_ref()
+            .or(self.retry.as_ref())
+            .or(notifier.retries_by_default().then_some(&DEFAULT_RETRY))
+    }
+
+    fn retries_anyway(&self, route: usize) -> bool {
+        let route = &self.routes[route];
+        route.retry.is_some() || route.notifier.retries_by_default()
+    }
+
+    fn retry_queue(&self) -> &RetryQueue {
+        self.retry_queue.get_or_init(|| {
+            // its own queues, so that the worker doesn't keep itself alive
+            RetryQueue::start(Self {
+                digest_queue: Arc::new(OnceCell::new()),
+                retry_queue: Arc::new(OnceCell::new()),
+                ..self.clone()
+            })
+        })
+    }
+
+    /// Drops the retries queued for the task, e.g. once it is cancelled or
+    /// acknowledged.
+    pub fn forget(&self, task_id: &TaskID) {
+        if let Some(queue) = self.retry_queue.get() {
+            queue.forget(task_id);
+        }
+    }
+
+    // how long a notification has to wait for the digest's limit per minute
+    fn held_back(&self) -> Option<Duration> {
+        let max = self.digest.as_ref()?.max_per_minute?;
+        self.sent.wait(max)
+    }
+
+    // counts a notification against the limit per minute
+    fn count_sent(&self) {
+        if self
+            .digest
+            .as_ref()
+            .is_some_and(|d| d.max_per_minute.is_some())
+        {
+            self.sent.push();
+        }
+    }
+
+    // counts the firing among those of its task today; the count is picked up
+    // from the history after a restart
+    fn occurrence(&self, firing: &Firing) -> u32 {
+        let today = firing.fired_at.date();
+        let mut occurrences = self
+            .occurrences
+            .lock()
+            .expect("occurrence lock is poisoned");
+        let entry = occurrences
+            .entry(firing.task.task_id.clone())
+            .or_insert_with(|| {
+                let fired = self.history.as_ref().map_or(0, |history| {
+                    history
+                        .fired_on(&firing.task.task_id, today)
+                        .unwrap_or_else(|e| {
+                            warn!("fail to count earlier firings: {}", e);
+                            0
+                        })
+                });
+                (today, fired)
+            });
+        if entry.0 != today {
+            *entry = (today, 0);
+        }
+        entry.1 += 1;
+        entry.1
+    }
+
+    // sounds and hooks may take a while, so they run in the background and
+    // the record is completed once they are done
+    fn play_and_run_hooks(&self, firing: &Firing, ring: bool) {
+        let commands: Vec<String> = self
+            .hook
+            .command
+            .iter()
+            .chain(firing.task.get_exec())
+            .cloned()
+            .collect();
+        if commands.is_empty() && !(ring && firing.task.get_sound().is_some()) {
+            return;
+        }
+        let history = self.history.clone();
+        let timeout = Duration::from_secs(self.hook.timeout_secs);
+        let sound = self.sound.clone();
+        let firing = firing.clone();
+        std::thread::spawn(move || {
+            let task = firing.task.clone();
+            let player = std::thread::spawn(move || {
+                if ring {
+                    play_sound(&sound, &task)
+                } else {
+                    None
+                }
+            });
+            let hooks: Vec<HookRun> = commands
+                .iter()
+                .map(|command| run_hook(command, &firing, timeout))
+                .collect();
+            let sound = player.join().unwrap_or_default();
+            if let Some(history) = history {
+                let update = history.update(&firing, |record| {
+                    record.hooks = hooks;
+                    record.sound = sound;
+                });
+                if let Err(e) = update {
+                    error!("fail to save firing of task {}: {}", firing.task.task_id, e);
+                }
+            }
+        });
+    }
+}
+
+fn deliver(notifier: &Arc<dyn Notifier>, firing: &Firing) -> std::result::Result<(), String> {
+    notifier.notify(firing).map_err(|e| {
+        error!(
+            "notifier {} fails to deliver task {}: {}",
+            notifier.name(),
+            firing.task.task_id,
+            e
+        );
+        e.to_string()
+    })
+}
+
+impl Default for NotifierRegistry {
+    // a registry with only the desktop notifier
+    fn default() -> Self {
+        let mut registry = Self::new();
+        let desktop = DesktopNotifier::new(None, DesktopConfig::default())
+            .expect("the default desktop config is valid");
+        registry.register(Arc::new(desktop));
+        registry
+    }
+}
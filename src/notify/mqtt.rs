@@ -0,0 +1,210 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use rumqttc::{Client, Connection, Event, MqttOptions, Packet, QoS};
+use serde::Deserialize;
+
+use super::email::read_login;
+use super::{send_action, Firing, FiringPayload, Notifier, TaskAction};
+use crate::task_manager::TaskID;
+
+// waited before connecting again to an unreachable broker
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    // defaults to fmn-<pid>
+    pub client_id: Option<String>,
+    // {context} and {task_id} are filled in; tasks without a context use
+    // "default"
+    #[serde(default = "default_topic")]
+    pub topic: String,
+    // 0, 1 or 2
+    #[serde(default = "default_qos")]
+    pub qos: u8,
+    #[serde(default)]
+    pub retain: bool,
+    // a file with the username on the first line and the password on the second
+    pub credentials_file: Option<String>,
+    // messages here acknowledge the task whose id they hold, either as plain
+    // text or in the task_id field of a json object; wildcards are allowed,
+    // but must not match the topics firings are published to
+    pub ack_topic: Option<String>,
+    #[serde(default = "default_keep_alive_secs")]
+    pub keep_alive_secs: u64,
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_topic() -> String {
+    "fmn/{context}/{task_id}".to_owned()
+}
+
+fn default_qos() -> u8 {
+    1
+}
+
+fn default_keep_alive_secs() -> u64 {
+    30
+}
+
+fn qos(level: u8) -> Result<QoS> {
+    match level {
+        0 => Ok(QoS::AtMostOnce),
+        1 => Ok(QoS::AtLeastOnce),
+        2 => Ok(QoS::ExactlyOnce),
+        _ => Err(anyhow!("invalid mqtt qos {level}, expect 0, 1 or 2")),
+    }
+}
+
+// a topic level can't hold separators or wildcards
+fn topic_level(value: &str) -> String {
+    value.replace(['/', '+', '#'], "_")
+}
+
+/// Publishes every firing as a json `FiringPayload` to an MQTT broker, and
+/// optionally takes acknowledgements from an ack topic.
+pub struct MqttNotifier {
+    name: String,
+    config: MqttConfig,
+    qos: QoS,
+    broker: String,
+    // the connection thread only holds a weak reference, so it stops once
+    // the notifier is dropped
+    client: Arc<Client>,
+    connected: Arc<AtomicBool>,
+}
+
+impl MqttNotifier {
+    pub fn new(name: Option<String>, config: MqttConfig) -> Result<Self> {
+        let qos = qos(config.qos)?;
+        let client_id = config
+            .client_id
+            .clone()
+            .unwrap_or_else(|| format!("fmn-{}", std::process::id()));
+        let mut options = MqttOptions::new(client_id, &config.host, config.port);
+        options.set_keep_alive(Duration::from_secs(config.keep_alive_secs));
+        if let Some(path) = &config.credentials_file {
+            let (username, password) = read_login(path)?;
+            options.set_credentials(username, password);
+        }
+        let (client, connection) = Client::new(options, 16);
+        let client = Arc::new(client);
+        let connected = Arc::new(AtomicBool::new(false));
+        let broker = format!("{}:{}", config.host, config.port);
+        let worker = Worker {
+            client: Arc::downgrade(&client),
+            connected: connected.clone(),
+            ack_topic: config.ack_topic.clone(),
+            broker: broker.clone(),
+        };
+        std::thread::spawn(move || worker.run(connection));
+        Ok(Self {
+            name: name.unwrap_or_else(|| "mqtt".to_owned()),
+            config,
+            qos,
+            broker,
+            client,
+            connected,
+        })
+    }
+
+    fn topic(&self, firing: &Firing) -> String {
+        let task = &firing.task;
+        let context = match task.context.as_str() {
+            "" => "default",
+            context => context,
+        };
+        self.config
+            .topic
+            .replace("{context}", &topic_level(context))
+            .replace("{task_id}", &topic_level(&task.task_id))
+    }
+}
+
+impl Notifier for MqttNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, firing: &Firing) -> Result<()> {
+        if !self.connected.load(Ordering::SeqCst) {
+            return Err(anyhow!("not connected to mqtt broker {}", self.broker));
+        }
+        let payload = serde_json::to_vec(&FiringPayload::from(firing))?;
+        self.client
+            .try_publish(self.topic(firing), self.qos, self.config.retain, payload)
+            .map_err(|e| anyhow!("fail to publish to mqtt broker {}: {}", self.broker, e))
+    }
+
+    fn retries_by_default(&self) -> bool {
+        true
+    }
+}
+
+// drives the connection: reconnects, subscribes to the ack topic on every
+// connect and passes on the acknowledgements
+struct Worker {
+    client: Weak<Client>,
+    connected: Arc<AtomicBool>,
+    ack_topic: Option<String>,
+    broker: String,
+}
+
+impl Worker {
+    fn run(self, mut connection: Connection) {
+        for event in connection.iter() {
+            match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("connect to mqtt broker {}", self.broker);
+                    self.connected.store(true, Ordering::SeqCst);
+                    self.subscribe();
+                }
+                // only the ack topic is subscribed to
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    match ack_task_id(&publish.payload) {
+                        Some(task_id) => {
+                            info!("task {} is acknowledged on {}", task_id, publish.topic);
+                            send_action(TaskAction::Acknowledge(task_id));
+                        }
+                        None => warn!("ignore mqtt message on {} without a task id", publish.topic),
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    if self.connected.swap(false, Ordering::SeqCst) {
+                        warn!("lose mqtt broker {}: {}", self.broker, e);
+                    }
+                    std::thread::sleep(RECONNECT_DELAY);
+                }
+            }
+        }
+    }
+
+    fn subscribe(&self) {
+        let (Some(topic), Some(client)) = (&self.ack_topic, self.client.upgrade()) else {
+            return;
+        };
+        if let Err(e) = client.try_subscribe(topic.as_str(), QoS::AtLeastOnce) {
+            error!("fail to subscribe to mqtt topic {}: {}", topic, e);
+        }
+    }
+}
+
+// "<task id>" or {"task_id": "<task id>", ...}
+fn ack_task_id(payload: &[u8]) -> Option<TaskID> {
+    let text = std::str::from_utf8(payload).ok()?.trim();
+    if text.starts_with('{') {
+        let value: serde_json::Value = serde_json::from_str(text).ok()?;
+        return value["task_id"].as_str().map(str::to_owned);
+    }
+    (!text.is_empty()).then(|| text.to_owned())
+}
@@ -0,0 +1,275 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use log::info;
+use serde::Deserialize;
+use serde_json::json;
+use ureq::{Agent, AgentBuilder};
+
+use super::webhook::{default_timeout_secs, sent};
+use super::{first_url, summary, Firing, Notifier};
+use crate::task_manager::{Task, Urgency};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NtfyConfig {
+    // e.g. https://ntfy.sh
+    pub server: String,
+    pub topic: String,
+    // a file holding an access token of the server
+    pub token_file: Option<String>,
+    // sent along with the context of the task
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // opened when the notification is tapped; the first url of the
+    // description if not given
+    pub click: Option<String>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GotifyConfig {
+    // e.g. https://gotify.example.com
+    pub server: String,
+    // a file holding the token of the gotify application
+    pub token_file: String,
+    pub click: Option<String>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn read_token(path: &str) -> Result<String> {
+    let token = std::fs::read_to_string(path).context(format!("fail to read token {path}"))?;
+    Ok(token.trim().to_owned())
+}
+
+fn agent(timeout_secs: u64) -> Agent {
+    AgentBuilder::new()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()
+}
+
+fn click_url<'a>(click: &'a Option<String>, task: &'a Task) -> Option<&'a str> {
+    click.as_deref().or_else(|| first_url(&task.description))
+}
+
+fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+// http headers are ascii; ntfy decodes the rest from rfc 2047
+fn header_value(value: &str) -> String {
+    if value.is_ascii() && !value.contains('\n') {
+        return value.to_owned();
+    }
+    format!("=?UTF-8?B?{}?=", STANDARD.encode(value))
+}
+
+/// Publishes firings to an ntfy topic; an `image_path` of the task is
+/// uploaded as an attachment.
+pub struct NtfyNotifier {
+    name: String,
+    config: NtfyConfig,
+    token: Option<String>,
+    agent: Agent,
+}
+
+impl NtfyNotifier {
+    pub fn new(name: Option<String>, config: NtfyConfig) -> Result<Self> {
+        let token = config.token_file.as_deref().map(read_token).transpose()?;
+        Ok(Self {
+            name: name.unwrap_or_else(|| "ntfy".to_owned()),
+            agent: agent(config.timeout_secs),
+            config,
+            token,
+        })
+    }
+
+    fn priority(task: &Task) -> u8 {
+        match task.notification.urgency.unwrap_or_default() {
+            Urgency::Low => 2,
+            Urgency::Normal => 3,
+            Urgency::Critical => 5,
+        }
+    }
+
+    fn tags(&self, task: &Task) -> Vec<String> {
+        let mut tags = self.config.tags.clone();
+        if !task.context.is_empty() {
+            tags.push(task.context.clone());
+        }
+        tags
+    }
+
+    // the message as json, attaching the image by its url if it has one
+    fn publish(&self, firing: &Firing) -> std::result::Result<(), Box<ureq::Error>> {
+        let task = &firing.task;
+        let mut message = json!({
+            "topic": self.config.topic,
+            "title": summary(task),
+            "message": task.description,
+            "priority": Self::priority(task),
+            "tags": self.tags(task),
+        });
+        if let Some(click) = click_url(&self.config.click, task) {
+            message["click"] = json!(click);
+        }
+        if let Some(image) = task.get_image().filter(|path| is_url(path)) {
+            message["attach"] = json!(image);
+        }
+        if let Some(icon) = task
+            .notification
+            .icon
+            .as_deref()
+            .filter(|path| is_url(path))
+        {
+            message["icon"] = json!(icon);
+        }
+        let mut request = self
+            .agent
+            .post(self.config.server.trim_end_matches('/'))
+            .set("Content-Type", "application/json");
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {token}"));
+        }
+        request
+            .send_string(&message.to_string())
+            .map_err(Box::new)?;
+        Ok(())
+    }
+
+    // the image file as the body, with the message in headers
+    fn upload(
+        &self,
+        firing: &Firing,
+        image: &[u8],
+        filename: &str,
+    ) -> std::result::Result<(), Box<ureq::Error>> {
+        let task = &firing.task;
+        let url = format!(
+            "{}/{}",
+            self.config.server.trim_end_matches('/'),
+            self.config.topic
+        );
+        let mut request = self
+            .agent
+            .put(&url)
+            .set("X-Title", &header_value(summary(task)))
+            .set("X-Message", &header_value(&task.description))
+            .set("X-Priority", &Self::priority(task).to_string())
+            .set("X-Filename", &header_value(filename));
+        let tags = self.tags(task);
+        if !tags.is_empty() {
+            request = request.set("X-Tags", &header_value(&tags.join(",")));
+        }
+        if let Some(click) = click_url(&self.config.click, task) {
+            request = request.set("X-Click", click);
+        }
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {token}"));
+        }
+        request.send_bytes(image).map_err(Box::new)?;
+        Ok(())
+    }
+}
+
+impl Notifier for NtfyNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, firing: &Firing) -> Result<()> {
+        let server = &self.config.server;
+        match firing.task.get_image().filter(|path| !is_url(path)) {
+            Some(path) => {
+                let image = std::fs::read(path).context(format!("fail to read image {path}"))?;
+                let filename = Path::new(path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                sent(server, self.upload(firing, &image, &filename))
+            }
+            None => sent(server, self.publish(firing)),
+        }
+    }
+
+    fn retries_by_default(&self) -> bool {
+        true
+    }
+}
+
+/// Pushes firings as messages of a Gotify application.
+pub struct GotifyNotifier {
+    name: String,
+    config: GotifyConfig,
+    token: String,
+    agent: Agent,
+}
+
+impl GotifyNotifier {
+    pub fn new(name: Option<String>, config: GotifyConfig) -> Result<Self> {
+        let token = read_token(&config.token_file)?;
+        Ok(Self {
+            name: name.unwrap_or_else(|| "gotify".to_owned()),
+            agent: agent(config.timeout_secs),
+            config,
+            token,
+        })
+    }
+
+    fn priority(task: &Task) -> u8 {
+        match task.notification.urgency.unwrap_or_default() {
+            Urgency::Low => 2,
+            Urgency::Normal => 5,
+            Urgency::Critical => 8,
+        }
+    }
+
+    fn post(&self, firing: &Firing) -> std::result::Result<(), Box<ureq::Error>> {
+        let task = &firing.task;
+        let mut notification = json!({});
+        if let Some(click) = click_url(&self.config.click, task) {
+            notification["click"] = json!({ "url": click });
+        }
+        match task.get_image() {
+            Some(image) if is_url(image) => notification["bigImageUrl"] = json!(image),
+            // gotify only shows images it can download
+            Some(image) => info!("gotify can't attach the local image {}", image),
+            None => {}
+        }
+        let message = json!({
+            "title": summary(task),
+            "message": task.description,
+            "priority": Self::priority(task),
+            "extras": {
+                "client::display": { "contentType": "text/plain" },
+                "client::notification": notification,
+            },
+        });
+        let url = format!("{}/message", self.config.server.trim_end_matches('/'));
+        self.agent
+            .post(&url)
+            .set("Content-Type", "application/json")
+            .set("X-Gotify-Key", &self.token)
+            .send_string(&message.to_string())
+            .map_err(Box::new)?;
+        Ok(())
+    }
+}
+
+impl Notifier for GotifyNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, firing: &Firing) -> Result<()> {
+        sent(&self.config.server, self.post(firing))
+    }
+
+    fn retries_by_default(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+use serde::Deserialize;
+
+use super::{digest_firing, Firing, NotifierRegistry};
+use crate::comm::parse_duration;
+use crate::task_manager::{ClockType, TaskID};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    // delay before the first retry, doubled after every failed one
+    pub initial_secs: u64,
+    pub max_secs: u64,
+    // the firing is dropped once this many retries failed
+    pub max_attempts: u32,
+}
+
+// also the policy of the notifiers retried by default
+pub(super) const DEFAULT_RETRY: RetryPolicy = RetryPolicy {
+    initial_secs: 30,
+    max_secs: 3600,
+    max_attempts: 20,
+};
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        DEFAULT_RETRY
+    }
+}
+
+impl RetryPolicy {
+    fn delay(&self, attempt: u32) -> Duration {
+        let secs = self
+            .initial_secs
+            .saturating_mul(2_u64.saturating_pow(attempt))
+            .min(self.max_secs);
+        Duration::from_secs(secs)
+    }
+}
+
+// a firing one notifier of the registry failed, waiting to be tried again
+pub(super) struct Retry {
+    // the index of the notifier's route in the registry
+    route: usize,
+    firing: Firing,
+    // the retries so far
+    attempt: u32,
+    due: Instant,
+    // by then the task fired again, which makes this firing pointless
+    stale_at: Option<Instant>,
+}
+
+impl Retry {
+    pub(super) fn new(route: usize, firing: &Firing, policy: &RetryPolicy) -> Self {
+        let now = Instant::now();
+        Self {
+            route,
+            firing: firing.clone(),
+            attempt: 0,
+            due: now + policy.delay(0),
+            stale_at: period(&firing.task.clock_type).map(|period| now + period),
+        }
+    }
+}
+
+// how often a task fires; None for the ones firing once
+fn period(clock_type: &ClockType) -> Option<Duration> {
+    match clock_type {
+        ClockType::Period(period) => parse_duration(period).ok(),
+        ClockType::OncePerDay(..) => Some(Duration::from_secs(24 * 3600)),
+        ClockType::Once(_) => None,
+    }
+}
+
+enum Message {
+    Retry(Box<Retry>),
+    // the task is cancelled or acknowledged
+    Forget(TaskID),
+}
+
+/// Tries failed deliveries again in the background, each through the
+/// notifier that failed it, under the digest's limit of notifications per
+/// minute. The outcome ends up in the history.
+pub(super) struct RetryQueue {
+    sender: Mutex<Sender<Message>>,
+}
+
+impl RetryQueue {
+    pub(super) fn start(registry: NotifierRegistry) -> Self {
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || run_retries(registry, receiver));
+        Self {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    pub(super) fn retry_later(&self, retry: Retry) {
+        self.send(Message::Retry(Box::new(retry)));
+    }
+
+    pub(super) fn forget(&self, task_id: &TaskID) {
+        self.send(Message::Forget(task_id.clone()));
+    }
+
+    fn send(&self, message: Message) {
+        let sender = self.sender.lock().expect("retry queue lock is poisoned");
+        if let Err(e) = sender.send(message) {
+            error!("fail to reach the retry queue: {}", e);
+        }
+    }
+}
+
+fn run_retries(registry: NotifierRegistry, receiver: Receiver<Message>) {
+    let mut pending: Vec<Retry> = vec![];
+    loop {
+        let timeout = pending
+            .iter()
+            .map(|r| r.due.saturating_duration_since(Instant::now()))
+            .min()
+            .unwrap_or(Duration::from_secs(3600));
+        match receiver.recv_timeout(timeout) {
+            Ok(Message::Retry(retry)) => pending.push(*retry),
+            Ok(Message::Forget(task_id)) => {
+                let queued = pending.len();
+                pending.retain(|r| r.firing.task.task_id != task_id);
+                if pending.len() < queued {
+                    info!("drop the retries of task {}", task_id);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            // the registry is dropped; give the queue a last chance
+            Err(RecvTimeoutError::Disconnected) if pending.is_empty() => return,
+            Err(RecvTimeoutError::Disconnected) => std::thread::sleep(timeout),
+        }
+        let now = Instant::now();
+        let (due, waiting): (Vec<_>, Vec<_>) = pending.drain(..).partition(|r| r.due <= now);
+        pending = waiting;
+        // the retries due together on a notifier make a digest
+        let mut by_route: BTreeMap<usize, Vec<Retry>> = BTreeMap::new();
+        for retry in due {
+            if retry.stale_at.is_some_and(|at| at <= now) {
+                warn!(
+                    "give up task {}: it fired again meanwhile",
+                    retry.firing.task.task_id
+                );
+                settle(&registry, &retry, Some("gave up: the task fired again"));
+                continue;
+            }
+            by_route.entry(retry.route).or_default().push(retry);
+        }
+        for (route, retries) in by_route {
+            if let Some(wait) = registry.held_back() {
+                info!(
+                    "hold back {} retries for the limit of notifications per minute",
+                    retries.len()
+                );
+                pending.extend(retries.into_iter().map(|mut r| {
+                    r.due = now + wait;
+                    r
+                }));
+                continue;
+            }
+            pending.extend(redeliver(&registry, route, retries));
+        }
+    }
+}
+
+// tries the retries of a notifier once more and returns those to try again
+fn redeliver(registry: &NotifierRegistry, route: usize, retries: Vec<Retry>) -> Vec<Retry> {
+    let notifier = &registry.routes[route].notifier;
+    let Some(policy) = registry.retry_policy(route) else {
+        return vec![];
+    };
+    let firing = match &retries[..] {
+        [retry] => retry.firing.clone(),
+        _ => digest_firing(&retries.iter().map(|r| &r.firing).collect::<Vec<_>>()),
+    };
+    registry.count_sent();
+    let e = match notifier.notify(&firing) {
+        Ok(()) => {
+            for retry in retries.iter() {
+                info!(
+                    "notifier {} delivers task {} on retry {}",
+                    notifier.name(),
+                    retry.firing.task.task_id,
+                    retry.attempt + 1
+                );
+                settle(registry, retry, None);
+            }
+            return vec![];
+        }
+        Err(e) => e,
+    };
+    let mut again = vec![];
+    for mut retry in retries {
+        retry.attempt += 1;
+        let task_id = &retry.firing.task.task_id;
+        if retry.attempt >= policy.max_attempts {
+            error!(
+                "notifier {} gives up task {} after {} retries: {}",
+                notifier.name(),
+                task_id,
+                retry.attempt,
+                e
+            );
+            settle(registry, &retry, Some(&format!("gave up: {e}")));
+            continue;
+        }
+        warn!(
+            "notifier {} fails to deliver task {} on retry {}: {}",
+            notifier.name(),
+            task_id,
+            retry.attempt,
+            e
+        );
+        retry.due = Instant::now() + policy.delay(retry.attempt);
+        again.push(retry);
+    }
+    again
+}
+
+// records how the delivery of the retry ended
+fn settle(registry: &NotifierRegistry, retry: &Retry, error: Option<&str>) {
+    let notifier = registry.routes[retry.route].notifier.name();
+    let Some(history) = &registry.history else {
+        return;
+    };
+    let update = history.update(&retry.firing, |record| {
+        for delivery in record.deliveries.iter_mut() {
+            if delivery.notifier == notifier {
+                delivery.error = error.map(str::to_owned);
+                delivery.retries = retry.attempt + u32::from(error.is_none());
+            }
+        }
+    });
+    if let Err(e) = update {
+        error!(
+            "fail to record the retries of task {}: {}",
+            retry.firing.task.task_id, e
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use serde::Deserialize;
+
+use super::hook::{capture, wait_timeout};
+use crate::history::SoundRun;
+use crate::task_manager::Task;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SoundConfig {
+    // tried in order until one plays the sound: a known player (pw-play,
+    // paplay, aplay, afplay) or a command line with {path} and {volume}
+    // (0-100) placeholders, e.g. "mpv --no-video --volume={volume} {path}"
+    pub players: Vec<String>,
+    // a player still playing after this long is killed
+    pub timeout_secs: u64,
+}
+
+impl Default for SoundConfig {
+    fn default() -> Self {
+        #[cfg(target_os = "macos")]
+        let players = vec!["afplay".to_owned()];
+        #[cfg(not(target_os = "macos"))]
+        let players = vec![
+            "pw-play".to_owned(),
+            "paplay".to_owned(),
+            "aplay".to_owned(),
+        ];
+        Self {
+            players,
+            timeout_secs: 60,
+        }
+    }
+}
+
+// the arguments a player takes to play `path` at `volume` percent
+fn player_args(player: &str, path: &str, volume: u8) -> Vec<String> {
+    let ratio = f32::from(volume) / 100.0;
+    match player {
+        "pw-play" => vec![format!("--volume={ratio}"), path.to_owned()],
+        "paplay" => vec![
+            format!("--volume={}", (65536.0 * ratio) as u32),
+            path.to_owned(),
+        ],
+        "afplay" => vec!["-v".to_owned(), ratio.to_string(), path.to_owned()],
+        // aplay can't change the volume
+        _ => vec!["-q".to_owned(), path.to_owned()],
+    }
+}
+
+// the program and arguments of a player entry
+fn command_line(player: &str, path: &str, volume: u8) -> (String, Vec<String>) {
+    if matches!(player, "pw-play" | "paplay" | "aplay" | "afplay") {
+        return (player.to_owned(), player_args(player, path, volume));
+    }
+    let mut words = player.split_whitespace().map(|word| {
+        word.replace("{path}", path)
+            .replace("{volume}", &volume.to_string())
+    });
+    let program = words.next().unwrap_or_default();
+    (program, words.collect())
+}
+
+// whether `program` is an existing path or found in $PATH
+pub(super) fn available(program: &str) -> bool {
+    if program.contains('/') {
+        return Path::new(program).is_file();
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+/// Plays the task's sound with the first available player that succeeds,
+/// `repeat` times in a row, and reports how that went.
+pub fn play_sound(config: &SoundConfig, task: &Task) -> Option<SoundRun> {
+    let path = task.get_sound()?;
+    let volume = task.notification.volume.unwrap_or(100).min(100);
+    let repeat = task.notification.repeat.unwrap_or(1).max(1);
+    let mut run = SoundRun {
+        path: path.to_owned(),
+        ..Default::default()
+    };
+    let mut errors = vec![];
+    for player in config.players.iter() {
+        let (program, args) = command_line(player, path, volume);
+        if !available(&program) {
+            continue;
+        }
+        match play(&program, &args, repeat, config.timeout_secs) {
+            Ok(()) => {
+                info!("play sound {} with {} {} times", path, program, repeat);
+                run.player = Some(program);
+                run.plays = repeat;
+                return Some(run);
+            }
+            Err(e) => {
+                warn!("fail to play sound {} with {}: {}", path, program, e);
+                errors.push(format!("{program}: {e}"));
+            }
+        }
+    }
+    run.error = Some(if errors.is_empty() {
+        format!("none of the players {:?} is available", config.players)
+    } else {
+        errors.join("; ")
+    });
+    Some(run)
+}
+
+fn play(program: &str, args: &[String], repeat: u32, timeout_secs: u64) -> Result<()> {
+    let timeout = Duration::from_secs(timeout_secs);
+    for _ in 0..repeat {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context(format!("fail to run {program}"))?;
+        let stderr = capture(child.stderr.take());
+        match wait_timeout(&mut child, timeout)? {
+            Some(status) if status.success() => {}
+            Some(status) => return Err(anyhow!("{} ({})", status, stderr.output().trim())),
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(anyhow!("killed after {:?}", timeout));
+            }
+        }
+    }
+    Ok(())
+}
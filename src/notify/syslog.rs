@@ -0,0 +1,198 @@
+use std::os::unix::net::UnixDatagram;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use time::format_description::well_known::Rfc3339;
+
+use super::{summary, Firing, Notifier};
+use crate::task_manager::{Task, Urgency};
+
+// the enterprise number structured data of rfc 5424 is named after; 32473 is
+// reserved for documentation
+const SD_ID: &str = "fmn@32473";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct JournalConfig {
+    pub socket: String,
+    // SYSLOG_IDENTIFIER of the entries
+    pub identifier: String,
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        Self {
+            socket: "/run/systemd/journal/socket".to_owned(),
+            identifier: "fmn".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SyslogConfig {
+    pub socket: String,
+    pub app_name: String,
+    // user, daemon or local0 to local7
+    pub facility: String,
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        Self {
+            socket: "/dev/log".to_owned(),
+            app_name: "fmn".to_owned(),
+            facility: "user".to_owned(),
+        }
+    }
+}
+
+// the syslog severity of a task: informational, notice or critical
+fn severity(task: &Task) -> u8 {
+    match task.notification.urgency.unwrap_or_default() {
+        Urgency::Low => 6,
+        Urgency::Normal => 5,
+        Urgency::Critical => 2,
+    }
+}
+
+fn facility(name: &str) -> Result<u8> {
+    match name {
+        "user" => Ok(1),
+        "daemon" => Ok(3),
+        _ => match name.strip_prefix("local").map(str::parse::<u8>) {
+            Some(Ok(n)) if n <= 7 => Ok(16 + n),
+            _ => Err(anyhow!(
+                "unknown syslog facility {name}, expect user, daemon or local0 to local7"
+            )),
+        },
+    }
+}
+
+fn send(socket: &str, datagram: &[u8]) -> Result<()> {
+    let sender = UnixDatagram::unbound().context("fail to create unix datagram socket")?;
+    sender
+        .send_to(datagram, socket)
+        .context(format!("fail to write to {socket}"))?;
+    Ok(())
+}
+
+/// Writes every firing as a structured systemd journal entry, carrying the
+/// task in `FMN_*` fields.
+pub struct JournalNotifier {
+    name: String,
+    config: JournalConfig,
+}
+
+impl JournalNotifier {
+    pub fn new(name: Option<String>, config: JournalConfig) -> Self {
+        Self {
+            name: name.unwrap_or_else(|| "journal".to_owned()),
+            config,
+        }
+    }
+
+    // the native journal protocol: KEY=value lines, or for values with a
+    // newline the key, a newline, the length as a little endian u64 and the
+    // value
+    fn entry(&self, firing: &Firing) -> Vec<u8> {
+        let task = &firing.task;
+        let fields = [
+            (
+                "MESSAGE",
+                format!("{}: {}", summary(task), task.description),
+            ),
+            ("PRIORITY", severity(task).to_string()),
+            ("SYSLOG_IDENTIFIER", self.config.identifier.clone()),
+            ("FMN_TASK_ID", task.task_id.clone()),
+            ("FMN_CONTEXT", task.context.clone()),
+            ("FMN_DESCRIPTION", task.description.clone()),
+            ("FMN_CLOCK", task.clock_type.to_string()),
+            ("FMN_SCHEDULED_AT", firing.scheduled_at.to_string()),
+            ("FMN_FIRED_AT", firing.fired_at.to_string()),
+        ];
+        let mut entry = vec![];
+        for (key, value) in fields {
+            entry.extend_from_slice(key.as_bytes());
+            if value.contains('\n') {
+                entry.push(b'\n');
+                entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            } else {
+                entry.push(b'=');
+            }
+            entry.extend_from_slice(value.as_bytes());
+            entry.push(b'\n');
+        }
+        entry
+    }
+}
+
+impl Notifier for JournalNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, firing: &Firing) -> Result<()> {
+        send(&self.config.socket, &self.entry(firing))
+    }
+}
+
+/// Sends every firing as an RFC 5424 message to the local syslog socket, with
+/// the task in its structured data.
+pub struct SyslogNotifier {
+    name: String,
+    config: SyslogConfig,
+    facility: u8,
+    hostname: String,
+}
+
+impl SyslogNotifier {
+    pub fn new(name: Option<String>, config: SyslogConfig) -> Result<Self> {
+        let facility = facility(&config.facility)?;
+        let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .map(|name| name.trim().to_owned())
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .unwrap_or_else(|_| "-".to_owned());
+        Ok(Self {
+            name: name.unwrap_or_else(|| "syslog".to_owned()),
+            config,
+            facility,
+            hostname,
+        })
+    }
+
+    fn message(&self, firing: &Firing) -> Result<String> {
+        let task = &firing.task;
+        let priority = self.facility * 8 + severity(task);
+        let timestamp = firing.fired_at.format(&Rfc3339)?;
+        Ok(format!(
+            "<{priority}>1 {timestamp} {} {} {} firing [{SD_ID} task_id=\"{}\" context=\"{}\" clock=\"{}\"] {}: {}",
+            self.hostname,
+            self.config.app_name,
+            std::process::id(),
+            param_value(&task.task_id),
+            param_value(&task.context),
+            param_value(&task.clock_type.to_string()),
+            summary(task),
+            task.description.replace('\n', " "),
+        ))
+    }
+}
+
+// rfc 5424 param values escape ", \ and ]
+fn param_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(']', "\\]")
+}
+
+impl Notifier for SyslogNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, firing: &Firing) -> Result<()> {
+        send(&self.config.socket, self.message(firing)?.as_bytes())
+    }
+}
@@ -0,0 +1,246 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use serde::Deserialize;
+use time::macros::format_description;
+
+use super::{summary, Firing, Notifier};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TtyConfig {
+    // whose terminals to write to, $USER by default
+    pub user: Option<String>,
+    // explicit terminal devices instead of the ones `who` reports
+    pub ttys: Vec<String>,
+    // ring the bell along with the message
+    pub bell: bool,
+}
+
+impl TtyConfig {
+    fn ttys(&self) -> Result<Vec<String>> {
+        if !self.ttys.is_empty() {
+            return Ok(self.ttys.clone());
+        }
+        let user = match &self.user {
+            Some(user) => user.clone(),
+            None => std::env::var("USER").context("fail to find out the current user")?,
+        };
+        user_ttys(&user)
+    }
+}
+
+// the terminals `user` is logged in on, according to `who`
+fn user_ttys(user: &str) -> Result<Vec<String>> {
+    let output = Command::new("who").output().context("fail to run who")?;
+    let ttys = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some(name), Some(tty)) if name == user => Some(format!("/dev/{tty}")),
+                _ => None,
+            }
+        })
+        .collect::<Vec<String>>();
+    if ttys.is_empty() {
+        return Err(anyhow!("user {user} isn't logged in on any terminal"));
+    }
+    Ok(ttys)
+}
+
+// writes `text` to every tty, succeeding if any of them took it
+fn write_ttys(ttys: &[String], text: &str) -> Result<()> {
+    let mut errors = vec![];
+    for tty in ttys {
+        let written = OpenOptions::new()
+            .append(true)
+            .open(tty)
+            .and_then(|mut f| f.write_all(text.as_bytes()));
+        if let Err(e) = written {
+            warn!("fail to write to terminal {}: {}", tty, e);
+            errors.push(format!("{tty}: {e}"));
+        }
+    }
+    if errors.len() == ttys.len() {
+        return Err(anyhow!("fail to write to terminals: {}", errors.join("; ")));
+    }
+    Ok(())
+}
+
+/// Writes the reminder to the user's terminals, like `wall` does.
+pub struct TtyNotifier {
+    name: String,
+    config: TtyConfig,
+}
+
+impl TtyNotifier {
+    pub fn new(name: Option<String>, config: TtyConfig) -> Self {
+        Self {
+            name: name.unwrap_or_else(|| "tty".to_owned()),
+            config,
+        }
+    }
+}
+
+impl Notifier for TtyNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, firing: &Firing) -> Result<()> {
+        let at = firing
+            .fired_at
+            .format(format_description!("[hour]:[minute]"))?;
+        let bell = if self.config.bell { "\x07" } else { "" };
+        let text = format!(
+            "\r\n{bell}Reminder from {} ({at}):\r\n    {}\r\n",
+            printable(summary(&firing.task)).replace("\r\n    ", " "),
+            printable(&firing.task.description)
+        );
+        write_ttys(&self.config.ttys()?, &text)
+    }
+}
+
+// the text with control characters shown the way `wall` does, e.g. ESC as
+// ^[, so that a description can't drive the terminals it is written to;
+// lines after the first are indented
+fn printable(text: &str) -> String {
+    let mut printable = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' => printable.push_str("\r\n    "),
+            '\t' => printable.push(c),
+            '\x00'..='\x1f' => {
+                printable.push('^');
+                printable.push(char::from(c as u8 + b'@'));
+            }
+            '\x7f' => printable.push_str("^?"),
+            c if c.is_control() => printable.push('?'),
+            c => printable.push(c),
+        }
+    }
+    printable
+}
+
+/// Only rings the bell of the user's terminals.
+pub struct BellNotifier {
+    name: String,
+    config: TtyConfig,
+}
+
+impl BellNotifier {
+    pub fn new(name: Option<String>, config: TtyConfig) -> Self {
+        Self {
+            name: name.unwrap_or_else(|| "bell".to_owned()),
+            config,
+        }
+    }
+}
+
+impl Notifier for BellNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, _firing: &Firing) -> Result<()> {
+        write_ttys(&self.config.ttys()?, "\x07")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TmuxMode {
+    // a message shown on every attached client
+    #[default]
+    Display,
+    // sets the global user option `@fmn_reminder` to show in `status-right`
+    Status,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TmuxConfig {
+    pub mode: TmuxMode,
+    // the tmux binary
+    pub command: String,
+    // the server socket (`tmux -S`), the default server if not given
+    pub socket: Option<String>,
+    pub display_ms: u64,
+}
+
+impl Default for TmuxConfig {
+    fn default() -> Self {
+        Self {
+            mode: TmuxMode::default(),
+            command: "tmux".to_owned(),
+            socket: None,
+            display_ms: 5000,
+        }
+    }
+}
+
+pub struct TmuxNotifier {
+    name: String,
+    config: TmuxConfig,
+}
+
+impl TmuxNotifier {
+    pub fn new(name: Option<String>, config: TmuxConfig) -> Self {
+        Self {
+            name: name.unwrap_or_else(|| "tmux".to_owned()),
+            config,
+        }
+    }
+
+    fn tmux(&self, args: &[&str]) -> Result<String> {
+        let mut command = Command::new(&self.config.command);
+        if let Some(socket) = &self.config.socket {
+            command.arg("-S").arg(socket);
+        }
+        let output = command
+            .args(args)
+            .output()
+            .context(format!("fail to run {}", self.config.command))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "tmux {} fails: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl Notifier for TmuxNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, firing: &Firing) -> Result<()> {
+        // tmux expands #() and #{} in messages
+        let text =
+            format!("{}: {}", summary(&firing.task), firing.task.description).replace('#', "##");
+        match self.config.mode {
+            TmuxMode::Display => {
+                let clients = self.tmux(&["list-clients", "-F", "#{client_name}"])?;
+                let clients: Vec<&str> = clients.lines().collect();
+                if clients.is_empty() {
+                    return Err(anyhow!("no tmux client is attached"));
+                }
+                let display_ms = self.config.display_ms.to_string();
+                for client in clients {
+                    self.tmux(&["display-message", "-c", client, "-d", &display_ms, &text])?;
+                }
+            }
+            TmuxMode::Status => {
+                self.tmux(&["set-option", "-g", "@fmn_reminder", &text])?;
+            }
+        }
+        Ok(())
+    }
+}
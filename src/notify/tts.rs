@@ -0,0 +1,236 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use log::{error, info};
+use serde::Deserialize;
+use time::Time;
+
+use super::hook::{capture, wait_timeout};
+use super::sound::available;
+use super::{Firing, Notifier};
+use crate::comm::get_local_now;
+use crate::template::{time_of_day, Template};
+
+// the speaking rate of espeak-ng, which the other engines are scaled to
+const DEFAULT_RATE: u32 = 175;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TtsConfig {
+    // espeak-ng, piper, festival or a command line with {text}, {voice} and
+    // {rate} placeholders, e.g. "say -v {voice} {text}"
+    pub engine: String,
+    // an espeak-ng voice, a piper model file or a festival voice, e.g. kal_diphone
+    pub voice: Option<String>,
+    // words per minute
+    pub rate: Option<u32>,
+    // what is spoken, a template like the ones of email
+    pub text: String,
+    // nothing is spoken from `start` until `end`, both like "22:00"
+    pub quiet_hours: Option<QuietHours>,
+    pub timeout_secs: u64,
+}
+
+impl Default for TtsConfig {
+    fn default() -> Self {
+        Self {
+            engine: "espeak-ng".to_owned(),
+            voice: None,
+            rate: None,
+            text: "Reminder: {description}".to_owned(),
+            quiet_hours: None,
+            timeout_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuietHours {
+    pub start: String,
+    pub end: String,
+}
+
+impl QuietHours {
+    fn parse(&self) -> Result<(Time, Time)> {
+        let parse =
+            |s: &str| time_of_day(s).ok_or_else(|| anyhow!("{s:?} isn't a time of day like 22:00"));
+        Ok((parse(&self.start)?, parse(&self.end)?))
+    }
+}
+
+// whether `now` is within the hours, which may span midnight
+fn is_quiet((start, end): (Time, Time), now: Time) -> bool {
+    if start <= end {
+        start <= now && now < end
+    } else {
+        now >= start || now < end
+    }
+}
+
+// festival reads scheme; the text is put in a string literal
+fn scheme_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// an engine command and what it reads from stdin
+type Speech = (Command, Option<String>);
+
+/// Speaks reminders aloud with a local text-to-speech engine. The speeches
+/// take their time, so a thread of the notifier says them one after another
+/// while the other notifiers carry on.
+pub struct TtsNotifier {
+    name: String,
+    config: TtsConfig,
+    quiet_hours: Option<(Time, Time)>,
+    text: Template,
+    speaker: Mutex<Sender<Speech>>,
+}
+
+impl TtsNotifier {
+    pub fn new(name: Option<String>, config: TtsConfig) -> Result<Self> {
+        let quiet_hours = config
+            .quiet_hours
+            .as_ref()
+            .map(QuietHours::parse)
+            .transpose()?;
+        if config.engine == "piper" && config.voice.is_none() {
+            return Err(anyhow!("piper needs a model file as the voice"));
+        }
+        let text = Template::parse_notifier_text(&config.text)?;
+        let (sender, receiver) = channel::<Speech>();
+        let timeout = Duration::from_secs(config.timeout_secs);
+        std::thread::spawn(move || {
+            for (command, input) in receiver {
+                if let Err(e) = speak(command, input, timeout) {
+                    error!("fail to speak a reminder: {}", e);
+                }
+            }
+        });
+        Ok(Self {
+            name: name.unwrap_or_else(|| "tts".to_owned()),
+            config,
+            quiet_hours,
+            text,
+            speaker: Mutex::new(sender),
+        })
+    }
+
+    // the command speaking `text`, and what it reads from stdin
+    fn command(&self, text: &str) -> Speech {
+        let voice = self.config.voice.clone().unwrap_or_default();
+        let rate = self.config.rate.unwrap_or(DEFAULT_RATE).max(1);
+        // how much longer than usual every sound lasts
+        let stretch = f64::from(DEFAULT_RATE) / f64::from(rate);
+        match self.config.engine.as_str() {
+            "espeak-ng" => {
+                let mut command = Command::new("espeak-ng");
+                if !voice.is_empty() {
+                    command.args(["-v", &voice]);
+                }
+                command.args(["-s", &rate.to_string(), "--", text]);
+                (command, None)
+            }
+            "festival" => {
+                let mut command = Command::new("festival");
+                command.arg("--batch");
+                if !voice.is_empty() {
+                    command.arg(format!("(voice_{voice})"));
+                }
+                command
+                    .arg(format!("(Parameter.set 'Duration_Stretch {stretch})"))
+                    .arg(format!("(SayText {})", scheme_string(text)));
+                (command, None)
+            }
+            // piper only writes audio, which aplay plays
+            "piper" => {
+                let mut command = Command::new("sh");
+                command
+                    .arg("-c")
+                    .arg(
+                        "piper --model \"$0\" --length_scale \"$1\" --output_raw \
+                         | aplay -q -r 22050 -f S16_LE -t raw -",
+                    )
+                    .arg(&voice)
+                    .arg(stretch.to_string());
+                (command, Some(text.to_owned()))
+            }
+            engine => {
+                let mut words = engine.split_whitespace().map(|word| {
+                    word.replace("{text}", text)
+                        .replace("{voice}", &voice)
+                        .replace("{rate}", &rate.to_string())
+                });
+                let mut command = Command::new(words.next().unwrap_or_default());
+                command.args(words);
+                (command, None)
+            }
+        }
+    }
+}
+
+// runs the engine until it is done speaking
+fn speak(mut command: Command, input: Option<String>, timeout: Duration) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(format!("fail to run {program}"))?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin
+            .write_all(input.as_bytes())
+            .context(format!("fail to pass the text to {program}"))?;
+    }
+    let stderr = capture(child.stderr.take());
+    match wait_timeout(&mut child, timeout)? {
+        Some(status) if status.success() => Ok(()),
+        Some(status) => Err(anyhow!(
+            "{} exits with {} ({})",
+            program,
+            status,
+            stderr.output().trim()
+        )),
+        None => {
+            let _ = child.kill();
+            let _ = child.wait();
+            Err(anyhow!("{program} timed out after {timeout:?}"))
+        }
+    }
+}
+
+impl Notifier for TtsNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, firing: &Firing) -> Result<()> {
+        // quiet hours are a choice, so the reminder still counts as delivered
+        if let Some(hours) = self.quiet_hours {
+            if is_quiet(hours, get_local_now().time()) {
+                info!("keep quiet about task {}", firing.task.task_id);
+                return Ok(());
+            }
+        }
+        // notifier templates can't use the occurrence, so it isn't passed on
+        let (command, input) = self.command(&self.text.render(firing, 0));
+        // a missing engine fails the delivery; what it does later is logged
+        let program = command.get_program().to_string_lossy().into_owned();
+        if !available(&program) {
+            return Err(anyhow!("fail to find the speech engine {program}"));
+        }
+        self.speaker
+            .lock()
+            .expect("speaker lock is poisoned")
+            .send((command, input))
+            .map_err(|_| anyhow!("the speaker of {} is gone", self.name))
+    }
+}
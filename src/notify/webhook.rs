@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use ureq::{Agent, AgentBuilder};
+
+use super::{Firing, FiringPayload, Notifier};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+pub(super) fn default_timeout_secs() -> u64 {
+    10
+}
+
+// posts every firing as a json `FiringPayload`
+pub struct WebhookNotifier {
+    name: String,
+    config: WebhookConfig,
+    agent: Agent,
+}
+
+impl WebhookNotifier {
+    pub fn new(name: Option<String>, config: WebhookConfig) -> Self {
+        let agent = AgentBuilder::new()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build();
+        Self {
+            name: name.unwrap_or_else(|| "webhook".to_owned()),
+            config,
+            agent,
+        }
+    }
+
+    fn post(&self, body: &str) -> std::result::Result<(), Box<ureq::Error>> {
+        let mut request = self
+            .agent
+            .post(&self.config.url)
+            .set("Content-Type", "application/json");
+        for (header, value) in self.config.headers.iter() {
+            request = request.set(header, value);
+        }
+        request.send_string(body).map_err(Box::new)?;
+        Ok(())
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, firing: &Firing) -> Result<()> {
+        let body = serde_json::to_string(&FiringPayload::from(firing))?;
+        sent(&self.config.url, self.post(&body))
+    }
+
+    fn retries_by_default(&self) -> bool {
+        true
+    }
+}
+
+// a failed post is an error of the notifier; it is tried again in the
+// background by the retry queue instead of while the reminder fires
+pub(super) fn sent(target: &str, result: std::result::Result<(), Box<ureq::Error>>) -> Result<()> {
+    result.map_err(|e| anyhow!("fail to post to {}: {}", target, e))
+}
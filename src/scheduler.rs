@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
 use anyhow::{anyhow, Context, Result};
@@ -11,26 +12,40 @@ use tokio::sync::mpsc;
 use tokio::time::sleep;
 
 use crate::comm::{get_local_utc_offset, parse_duration};
-use crate::notify::desktop_notification;
-use crate::task_manager::{ClockType, Task, TaskID};
+use crate::events::{Event, EventBus};
+use crate::notify::{Firing, FiringPayload, NotifierRegistry};
+use crate::task_manager::{ClockType, ContextSettings, Task, TaskContext, TaskID};
 
-const SUMMARY: &str = "forget-me-not";
 const CONSTANT_WAKUP_SECS: u64 = 30; // a task wake up periodically to check whether the time has
                                      // passed, in case that the host goes to sleep
 
+// the settings of every context, filled into its tasks when they fire
+type ContextDefaults = Arc<RwLock<HashMap<TaskContext, ContextSettings>>>;
+
 pub struct Scheduler {
-    task_sender: mpsc::Sender<SchedulerCommand>,
+    // unbounded, so that the task manager never waits on the scheduler while
+    // clients wait on the task manager
+    task_sender: mpsc::UnboundedSender<SchedulerCommand>,
+    events: EventBus,
+    // the notifiers tasks and contexts may name
+    notifier_names: Vec<String>,
+    context_defaults: ContextDefaults,
 }
 
 pub struct InnerScheduler {
     cancel_channels: HashMap<TaskID, broadcast::Sender<TaskCommand>>,
     tzdiff: UtcOffset,
+    notifiers: Arc<NotifierRegistry>,
+    events: EventBus,
+    context_defaults: ContextDefaults,
 }
 
 #[derive(Debug)]
 enum SchedulerCommand {
     Add(Task),
     Cancel(Task),
+    // drop the retries queued for the task
+    Forget(TaskID),
 }
 
 #[derive(Clone, Debug)]
@@ -41,12 +56,23 @@ enum TaskCommand {
 
 impl Scheduler {
     pub fn new() -> Self {
-        let (sender, receiver) = mpsc::channel(8);
+        Self::with_notifiers(NotifierRegistry::default())
+    }
+
+    // fired tasks are delivered to every notifier in the registry
+    pub fn with_notifiers(notifiers: NotifierRegistry) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
         let tzdiff = get_local_utc_offset();
+        let events = EventBus::default();
+        let inner_events = events.clone();
+        let notifier_names = notifiers.names().into_iter().map(str::to_owned).collect();
+        let context_defaults = ContextDefaults::default();
+        let inner_defaults = context_defaults.clone();
         std::thread::spawn(
             move || match Builder::new_current_thread().enable_all().build() {
                 Ok(rt) => {
-                    let mut inner = InnerScheduler::new(tzdiff);
+                    let mut inner =
+                        InnerScheduler::new(tzdiff, notifiers, inner_events, inner_defaults);
                     inner.start(rt, receiver);
                 }
                 Err(e) => {
@@ -56,15 +82,51 @@ impl Scheduler {
         );
         Scheduler {
             task_sender: sender,
+            events,
+            notifier_names,
+            context_defaults,
+        }
+    }
+
+    // fails on the first of `names` no notifier of the daemon goes by
+    pub fn check_notifiers(&self, names: &[String]) -> Result<()> {
+        match names
+            .iter()
+            .find(|name| !self.notifier_names.contains(name))
+        {
+            Some(name) => Err(anyhow!(
+                "no notifier named {}; the daemon has {}",
+                name,
+                self.notifier_names.join(", ")
+            )),
+            None => Ok(()),
         }
     }
 
+    // the settings the tasks of the context fire with from now on, including
+    // those added before; None once the context has none
+    pub fn set_context_settings(&self, context: &TaskContext, settings: Option<ContextSettings>) {
+        let mut defaults = self
+            .context_defaults
+            .write()
+            .expect("context settings lock is poisoned");
+        match settings {
+            Some(settings) => defaults.insert(context.clone(), settings),
+            None => defaults.remove(context),
+        };
+    }
+
+    // where firings are published, along with the changes of the task manager
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
     pub fn add_task(&mut self, task: Task) -> Result<()> {
         if self.check_inner_scheduler_crashed() {
             panic!("the inner scheduler has paniced!");
         }
         let clock_type = task.clock_type.clone();
-        match self.task_sender.blocking_send(SchedulerCommand::Add(task)) {
+        match self.task_sender.send(SchedulerCommand::Add(task)) {
             Ok(()) => {
                 debug!(
                     "successfully send new task to inner scheduler: {}",
@@ -81,10 +143,7 @@ impl Scheduler {
             panic!("the inner scheduler has paniced!");
         }
         let task_id = task.task_id.clone();
-        match self
-            .task_sender
-            .blocking_send(SchedulerCommand::Cancel(task))
-        {
+        match self.task_sender.send(SchedulerCommand::Cancel(task)) {
             Ok(()) => {
                 debug!(
                     "successfully cancel new task to inner scheduler: {}",
@@ -99,6 +158,17 @@ impl Scheduler {
         }
     }
 
+    // the task is gone or its reminder acknowledged, so its failed deliveries
+    // are no longer tried again
+    pub fn forget(&self, task_id: TaskID) -> Result<()> {
+        if self.check_inner_scheduler_crashed() {
+            panic!("the inner scheduler has paniced!");
+        }
+        self.task_sender
+            .send(SchedulerCommand::Forget(task_id))
+            .map_err(|e| anyhow!("fail to send forget task to inner scheduler: {}", e))
+    }
+
     fn check_inner_scheduler_crashed(&self) -> bool {
         self.task_sender.is_closed()
     }
@@ -111,14 +181,22 @@ impl Default for Scheduler {
 }
 
 impl InnerScheduler {
-    fn new(tzdiff: UtcOffset) -> Self {
+    fn new(
+        tzdiff: UtcOffset,
+        notifiers: NotifierRegistry,
+        events: EventBus,
+        context_defaults: ContextDefaults,
+    ) -> Self {
         InnerScheduler {
             cancel_channels: HashMap::new(),
             tzdiff,
+            notifiers: Arc::new(notifiers),
+            events,
+            context_defaults,
         }
     }
 
-    fn start(&mut self, rt: Runtime, mut task_receiver: mpsc::Receiver<SchedulerCommand>) {
+    fn start(&mut self, rt: Runtime, mut task_receiver: mpsc::UnboundedReceiver<SchedulerCommand>) {
         rt.block_on(async {
             while let Some(scheduler_command) = task_receiver.recv().await {
                 match scheduler_command {
@@ -132,6 +210,7 @@ impl InnerScheduler {
                             error!("fail to cancel task: {}", e);
                         }
                     }
+                    SchedulerCommand::Forget(task_id) => self.notifiers.forget(&task_id),
                 }
             }
         });
@@ -145,6 +224,9 @@ impl InnerScheduler {
         let (sender, receiver) = broadcast::channel(1);
         // enter the tokio rt context so that we can use tokio::spawn
         let tzdiff = self.tzdiff;
+        let notifiers = self.notifiers.clone();
+        let events = self.events.clone();
+        let defaults = self.context_defaults.clone();
         match clock_type {
             ClockType::Once(next_fire) => {
                 let sender = sender.clone();
@@ -161,19 +243,14 @@ impl InnerScheduler {
                         let now = OffsetDateTime::now_utc();
                         let now_with_offset = now.to_offset(tzdiff);
                         if now_with_offset >= next_fire {
-                            if now_with_offset.minute() - next_fire.minute() <= 1 {
+                            // a clock woken long after, e.g. by a resume from
+                            // suspend, stays silent
+                            if now_with_offset - next_fire <= time::Duration::minutes(1) {
                                 info!(
                                     "a once clock at {}:{} and description {} fire!",
                                     hour, minute, &task.description
                                 );
-                                if let Err(e) = desktop_notification(
-                                    SUMMARY,
-                                    &task.description,
-                                    task.get_image(),
-                                    task.get_sound(),
-                                ) {
-                                    error!("fail to send de notification: {}", e);
-                                }
+                                fire(&notifiers, &events, &defaults, &task, next_fire);
                             }
                             sender
                                 .send(TaskCommand::Stop)
@@ -185,10 +262,11 @@ impl InnerScheduler {
             ClockType::Period(period) => {
                 let duration = parse_duration(&period)
                     .expect("this shall have been verified by the client side");
-                tokio::spawn(period_clock(task, duration, sender.clone(), receiver))
+                tokio::spawn(period_clock(
+                    task, duration, notifiers, events, defaults, receiver,
+                ))
             }
             ClockType::OncePerDay(hour, minute) => {
-                let sender = sender.clone();
                 tokio::spawn(period_do(
                     Duration::from_secs(60),
                     receiver,
@@ -202,17 +280,13 @@ impl InnerScheduler {
                                 "a clock at {}:{} everyday and description {} fire!",
                                 hour, minute, &task.description
                             );
-                            if let Err(e) = desktop_notification(
-                                SUMMARY,
-                                &task.description,
-                                task.get_image(),
-                                task.get_sound(),
-                            ) {
-                                error!("fail to send de notification: {}", e);
-                                sender
-                                    .send(TaskCommand::Stop)
-                                    .expect("fail to stop after de notify err");
-                            }
+                            let scheduled_at = now
+                                .replace_second(0)
+                                .and_then(|t| t.replace_nanosecond(0))
+                                .unwrap_or(now);
+                            // a failed delivery never stops the task; the
+                            // registry retries it
+                            fire(&notifiers, &events, &defaults, &task, scheduled_at);
                         }
                     },
                 ))
@@ -241,7 +315,9 @@ impl InnerScheduler {
 async fn period_clock(
     task: Task,
     period: Duration,
-    sender: broadcast::Sender<TaskCommand>,
+    notifiers: Arc<NotifierRegistry>,
+    events: EventBus,
+    defaults: ContextDefaults,
     receiver: broadcast::Receiver<TaskCommand>,
 ) {
     period_do(
@@ -256,22 +332,56 @@ async fn period_clock(
                 period.as_secs(),
                 &task.description
             );
-            if let Err(e) = desktop_notification(
-                SUMMARY,
-                &task.description,
-                task.get_image(),
-                task.get_sound(),
-            ) {
-                error!("fail to send de notification: {}", e);
-                sender
-                    .send(TaskCommand::Stop)
-                    .expect("fail to stop after de notify err");
-            }
+            let now = OffsetDateTime::now_utc().to_offset(get_local_utc_offset());
+            fire(&notifiers, &events, &defaults, &task, now);
         },
     )
     .await;
 }
 
+// hands the firing to the notifiers and tells the subscribers about it; the
+// task fires with the current settings of its context. Notifiers may take
+// their time, so this happens off the runtime, where the clocks and the
+// commands of the task manager carry on meanwhile.
+fn fire(
+    notifiers: &Arc<NotifierRegistry>,
+    events: &EventBus,
+    defaults: &ContextDefaults,
+    task: &Task,
+    scheduled_at: OffsetDateTime,
+) {
+    let notifiers = notifiers.clone();
+    let events = events.clone();
+    let defaults = defaults.clone();
+    let task = task.clone();
+    tokio::task::spawn_blocking(move || {
+        deliver(&notifiers, &events, &defaults, task, scheduled_at)
+    });
+}
+
+fn deliver(
+    notifiers: &NotifierRegistry,
+    events: &EventBus,
+    defaults: &ContextDefaults,
+    task: Task,
+    scheduled_at: OffsetDateTime,
+) {
+    let mut firing = Firing::new(task, scheduled_at);
+    if let Some(settings) = defaults
+        .read()
+        .expect("context settings lock is poisoned")
+        .get(&firing.task.context)
+    {
+        settings.apply(&mut firing.task);
+    }
+    if let Err(e) = notifiers.notify(&firing) {
+        error!("fail to send notification: {}", e);
+    }
+    events.publish(Event::ReminderFired {
+        firing: FiringPayload::from(&firing),
+    });
+}
+
 async fn period_do<F1, F2>(
     period: Duration,
     mut receiver: broadcast::Receiver<TaskCommand>,
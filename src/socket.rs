@@ -0,0 +1,164 @@
+use std::fs::{self, DirBuilder, Permissions};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use rustix::process::getuid;
+
+// platforms that tell who is on the other end of a unix socket
+const CHECKS_PEERS: bool = cfg!(any(target_os = "linux", target_os = "android"));
+
+/// The socket in a directory of the user's own: `$XDG_RUNTIME_DIR/fmn`, or
+/// `/tmp/fmn-<uid>` where there is no runtime directory.
+pub fn default_socket() -> PathBuf {
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("fmn"),
+        _ => PathBuf::from(format!("/tmp/fmn-{}", getuid().as_raw())),
+    };
+    dir.join("fmn.sock")
+}
+
+/// Binds a socket only its owner can connect to, unless `allowed_uids` lets
+/// in other users: then anyone may connect, and `check_peer` turns away the
+/// users not allowed; platforms that can't check peers keep the socket to its
+/// owner and refuse other users. A missing directory is created for the owner alone, or
+/// searchable by others for a shared socket; a socket left behind by a daemon
+/// that is gone is replaced, but not one still answering, one of another
+/// user, or a file that isn't a socket.
+pub fn bind_private(path: &Path, allowed_uids: &Option<Vec<u32>>) -> Result<UnixListener> {
+    let uid = getuid().as_raw();
+    let shared = allowed_uids
+        .as_ref()
+        .is_some_and(|uids| uids.iter().any(|&allowed| allowed != uid));
+    if shared && !CHECKS_PEERS {
+        return Err(anyhow!(
+            "server.allowed_uids can't name other users on this platform, \
+             which doesn't tell who connects to a socket"
+        ));
+    }
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        prepare_dir(dir, uid, shared)?;
+    }
+    remove_stale(path, uid)?;
+    let mode = if shared { 0o666 } else { 0o600 };
+    bind_aside(path, mode)
+}
+
+// binds the socket in a directory only the owner can reach and moves it into
+// place once it has its mode, so that nobody connects in between; the umask
+// is left alone, as it is shared by all the threads
+fn bind_aside(path: &Path, mode: u32) -> Result<UnixListener> {
+    static BINDS: AtomicUsize = AtomicUsize::new(0);
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{path:?} doesn't name a socket"))?;
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let aside = dir.join(format!(
+        ".fmn-bind-{}-{}",
+        std::process::id(),
+        BINDS.fetch_add(1, Ordering::Relaxed)
+    ));
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&aside)
+        .context(format!("fail to create {aside:?}"))?;
+    let bound = aside.join(name);
+    let listener = UnixListener::bind(&bound)
+        .context(format!("fail to bind socket {path:?}"))
+        .and_then(|listener| {
+            fs::set_permissions(&bound, Permissions::from_mode(mode))
+                .context(format!("fail to set the permissions of {path:?}"))?;
+            fs::rename(&bound, path).context(format!("fail to move the socket to {path:?}"))?;
+            Ok(listener)
+        });
+    // left behind only if binding failed halfway
+    let _ = fs::remove_file(&bound);
+    let _ = fs::remove_dir(&aside);
+    listener
+}
+
+fn prepare_dir(dir: &Path, uid: u32, shared: bool) -> Result<()> {
+    if !dir.exists() {
+        let mode = if shared { 0o711 } else { 0o700 };
+        DirBuilder::new()
+            .recursive(true)
+            .mode(mode)
+            .create(dir)
+            .context(format!("fail to create socket directory {dir:?}"))?;
+        // whatever the umask took away
+        return fs::set_permissions(dir, Permissions::from_mode(mode))
+            .context(format!("fail to set the permissions of {dir:?}"));
+    }
+    let metadata = fs::metadata(dir).context(format!("fail to inspect {dir:?}"))?;
+    // system directories like /tmp and /run belong to root
+    if metadata.uid() != uid && metadata.uid() != 0 {
+        return Err(anyhow!(
+            "refuse to listen in {dir:?}, which belongs to user {}",
+            metadata.uid()
+        ));
+    }
+    if metadata.mode() & 0o022 != 0 {
+        warn!(
+            "socket directory {:?} is writable by other users; prefer a private one",
+            dir
+        );
+    }
+    if shared && metadata.mode() & 0o001 == 0 {
+        warn!(
+            "other users can't reach the socket in {:?}; make it searchable (chmod o+x)",
+            dir
+        );
+    }
+    Ok(())
+}
+
+fn remove_stale(path: &Path, uid: u32) -> Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context(format!("fail to inspect {path:?}")),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(anyhow!("refuse to replace {path:?}, which is not a socket"));
+    }
+    if metadata.uid() != uid {
+        return Err(anyhow!(
+            "refuse to replace socket {path:?} of user {}",
+            metadata.uid()
+        ));
+    }
+    if UnixStream::connect(path).is_ok() {
+        return Err(anyhow!("another fmn-daemon is listening on {path:?}"));
+    }
+    fs::remove_file(path).context(format!("fail to remove stale socket {path:?}"))
+}
+
+/// Lets in peers running as one of `allowed_uids`, or as the user of the
+/// daemon if none are given: the uid of the peer, or why it is turned away.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn check_peer(stream: &UnixStream, allowed_uids: &Option<Vec<u32>>) -> Result<u32, String> {
+    let peer = rustix::net::sockopt::socket_peercred(stream)
+        .map_err(|e| format!("fail to get the credentials of the peer: {e}"))?;
+    let uid = peer.uid.as_raw();
+    let allowed = match allowed_uids {
+        Some(uids) => uids.contains(&uid),
+        None => uid == getuid().as_raw(),
+    };
+    if !allowed {
+        return Err(format!("user {uid} is not allowed to use this socket"));
+    }
+    Ok(uid)
+}
+
+// without SO_PEERCRED the socket is left to its owner, see bind_private
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn check_peer(_stream: &UnixStream, _allowed_uids: &Option<Vec<u32>>) -> Result<u32, String> {
+    Ok(getuid().as_raw())
+}
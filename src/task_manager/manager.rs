@@ -2,6 +2,7 @@ use std::fs::OpenOptions;
 use std::io::{self, BufRead, Write};
 use std::iter::Iterator;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::{anyhow, Context, Result};
 use serde::de::DeserializeOwned;
@@ -10,7 +11,10 @@ use serde_json::from_str;
 use time::OffsetDateTime;
 
 use super::task_context::default_context;
-use super::{ClockType, TaskID};
+use super::{ClockType, ContextSettings, TaskID};
+use crate::comm::get_local_now;
+use crate::events::{Event, EventBus};
+use crate::history::{FiringRecord, History};
 use crate::scheduler::Scheduler;
 use crate::task_manager::task_context::TaskContext;
 use crate::task_manager::Task;
@@ -19,18 +23,31 @@ pub struct TaskManager {
     scheduler: Scheduler,
     tasks: SimpleStore<Task>,
     contexts: SimpleStore<TaskContext>,
+    // only contexts with settings are in here
+    settings: SimpleStore<(TaskContext, ContextSettings)>,
+    history: History,
 }
 
 impl TaskManager {
+    // the settings of the task's context are left out; they apply when it
+    // fires
     pub fn add_task(&mut self, task: Task) -> Result<()> {
+        self.scheduler
+            .check_notifiers(&task.notification.notifiers)?;
         // push the task to the scheduler
         // and returns back a unique id
         // which would be later used to cancel a periodic task
         self.tasks.push(task.clone());
-        self.scheduler.add_task(task)?;
+        self.scheduler.add_task(task.clone())?;
+        self.events().publish(Event::TaskAdded { task });
         Ok(())
     }
 
+    // tells subscribers about firings and changes
+    pub fn events(&self) -> &EventBus {
+        self.scheduler.events()
+    }
+
     pub fn get_tasks(&self) -> Vec<Task> {
         let current_context = self.current_context();
         self.tasks
@@ -40,6 +57,28 @@ impl TaskManager {
             .collect()
     }
 
+    // puts the task in place of the task of the current context with its id,
+    // keeping its place in the store
+    pub fn replace_task(&mut self, mut task: Task) -> Result<()> {
+        self.scheduler
+            .check_notifiers(&task.notification.notifiers)?;
+        let context = self.current_context();
+        task.context = context.clone();
+        let old = self
+            .tasks
+            .replace_first(
+                |t| t.task_id == task.task_id && t.context == context,
+                task.clone(),
+            )
+            .ok_or_else(|| anyhow!("no such task found: {}", task.task_id))?;
+        self.scheduler.cancel_task(old.clone())?;
+        self.scheduler.forget(old.task_id.clone())?;
+        self.scheduler.add_task(task.clone())?;
+        self.events().publish(Event::TaskRemoved { task: old });
+        self.events().publish(Event::TaskAdded { task });
+        Ok(())
+    }
+
     pub fn cancel_task(&mut self, task_id: TaskID) -> Result<()> {
         self.cancel_tasks(vec![task_id])
     }
@@ -51,7 +90,9 @@ impl TaskManager {
                 .tasks
                 .remove_first(|t| t.task_id.starts_with(&task_id) && t.context == context)
             {
-                self.scheduler.cancel_task(task)?;
+                self.scheduler.cancel_task(task.clone())?;
+                self.scheduler.forget(task.task_id.clone())?;
+                self.events().publish(Event::TaskRemoved { task });
             } else {
                 return Err(anyhow!(format!("no such task found: {task_id}")));
             }
@@ -74,6 +115,9 @@ impl TaskManager {
         self.contexts
             .refresh_storage()
             .context("fail to refresh context store")?;
+        self.settings
+            .refresh_storage()
+            .context("fail to refresh context settings store")?;
         Ok(())
     }
 
@@ -93,20 +137,69 @@ impl TaskManager {
         if contexts.is_empty() {
             contexts.push(default_context());
         }
+        let settings_store_path = path.as_ref().join("context_settings.data");
+        let settings: Vec<(TaskContext, ContextSettings)> = read_items(&settings_store_path)
+            .context(format!(
+                "fail to open context settings store {settings_store_path:?}"
+            ))?;
+        for (context, settings) in settings.iter() {
+            scheduler.set_context_settings(context, Some(settings.clone()));
+        }
         let current_context = current_context(&contexts);
         for task in tasks.iter().filter(|t| t.context == current_context) {
             scheduler.add_task(task.clone())?;
         }
         let tasks = SimpleStore::new(tasks, task_store_path);
         let contexts = SimpleStore::new(contexts, context_store_path);
+        let settings = SimpleStore::new(settings, settings_store_path);
         let tm = TaskManager {
             scheduler,
             tasks,
             contexts,
+            settings,
+            history: History::new(path),
         };
         Ok(tm)
     }
 
+    pub fn history(&self, limit: usize) -> Result<Vec<FiringRecord>> {
+        self.history.latest(limit)
+    }
+
+    pub fn acknowledge(&self, task_id: &TaskID) -> Result<()> {
+        let record = self.history.acknowledge(task_id)?;
+        self.scheduler.forget(record.task_id.clone())?;
+        self.events().publish(Event::ReminderAcknowledged {
+            task_id: record.task_id,
+            context: record.context,
+        });
+        Ok(())
+    }
+
+    // reminds of a task of the current context once more after `after`
+    pub fn snooze(&mut self, task_id: &TaskID, after: Duration) -> Result<()> {
+        let context = self.current_context();
+        let task = self
+            .tasks
+            .inner()
+            .into_iter()
+            .find(|t| t.task_id.starts_with(task_id.as_str()) && t.context == context)
+            .ok_or_else(|| anyhow!("no such task found: {task_id}"))?;
+        self.snooze_task(task, after)
+    }
+
+    // reminds of the task once more after `after`, in the task's own context;
+    // for the tasks of the daemon's own firings, which may be gone by now
+    pub(crate) fn snooze_task(&mut self, task: Task, after: Duration) -> Result<()> {
+        let task = task.snoozed(get_local_now() + after);
+        self.tasks.push(task.clone());
+        if task.context == self.current_context() {
+            self.scheduler.add_task(task.clone())?;
+        }
+        self.events().publish(Event::TaskSnoozed { task });
+        Ok(())
+    }
+
     pub fn switch_context(&mut self, new_context: TaskContext) -> Result<()> {
         let current_context = self.current_context();
         if new_context == current_context {
@@ -124,6 +217,10 @@ impl TaskManager {
         }
         let index = position.unwrap();
         self.contexts.swap(0, index);
+        self.events().publish(Event::ContextSwitched {
+            from: current_context,
+            to: new_context,
+        });
         Ok(())
     }
 
@@ -131,14 +228,58 @@ impl TaskManager {
         current_context(&self.contexts.mem)
     }
 
-    pub fn define_context(&mut self, context: TaskContext) -> Result<()> {
-        if self.list_context().iter().any(|c| *c == context) {
+    pub fn define_context(
+        &mut self,
+        context: TaskContext,
+        settings: ContextSettings,
+    ) -> Result<()> {
+        if self.list_context().contains(&context) {
             return Err(anyhow!(format!("context {context} already exists")));
         }
+        self.scheduler.check_notifiers(&settings.notifiers)?;
+        if !settings.is_empty() {
+            self.scheduler
+                .set_context_settings(&context, Some(settings.clone()));
+            self.settings.push((context.clone(), settings));
+        }
         self.contexts.push(context);
         Ok(())
     }
 
+    // the defaults of the tasks of the context, whenever they were added
+    pub fn configure_context(
+        &mut self,
+        context: TaskContext,
+        settings: ContextSettings,
+        clear: bool,
+    ) -> Result<()> {
+        if !self.list_context().contains(&context) {
+            return Err(anyhow!("no such context: {}", &context));
+        }
+        self.scheduler.check_notifiers(&settings.notifiers)?;
+        let mut current = match self.settings.remove_first(|(c, _)| c == &context) {
+            Some((_, current)) if !clear => current,
+            _ => ContextSettings::default(),
+        };
+        current.update(settings);
+        if current.is_empty() {
+            self.scheduler.set_context_settings(&context, None);
+        } else {
+            self.scheduler
+                .set_context_settings(&context, Some(current.clone()));
+            self.settings.push((context, current));
+        }
+        Ok(())
+    }
+
+    pub fn context_settings(&self, context: &TaskContext) -> ContextSettings {
+        self.settings
+            .iter()
+            .find(|(c, _)| c == context)
+            .map(|(_, settings)| settings.clone())
+            .unwrap_or_default()
+    }
+
     pub fn list_context(&self) -> Vec<TaskContext> {
         self.contexts.inner()
     }
@@ -149,7 +290,19 @@ impl TaskManager {
             self.switch_context(default_context())?;
         }
         self.contexts.remove_first(|c| c == &context);
+        self.settings.retain(|(c, _)| c != &context);
+        self.scheduler.set_context_settings(&context, None);
+        let removed: Vec<Task> = self
+            .tasks
+            .iter()
+            .filter(|t| t.context == context)
+            .cloned()
+            .collect();
         self.tasks.retain(|t| t.context != context);
+        for task in removed {
+            self.scheduler.forget(task.task_id.clone())?;
+            self.events().publish(Event::TaskRemoved { task });
+        }
         Ok(())
     }
 }
@@ -168,6 +321,7 @@ where
         .read(true)
         .write(true)
         .create(true)
+        .truncate(false)
         .open(&path)?;
     for line in io::BufReader::new(file).lines() {
         let line = line?;
@@ -230,6 +384,15 @@ impl<T: Clone + Serialize> SimpleStore<T> {
         self.mem.push(item);
     }
 
+    // the item replaced, if any
+    pub fn replace_first<F>(&mut self, filter: F, item: T) -> Option<T>
+    where
+        F: for<'a> Fn(&'a T) -> bool,
+    {
+        let index = self.mem.iter().position(filter)?;
+        Some(std::mem::replace(&mut self.mem[index], item))
+    }
+
     pub fn retain<F>(&mut self, f: F)
     where
         F: FnMut(&T) -> bool,
@@ -2,5 +2,5 @@ pub mod manager;
 mod task;
 mod task_context;
 pub use manager::{read_items, TaskManager};
-pub use task::{ClockType, Task, TaskID};
-pub use task_context::TaskContext;
+pub use task::{ClockType, NotificationOptions, Task, TaskID, Urgency};
+pub use task_context::{ContextSettings, TaskContext};
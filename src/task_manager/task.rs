@@ -1,9 +1,12 @@
 use std::fmt::Display;
 
+use clap::ValueEnum;
 use nanoid::nanoid;
 use serde::{Deserialize, Serialize};
 use serde_json::to_vec;
-use time::{format_description, OffsetDateTime};
+use time::macros::format_description;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
 
 use super::task_context::TaskContext;
 
@@ -20,6 +23,58 @@ pub struct Task {
     // media shown when the notification fires
     image_path: Option<String>,
     sound_path: Option<String>,
+
+    // a shell command run by the daemon when the task fires
+    #[serde(default)]
+    exec: Option<String>,
+
+    // how the notification of the task looks and behaves
+    #[serde(default)]
+    pub notification: NotificationOptions,
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    ValueEnum,
+    ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(default)]
+pub struct NotificationOptions {
+    pub urgency: Option<Urgency>,
+    // how long the notification stays on screen; 0 means until dismissed
+    pub timeout_ms: Option<u32>,
+    // an icon name from the theme or a path
+    pub icon: Option<String>,
+    // replaces the "forget-me-not" title
+    pub summary: Option<String>,
+    // every firing updates the task's previous notification instead of
+    // stacking a new one
+    pub replace: bool,
+    // of the sound, in percent
+    pub volume: Option<u8>,
+    // how many times the sound is played in a row
+    pub repeat: Option<u32>,
+    // the names of the notifiers the task is delivered to; those routed by
+    // the config when empty
+    pub notifiers: Vec<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -31,8 +86,7 @@ pub enum ClockType {
 
 impl Display for ClockType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let format = format_description::parse("[year]-[month]-[day] [hour]:[minute]")
-            .expect("fail to parse display format for OffsetDatetime");
+        let format = format_description!("[year]-[month]-[day] [hour]:[minute]");
         match self {
             ClockType::Once(next_fire) => {
                 write!(
@@ -63,6 +117,8 @@ impl Task {
             task_id: nanoid!(),
             image_path: None,
             sound_path: None,
+            exec: None,
+            notification: NotificationOptions::default(),
             // task_id: Uuid::new_v4(),
         }
     }
@@ -80,6 +136,10 @@ impl Task {
         self.sound_path = Some(sound_path);
     }
 
+    pub fn add_exec(&mut self, command: String) {
+        self.exec = Some(command);
+    }
+
     pub fn get_image(&self) -> Option<&str> {
         self.image_path.as_deref()
     }
@@ -88,6 +148,30 @@ impl Task {
         self.sound_path.as_deref()
     }
 
+    pub fn get_created_at(&self) -> OffsetDateTime {
+        self.created_at
+    }
+
+    pub fn get_exec(&self) -> Option<&String> {
+        self.exec.as_ref()
+    }
+
+    // the task as shown to those who may not see the commands it runs
+    pub fn without_exec(mut self) -> Self {
+        self.exec = None;
+        self
+    }
+
+    // a copy of the task firing once more at `next_fire`
+    pub fn snoozed(&self, next_fire: OffsetDateTime) -> Self {
+        Task {
+            created_at: OffsetDateTime::now_utc(),
+            task_id: nanoid!(),
+            clock_type: ClockType::Once(next_fire),
+            ..self.clone()
+        }
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         to_vec(self).unwrap_or_else(|_| panic!("fail to serialize task {:?}", &self))
     }
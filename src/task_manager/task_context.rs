@@ -1,6 +1,10 @@
 //use std::fmt::Display;
 //
-//use serde::{Deserialize, Serialize};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{Task, Urgency};
 
 pub type TaskContext = String;
 
@@ -8,6 +12,63 @@ pub fn default_context() -> TaskContext {
     "default".to_owned()
 }
 
+/// Notification defaults of the tasks of a context, filled in when they fire;
+/// whatever a task sets itself takes precedence.
+#[derive(Args, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(default)]
+pub struct ContextSettings {
+    #[arg(short, long)]
+    pub image_path: Option<String>,
+
+    #[arg(short, long)]
+    pub sound_path: Option<String>,
+
+    #[arg(short, long, value_enum)]
+    pub urgency: Option<Urgency>,
+
+    // the names of the notifiers the tasks are delivered to; all of them
+    // when empty
+    #[arg(short, long = "notifier")]
+    pub notifiers: Vec<String>,
+}
+
+impl ContextSettings {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    // fills in what the task leaves unset
+    pub fn apply(&self, task: &mut Task) {
+        if let (None, Some(image_path)) = (task.get_image(), &self.image_path) {
+            task.add_image(image_path.clone());
+        }
+        if let (None, Some(sound_path)) = (task.get_sound(), &self.sound_path) {
+            task.add_sound(sound_path.clone());
+        }
+        let notification = &mut task.notification;
+        notification.urgency = notification.urgency.or(self.urgency);
+        if notification.notifiers.is_empty() {
+            notification.notifiers = self.notifiers.clone();
+        }
+    }
+
+    // takes the settings given in `other`, keeping the rest
+    pub fn update(&mut self, other: ContextSettings) {
+        if other.image_path.is_some() {
+            self.image_path = other.image_path;
+        }
+        if other.sound_path.is_some() {
+            self.sound_path = other.sound_path;
+        }
+        if other.urgency.is_some() {
+            self.urgency = other.urgency;
+        }
+        if !other.notifiers.is_empty() {
+            self.notifiers = other.notifiers;
+        }
+    }
+}
+
 //#[derive(Debug, Deserialize, PartialEq, Clone, Serialize)]
 //pub struct TaskContext(pub String);
 
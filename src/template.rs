@@ -0,0 +1,312 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+use time::macros::{format_description, time};
+use time::{OffsetDateTime, Time};
+
+use crate::comm::{get_local_utc_offset, parse_duration};
+use crate::notify::{summary, Firing};
+use crate::task_manager::ClockType;
+
+// placeholders a description or summary may use; the templates of notifiers
+// also know NOTIFIER_VARIABLES but not the occurrence
+const VARIABLES: [&str; 11] = [
+    "task_id",
+    "context",
+    "clock",
+    "created_at",
+    "created_ago",
+    "occurrence",
+    "count",
+    "time",
+    "date",
+    "weekday",
+    "minutes_until",
+];
+
+const NOTIFIER_VARIABLES: [&str; 4] = ["description", "summary", "scheduled_at", "fired_at"];
+
+/// The error returned by [`Template::parse`]; `position` is the char offset in
+/// `input` of the offending placeholder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateError {
+    pub input: String,
+    pub position: usize,
+    pub reason: String,
+}
+
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "invalid template {:?}: {} at position {}",
+            self.input, self.reason, self.position
+        )?;
+        writeln!(f, "  {}", self.input)?;
+        write!(f, "  {}^", " ".repeat(self.position))?;
+        write!(
+            f,
+            "\nknown placeholders: {}; write {{{{ and }}}} for literal braces",
+            VARIABLES
+                .iter()
+                .chain(NOTIFIER_VARIABLES.iter())
+                .map(|name| format!("{{{name}}}"))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Variable(Variable),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    TaskId,
+    Context,
+    Clock,
+    CreatedAt,
+    CreatedAgo,
+    Occurrence,
+    Count,
+    Time,
+    Date,
+    Weekday,
+    MinutesUntil(Option<Time>),
+    Description,
+    Summary,
+    ScheduledAt,
+    FiredAt,
+}
+
+/// A description or summary with `{placeholder}`s filled in when the task
+/// fires, e.g. `"Drink water ({occurrence} of {count} today)"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(input: &str) -> Result<Self, TemplateError> {
+        Self::parse_with(input, false)
+    }
+
+    /// Parses the subject, body or spoken text of a notifier, which is
+    /// rendered after the description and summary and may use them.
+    pub fn parse_notifier_text(input: &str) -> Result<Self, TemplateError> {
+        Self::parse_with(input, true)
+    }
+
+    fn parse_with(input: &str, notifier: bool) -> Result<Self, TemplateError> {
+        let error = |position: usize, reason: String| TemplateError {
+            input: input.to_owned(),
+            position,
+            reason,
+        };
+        let chars: Vec<char> = input.chars().collect();
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut pos = 0;
+        while pos < chars.len() {
+            match (chars[pos], chars.get(pos + 1)) {
+                ('{', Some('{')) | ('}', Some('}')) => {
+                    text.push(chars[pos]);
+                    pos += 2;
+                }
+                ('{', _) => {
+                    let start = pos;
+                    let end = chars[start..]
+                        .iter()
+                        .position(|&c| c == '}')
+                        .map(|offset| start + offset)
+                        .ok_or_else(|| error(start, "unclosed placeholder".to_owned()))?;
+                    let placeholder: String = chars[start + 1..end].iter().collect();
+                    let variable =
+                        variable(&placeholder, notifier).map_err(|reason| error(start, reason))?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Variable(variable));
+                    pos = end + 1;
+                }
+                ('}', _) => {
+                    return Err(error(pos, "unmatched }".to_owned()));
+                }
+                (c, _) => {
+                    text.push(c);
+                    pos += 1;
+                }
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self { parts })
+    }
+
+    pub fn render(&self, firing: &Firing, occurrence: u32) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Variable(variable) => expand(*variable, firing, occurrence),
+            })
+            .collect()
+    }
+}
+
+fn variable(placeholder: &str, notifier: bool) -> Result<Variable, String> {
+    let (name, argument) = match placeholder.split_once(':') {
+        Some((name, argument)) => (name.trim(), Some(argument.trim())),
+        None => (placeholder.trim(), None),
+    };
+    let variable = match name {
+        "task_id" => Variable::TaskId,
+        "context" => Variable::Context,
+        "clock" => Variable::Clock,
+        "created_at" => Variable::CreatedAt,
+        "created_ago" => Variable::CreatedAgo,
+        "occurrence" if !notifier => Variable::Occurrence,
+        "count" if !notifier => Variable::Count,
+        "time" => Variable::Time,
+        "date" => Variable::Date,
+        "weekday" => Variable::Weekday,
+        "description" if notifier => Variable::Description,
+        "summary" if notifier => Variable::Summary,
+        "scheduled_at" if notifier => Variable::ScheduledAt,
+        "fired_at" if notifier => Variable::FiredAt,
+        "occurrence" | "count" => {
+            return Err(format!(
+                "{{{name}}} is only known to descriptions and summaries"
+            ))
+        }
+        name if NOTIFIER_VARIABLES.contains(&name) => {
+            return Err(format!("{{{name}}} is only known to notifier templates"))
+        }
+        "minutes_until" => {
+            return match argument {
+                Some(argument) => time_of_day(argument)
+                    .map(|t| Variable::MinutesUntil(Some(t)))
+                    .ok_or_else(|| format!("{argument:?} isn't a time of day like 10:00")),
+                None => Ok(Variable::MinutesUntil(None)),
+            }
+        }
+        _ => return Err(format!("unknown placeholder {{{name}}}")),
+    };
+    match argument {
+        Some(_) => Err(format!("placeholder {{{name}}} takes no argument")),
+        None => Ok(variable),
+    }
+}
+
+// "9:30" or "09:30"
+pub(crate) fn time_of_day(s: &str) -> Option<Time> {
+    let (hour, minute) = s.split_once(':')?;
+    Time::from_hms(hour.parse().ok()?, minute.parse().ok()?, 0).ok()
+}
+
+fn expand(variable: Variable, firing: &Firing, occurrence: u32) -> String {
+    let task = &firing.task;
+    let fired_at = firing.fired_at;
+    match variable {
+        Variable::TaskId => task.task_id.clone(),
+        Variable::Context => task.context.clone(),
+        Variable::Clock => task.clock_type.to_string(),
+        Variable::CreatedAt => local_minute(task.get_created_at()),
+        Variable::CreatedAgo => humanize((fired_at - task.get_created_at()).unsigned_abs()),
+        Variable::Occurrence => occurrence.to_string(),
+        Variable::Count => count_today(firing, occurrence).to_string(),
+        Variable::Time => fired_at
+            .format(format_description!("[hour]:[minute]"))
+            .unwrap_or_default(),
+        Variable::Date => fired_at
+            .format(format_description!("[year]-[month]-[day]"))
+            .unwrap_or_default(),
+        Variable::Weekday => fired_at.weekday().to_string(),
+        Variable::MinutesUntil(Some(at)) => {
+            let mut next = fired_at.replace_time(at);
+            if next < fired_at {
+                next += time::Duration::DAY;
+            }
+            (next - fired_at).whole_minutes().to_string()
+        }
+        Variable::MinutesUntil(None) => match &task.clock_type {
+            ClockType::Period(period) => parse_duration(period)
+                .map(|d| (d.as_secs() / 60).to_string())
+                .unwrap_or_default(),
+            ClockType::OncePerDay(..) => (24 * 60).to_string(),
+            ClockType::Once(_) => "0".to_owned(),
+        },
+        Variable::Description => task.description.clone(),
+        Variable::Summary => summary(task).to_owned(),
+        Variable::ScheduledAt => local_minute(firing.scheduled_at),
+        Variable::FiredAt => local_minute(fired_at),
+    }
+}
+
+fn local_minute(at: OffsetDateTime) -> String {
+    at.to_offset(get_local_utc_offset())
+        .format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+        .unwrap_or_default()
+}
+
+// the firings so far plus those still to come before midnight
+fn count_today(firing: &Firing, occurrence: u32) -> u32 {
+    let ClockType::Period(period) = &firing.task.clock_type else {
+        return occurrence.max(1);
+    };
+    let Ok(period) = parse_duration(period) else {
+        return occurrence;
+    };
+    let midnight = firing.fired_at.replace_time(time!(0:00)) + time::Duration::DAY;
+    let left = (midnight - firing.fired_at).unsigned_abs();
+    let remaining = left.as_nanos() / period.as_nanos().max(1);
+    occurrence.saturating_add(u32::try_from(remaining).unwrap_or(u32::MAX))
+}
+
+// the two largest units of a duration, e.g. "3d 4h"
+fn humanize(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let units = [
+        (secs / 86400, "d"),
+        (secs % 86400 / 3600, "h"),
+        (secs % 3600 / 60, "m"),
+    ];
+    let parts: Vec<String> = units
+        .iter()
+        .skip_while(|(n, _)| *n == 0)
+        .take(2)
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, unit)| format!("{n}{unit}"))
+        .collect();
+    if parts.is_empty() {
+        return "0m".to_owned();
+    }
+    parts.join(" ")
+}
+
+/// Checks that a description or summary only uses known placeholders.
+pub fn validate_template(input: &str) -> Result<(), TemplateError> {
+    Template::parse(input).map(|_| ())
+}
+
+/// The firing with the description and summary of its task rendered. Texts
+/// that don't parse, e.g. of tasks added before templates existed, are kept
+/// as they are.
+pub fn render_firing(firing: &Firing, occurrence: u32) -> Firing {
+    let render = |text: &str| match Template::parse(text) {
+        Ok(template) => template.render(firing, occurrence),
+        _ => text.to_owned(),
+    };
+    let mut rendered = firing.clone();
+    rendered.task.description = render(&firing.task.description);
+    if let Some(summary) = &firing.task.notification.summary {
+        rendered.task.notification.summary = Some(render(summary));
+    }
+    rendered
+}
@@ -0,0 +1,118 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use serde::Deserialize;
+
+/// The certificate of `tls:` endpoints, `server.tls` in `$FMN_DIR/config.json`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsConfig {
+    // pem files of the certificate chain and its private key
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    // if given, clients need a certificate signed by one of the CAs in this
+    // pem file
+    pub client_ca: Option<PathBuf>,
+}
+
+/// What fmn presents to a daemon, taken from the environment:
+/// `FMN_TOKEN`, and for `tls:` endpoints `FMN_TLS_CA`, `FMN_TLS_CERT` and
+/// `FMN_TLS_KEY`.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub token: Option<String>,
+    // the CAs to trust instead of the usual web roots
+    pub ca: Option<PathBuf>,
+    // a client certificate and its key, for daemons that ask for one
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+}
+
+impl Credentials {
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok().filter(|v: &String| !v.is_empty());
+        Self {
+            token: var("FMN_TOKEN"),
+            ca: var("FMN_TLS_CA").map(PathBuf::from),
+            cert: var("FMN_TLS_CERT").map(PathBuf::from),
+            key: var("FMN_TLS_KEY").map(PathBuf::from),
+        }
+    }
+}
+
+fn read_certs(path: &PathBuf) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow!("fail to read certificates {path:?}: {e}"))?;
+    if certs.is_empty() {
+        return Err(anyhow!("no certificate in {path:?}"));
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &PathBuf) -> Result<PrivateKeyDer<'static>> {
+    PrivateKeyDer::from_pem_file(path)
+        .map_err(|e| anyhow!("fail to read private key {path:?}: {e}"))
+}
+
+fn read_roots(path: &PathBuf) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in read_certs(path)? {
+        roots
+            .add(cert)
+            .context(format!("invalid CA certificate in {path:?}"))?;
+    }
+    Ok(roots)
+}
+
+pub fn server_config(config: &TlsConfig) -> Result<Arc<ServerConfig>> {
+    let provider = Arc::new(default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match &config.client_ca {
+        Some(path) => {
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(read_roots(path)?), provider)
+                    .build()
+                    .context("fail to set up client certificate verification")?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let tls = builder
+        .with_single_cert(read_certs(&config.cert)?, read_key(&config.key)?)
+        .context("fail to use the tls certificate")?;
+    Ok(Arc::new(tls))
+}
+
+pub fn client_config(credentials: &Credentials) -> Result<Arc<ClientConfig>> {
+    let roots = match &credentials.ca {
+        Some(path) => read_roots(path)?,
+        None => RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        },
+    };
+    let builder = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots);
+    let tls = match (&credentials.cert, &credentials.key) {
+        (Some(cert), Some(key)) => builder
+            .with_client_auth_cert(read_certs(cert)?, read_key(key)?)
+            .context("fail to use the client certificate")?,
+        (None, None) => builder.with_no_client_auth(),
+        _ => return Err(anyhow!("FMN_TLS_CERT and FMN_TLS_KEY go together")),
+    };
+    Ok(Arc::new(tls))
+}
+
+// the name the certificate of the daemon at host:port has to carry
+pub fn server_name(addr: &str) -> Result<ServerName<'static>> {
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    ServerName::try_from(host.to_owned()).map_err(|_| anyhow!("invalid server name {host:?}"))
+}
@@ -0,0 +1,295 @@
+use std::net::TcpListener;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair};
+use task_reminder::auth::{Auth, Scope, Tokens};
+use task_reminder::client::{send_request, send_request_as};
+use task_reminder::comm::{AddRequest, Request, Response};
+use task_reminder::daemon::{listen, ServerConfig};
+use task_reminder::scheduler::Scheduler;
+use task_reminder::task_manager::{ClockType, TaskManager};
+use task_reminder::tls::{Credentials, TlsConfig};
+use tempfile::tempdir;
+
+// listens on the scheme at a free port of localhost
+fn start_daemon(fmn_dir: &Path, scheme: &str, config: ServerConfig) -> Result<String> {
+    let tm = Arc::new(Mutex::new(TaskManager::new(fmn_dir, Scheduler::new())?));
+    let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+    let endpoint = format!("{scheme}:localhost:{port}");
+    listen(&endpoint.parse()?, tm, config, fmn_dir)?;
+    Ok(endpoint)
+}
+
+fn with_token(token: &str) -> Credentials {
+    Credentials {
+        token: Some(token.to_owned()),
+        ..Credentials::default()
+    }
+}
+
+fn add_request() -> Request {
+    Request::Add(AddRequest::new(
+        "stretch".to_owned(),
+        ClockType::Period("1h".to_owned()),
+    ))
+}
+
+#[test]
+fn tcp_clients_need_a_token() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let mut tokens = Tokens::load(&fmn_dir)?;
+    let reader = tokens.create("dashboard", Scope::Read)?;
+    let writer = tokens.create("phone", Scope::Full)?;
+    assert!(tokens.create("phone", Scope::Read).is_err());
+    tokens.save()?;
+    let endpoint = start_daemon(fmn_dir.path(), "tcp", ServerConfig::default())?;
+
+    let unauthorized = |credentials: &Credentials| -> Result<bool> {
+        let response = send_request_as(Request::Show, &endpoint, credentials)?;
+        Ok(matches!(response, Response::Unauthorized(_)))
+    };
+    assert!(unauthorized(&Credentials::default())?);
+    assert!(unauthorized(&with_token("guess"))?);
+
+    // a read-only token may look but not touch
+    assert!(matches!(
+        send_request_as(Request::Show, &endpoint, &with_token(&reader))?,
+        Response::GetTasks(_)
+    ));
+    assert!(matches!(
+        send_request_as(add_request(), &endpoint, &with_token(&reader))?,
+        Response::Unauthorized(reason) if reason.contains("read-only")
+    ));
+    assert!(matches!(
+        send_request_as(add_request(), &endpoint, &with_token(&writer))?,
+        Response::AddSuccess
+    ));
+    // running commands is left to clients of the unix socket
+    let mut request = AddRequest::new("backup".to_owned(), ClockType::OncePerDay(2, 0));
+    request.exec = Some("~/bin/backup.sh".to_owned());
+    assert!(matches!(
+        send_request_as(Request::Add(request), &endpoint, &with_token(&writer))?,
+        Response::Unauthorized(reason) if reason.contains("unix socket")
+    ));
+
+    // revoking takes effect on the next connection
+    tokens.revoke("phone")?;
+    tokens.save()?;
+    assert!(unauthorized(&with_token(&writer))?);
+    assert!(!unauthorized(&with_token(&reader))?);
+    Ok(())
+}
+
+#[test]
+fn tokens_are_kept_hashed_and_private() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let path = fmn_dir.path().join("tokens.json");
+    // as older releases kept them, readable by others by mistake
+    std::fs::write(
+        &path,
+        r#"[{"name": "phone", "token": "old-secret", "scope": "full"}]"#,
+    )?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644))?;
+    let mut tokens = Tokens::load(&fmn_dir)?;
+    assert_eq!(
+        Auth::Token(tokens.clone()).authorize(Some("old-secret")),
+        Ok(Scope::Full)
+    );
+    let stored = std::fs::read_to_string(&path)?;
+    assert!(!stored.contains("old-secret"), "{stored}");
+    assert_eq!(
+        std::fs::metadata(&path)?.permissions().mode() & 0o777,
+        0o600
+    );
+
+    let secret = tokens.create("dashboard", Scope::Read)?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644))?;
+    tokens.save()?;
+    assert!(!std::fs::read_to_string(&path)?.contains(&secret));
+    assert_eq!(
+        std::fs::metadata(&path)?.permissions().mode() & 0o777,
+        0o600
+    );
+    let tokens = Tokens::load(&fmn_dir)?;
+    assert_eq!(
+        Auth::Token(tokens).authorize(Some(&secret)),
+        Ok(Scope::Read)
+    );
+    Ok(())
+}
+
+// a CA that issues the certificates of daemons and clients
+struct Pki {
+    ca: Certificate,
+    ca_key: KeyPair,
+}
+
+impl Pki {
+    fn new() -> Result<Self> {
+        let mut params = CertificateParams::new(vec![])?;
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate()?;
+        let ca = params.self_signed(&ca_key)?;
+        Ok(Self { ca, ca_key })
+    }
+
+    fn write_ca(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.ca.pem())?;
+        Ok(())
+    }
+
+    // writes <name>.pem and <name>.key to `dir`
+    fn issue(&self, dir: &Path, name: &str) -> Result<()> {
+        let key = KeyPair::generate()?;
+        let cert = CertificateParams::new(vec![name.to_owned()])?.signed_by(
+            &key,
+            &self.ca,
+            &self.ca_key,
+        )?;
+        std::fs::write(dir.join(format!("{name}.pem")), cert.pem())?;
+        std::fs::write(dir.join(format!("{name}.key")), key.serialize_pem())?;
+        Ok(())
+    }
+}
+
+#[test]
+fn tls_endpoint_verifies_client_certificates() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let dir = fmn_dir.path();
+    let pki = Pki::new()?;
+    pki.write_ca(&dir.join("ca.pem"))?;
+    pki.issue(dir, "localhost")?;
+    pki.issue(dir, "laptop")?;
+    let mut tokens = Tokens::load(dir)?;
+    let token = tokens.create("laptop", Scope::Full)?;
+    tokens.save()?;
+    let config = ServerConfig {
+        tls: Some(TlsConfig {
+            cert: dir.join("localhost.pem"),
+            key: dir.join("localhost.key"),
+            client_ca: Some(dir.join("ca.pem")),
+        }),
+        ..ServerConfig::default()
+    };
+    let endpoint = start_daemon(dir, "tls", config)?;
+
+    let credentials = Credentials {
+        token: Some(token),
+        ca: Some(dir.join("ca.pem")),
+        cert: Some(dir.join("laptop.pem")),
+        key: Some(dir.join("laptop.key")),
+    };
+    assert!(matches!(
+        send_request_as(add_request(), &endpoint, &credentials)?,
+        Response::AddSuccess
+    ));
+    assert!(matches!(
+        send_request_as(Request::Show, &endpoint, &credentials)?,
+        Response::GetTasks(tasks) if tasks.len() == 1
+    ));
+
+    // the daemon turns away clients without a certificate
+    let anonymous = Credentials {
+        cert: None,
+        key: None,
+        ..credentials.clone()
+    };
+    assert!(send_request_as(Request::Show, &endpoint, &anonymous).is_err());
+    // and the client turns away daemons it doesn't trust
+    let untrusting = Credentials {
+        ca: None,
+        ..credentials
+    };
+    assert!(send_request_as(Request::Show, &endpoint, &untrusting).is_err());
+    Ok(())
+}
+
+#[test]
+fn tls_endpoint_needs_a_certificate() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let error = start_daemon(fmn_dir.path(), "tls", ServerConfig::default()).unwrap_err();
+    assert!(error.to_string().contains("needs server.tls"));
+    Ok(())
+}
+
+fn listen_on_socket(fmn_dir: &Path, socket: &Path, config: ServerConfig) -> Result<()> {
+    let tm = Arc::new(Mutex::new(TaskManager::new(fmn_dir, Scheduler::new())?));
+    let endpoint = format!("unix:{}", socket.display());
+    listen(&endpoint.parse()?, tm, config, fmn_dir)?;
+    Ok(())
+}
+
+#[test]
+fn socket_is_private() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let socket = fmn_dir.path().join("run").join("fmn.sock");
+    listen_on_socket(fmn_dir.path(), &socket, ServerConfig::default())?;
+    let mode = |path: &Path| std::fs::metadata(path).map(|m| m.permissions().mode() & 0o777);
+    assert_eq!(mode(socket.parent().unwrap())?, 0o700);
+    assert_eq!(mode(&socket)?, 0o600);
+    // bound aside and moved into place, with nothing left behind
+    let entries: Vec<_> = std::fs::read_dir(socket.parent().unwrap())?.collect();
+    assert_eq!(entries.len(), 1);
+    assert!(matches!(
+        send_request(Request::Show, &format!("unix:{}", socket.display()))?,
+        Response::GetTasks(_)
+    ));
+    Ok(())
+}
+
+#[test]
+fn only_stale_sockets_are_replaced() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let socket = fmn_dir.path().join("fmn.sock");
+    // left behind by a daemon that is gone
+    drop(UnixListener::bind(&socket)?);
+    listen_on_socket(fmn_dir.path(), &socket, ServerConfig::default())?;
+
+    // but not while a daemon is listening
+    let error = listen_on_socket(fmn_dir.path(), &socket, ServerConfig::default()).unwrap_err();
+    assert!(format!("{error:#}").contains("another fmn-daemon is listening"));
+
+    let file = fmn_dir.path().join("notes");
+    std::fs::write(&file, "keep me")?;
+    let error = listen_on_socket(fmn_dir.path(), &file, ServerConfig::default()).unwrap_err();
+    assert!(format!("{error:#}").contains("not a socket"));
+    assert_eq!(std::fs::read_to_string(&file)?, "keep me");
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn socket_peers_are_checked_against_allowed_uids() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let uid = std::fs::metadata(fmn_dir.path())?.uid();
+    let socket = fmn_dir.path().join("fmn.sock");
+    let config = ServerConfig {
+        allowed_uids: Some(vec![uid + 1]),
+        ..ServerConfig::default()
+    };
+    listen_on_socket(fmn_dir.path(), &socket, config)?;
+    assert!(matches!(
+        send_request(Request::Show, &format!("unix:{}", socket.display()))?,
+        Response::Unauthorized(reason) if reason.contains(&format!("user {uid}"))
+    ));
+
+    // other users have to reach a socket they are allowed to use
+    let shared = fmn_dir.path().join("shared").join("fmn.sock");
+    let config = ServerConfig {
+        allowed_uids: Some(vec![uid, uid + 1]),
+        ..ServerConfig::default()
+    };
+    listen_on_socket(fmn_dir.path(), &shared, config)?;
+    let mode = |path: &Path| std::fs::metadata(path).map(|m| m.permissions().mode() & 0o777);
+    assert_eq!(mode(shared.parent().unwrap())?, 0o711);
+    assert_eq!(mode(&shared)?, 0o666);
+    assert!(matches!(
+        send_request(Request::Show, &format!("unix:{}", shared.display()))?,
+        Response::GetTasks(_)
+    ));
+    Ok(())
+}
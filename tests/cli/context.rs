@@ -1,11 +1,13 @@
 use anyhow::Result;
-use predicates::str::diff;
+use predicates::prelude::*;
+use predicates::str::{contains, diff};
+use task_reminder::task_manager::Urgency;
 
 use super::helpers::{fmn, spawn_test_daemon};
 
 #[test]
 fn test_define_context() -> Result<()> {
-    let guard = spawn_test_daemon("test_define_context")?;
+    let _guard = spawn_test_daemon("test_define_context")?;
     define_context("a");
     Ok(())
 }
@@ -42,6 +44,73 @@ fn test_rm_context() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn tasks_inherit_context_settings() -> Result<()> {
+    let guard = spawn_test_daemon("tasks_inherit_context_settings")?;
+    fmn(&[
+        "context",
+        "define",
+        "work",
+        "-s",
+        "/sounds/work.wav",
+        "-u",
+        "critical",
+    ])
+    .assert()
+    .success();
+    fmn(&["context", "configure", "work", "--notifier", "log"])
+        .assert()
+        .success();
+    fmn(&["context", "configure", "work", "--notifier", "webhook"])
+        .assert()
+        .stderr(contains("no notifier named webhook"));
+    set_context("work");
+    fmn(&["context", "show"])
+        .assert()
+        .success()
+        .stdout(contains("/sounds/work.wav").and(contains("log")));
+    fmn(&["add", "standup", "after", "1h"]).assert().success();
+    fmn(&[
+        "add",
+        "demo",
+        "-s",
+        "/sounds/demo.wav",
+        "-u",
+        "low",
+        "--notifier",
+        "desktop",
+        "after",
+        "2h",
+    ])
+    .assert()
+    .success();
+    fmn(&["add", "retro", "--notifier", "webhook", "after", "2h"])
+        .assert()
+        .stderr(contains("no notifier named webhook"));
+    // the settings of the context are filled in when a task fires, so they
+    // aren't stored with it
+    let tasks = guard.read_tasks()?;
+    assert_eq!(tasks.len(), 2);
+    assert_eq!(tasks[0].get_sound(), None);
+    assert_eq!(tasks[0].notification.urgency, None);
+    assert!(tasks[0].notification.notifiers.is_empty());
+    assert_eq!(tasks[1].get_sound(), Some("/sounds/demo.wav"));
+    assert_eq!(tasks[1].notification.urgency, Some(Urgency::Low));
+    assert_eq!(tasks[1].notification.notifiers, vec!["desktop"]);
+
+    fmn(&["context", "configure", "work", "--clear", "-u", "low"])
+        .assert()
+        .success();
+    fmn(&["context", "show", "work"])
+        .assert()
+        .success()
+        .stdout(contains("low").and(contains("work.wav").not()));
+    fmn(&["context", "configure", "nowhere", "-u", "low"])
+        .assert()
+        .stderr(contains("no such context"));
+    Ok(())
+}
+
 fn define_context(context: &str) {
     fmn(&["context", "define", context]).assert().success();
 }
@@ -58,3 +127,23 @@ fn list_context(context: Vec<&str>) {
 fn rm_context(context: &str) {
     fmn(&["context", "rm", context]).assert().success();
 }
+
+#[test]
+fn watch_prints_events() -> Result<()> {
+    let _guard = spawn_test_daemon("watch_prints_events")?;
+    define_context("foo");
+    // once fmn watch is subscribed
+    let switch = std::thread::spawn(|| {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        set_context("foo");
+    });
+    fmn(&["watch", "--event", "context_switched"])
+        .timeout(std::time::Duration::from_secs(3))
+        .assert()
+        .interrupted()
+        .stdout(diff(
+            "{\"event\":\"context_switched\",\"from\":\"default\",\"to\":\"foo\"}\n",
+        ));
+    switch.join().unwrap();
+    Ok(())
+}
@@ -1,17 +1,18 @@
-use std::io::{BufReader, BufWriter};
-#[cfg(feature = "tcp")]
-use std::net::TcpListener;
-#[cfg(feature = "unix_socket")]
 use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 use std::{io, sync::mpsc::SyncSender};
 
 use anyhow::Result;
 use assert_cmd::Command;
 use log::{error, info};
 use predicates::str::diff;
+use task_reminder::auth::Auth;
+use task_reminder::daemon::{spawn_serve, ServerConfig};
 use task_reminder::format::tabular_output;
+use task_reminder::notify::{NotifierConfig, NotifierRegistry};
 use task_reminder::task_manager::{read_items, Task, TaskContext};
-use task_reminder::{daemon::serve, scheduler::Scheduler, task_manager::TaskManager};
+use task_reminder::{scheduler::Scheduler, task_manager::TaskManager};
 use tempfile::{tempdir, TempDir};
 
 const BINARY_NAME: &str = "fmn";
@@ -24,6 +25,7 @@ pub fn fmn(args: &[&str]) -> Command {
 
 pub struct DaemonGuard {
     id: String,
+    addr: String,
     _temp_dir: TempDir,
     stop_chan: Option<SyncSender<()>>,
 }
@@ -37,10 +39,20 @@ impl DaemonGuard {
         read_items(self._temp_dir.path().join("task_context.data"))
     }
 
-    fn new(id: String, temp_dir: TempDir) -> Self {
+    pub fn fmn_dir(&self) -> &Path {
+        self._temp_dir.path()
+    }
+
+    // where the daemon listens, for requests sent without the fmn binary
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    fn new(id: String, addr: String, temp_dir: TempDir) -> Self {
         Self {
             stop_chan: None,
             id,
+            addr,
             _temp_dir: temp_dir,
         }
     }
@@ -63,43 +75,27 @@ pub fn spawn_test_daemon(id: &str) -> Result<DaemonGuard> {
     let id = id.to_owned();
     let fmn_dir = tempdir()?;
 
-    #[cfg(feature = "unix_socket")]
     let addr = fmn_dir.path().join("fmn.sock");
-    #[cfg(feature = "unix_socket")]
-    std::env::set_var("FMN_DAEMON_ADDR", addr.to_str().unwrap());
-
-    #[cfg(feature = "tcp")]
-    let addr = "127.0.0.1:0";
+    let dest = format!("unix:{}", addr.display());
+    std::env::set_var("FMN_DAEMON_ADDR", &dest);
+    info!("creating fmn-daemon for {} at {}", id, dest);
 
     std::fs::create_dir_all(&fmn_dir)?;
-    let scheduler = Scheduler::new();
-
-    #[cfg(feature = "tcp")]
-    let listener = TcpListener::bind(addr)?;
-    #[cfg(feature = "tcp")]
-    {
-        listener
-            .set_nonblocking(true)
-            .expect("can't set tcp listener as non-blocking");
-        let dest = listener.local_addr()?.to_string();
-        std::env::set_var("FMN_DAEMON_ADDR", &dest);
-        info!("creating fmn-daemon for {} at {}", id, dest);
-    };
-
-    #[cfg(feature = "unix_socket")]
-    let listener = UnixListener::bind(addr)?;
-
-    let mut tm = TaskManager::new(&fmn_dir, scheduler)?;
-    let mut guard = DaemonGuard::new(id, fmn_dir);
+    // tasks and contexts may name the desktop and the log notifier
+    let mut notifiers = NotifierRegistry::default();
+    let log: NotifierConfig = serde_json::from_str(r#"{"kind": "log"}"#)?;
+    notifiers.register(log.build()?);
+    let scheduler = Scheduler::with_notifiers(notifiers);
+    let listener = UnixListener::bind(&addr)?;
+
+    let tm = Arc::new(Mutex::new(TaskManager::new(&fmn_dir, scheduler)?));
+    let mut guard = DaemonGuard::new(id, dest, fmn_dir);
     let (tx, rx) = std::sync::mpsc::sync_channel(1);
     std::thread::spawn(move || {
         for stream in listener.incoming() {
             match stream {
                 Ok(stream) => {
-                    if let Err(e) = serve(BufReader::new(&stream), BufWriter::new(&stream), &mut tm)
-                    {
-                        error!("error processing stream: {}", e);
-                    }
+                    spawn_serve(stream, tm.clone(), ServerConfig::default(), Auth::Trusted)
                 }
                 Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
                     if rx.try_recv().is_ok() {
@@ -1,7 +1,13 @@
+use std::time::Duration;
+
 use anyhow::{anyhow, Result};
-use task_reminder::{comm::get_local_now, task_manager::ClockType};
+use task_reminder::client::send_request;
+use task_reminder::comm::{get_local_now, Request, Response};
+use task_reminder::history::{FiringRecord, History};
+use task_reminder::notify::Firing;
+use task_reminder::task_manager::{ClockType, NotificationOptions, Task, Urgency};
 
-use crate::cli::helpers::list_tasks;
+use crate::cli::helpers::{fmn, list_tasks};
 
 use super::helpers::{add_task, rm_task, spawn_test_daemon, TestTask};
 
@@ -86,3 +92,167 @@ fn check_clock_type_per() -> Result<()> {
     }
     Ok(())
 }
+
+#[test]
+fn add_task_with_exec() -> Result<()> {
+    let guard = spawn_test_daemon("add_task_with_exec")?;
+    fmn(&["add", "backup", "--exec", "~/bin/backup.sh", "after", "1h"])
+        .assert()
+        .success();
+    let tasks = guard.read_tasks()?;
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(
+        tasks[0].get_exec().map(String::as_str),
+        Some("~/bin/backup.sh")
+    );
+    fmn(&["history"]).assert().success();
+    Ok(())
+}
+
+#[test]
+fn add_task_with_options_after_the_clock() -> Result<()> {
+    let guard = spawn_test_daemon("add_task_with_options_after_the_clock")?;
+    fmn(&[
+        "add",
+        "backup",
+        "at",
+        "2:00",
+        "--per-day",
+        "--exec",
+        "~/bin/backup.sh",
+    ])
+    .assert()
+    .success();
+    let tasks = guard.read_tasks()?;
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].clock_type, ClockType::OncePerDay(2, 0));
+    assert_eq!(
+        tasks[0].get_exec().map(String::as_str),
+        Some("~/bin/backup.sh")
+    );
+    Ok(())
+}
+
+#[test]
+fn snooze_task() -> Result<()> {
+    let guard = spawn_test_daemon("snooze_task")?;
+    add_task(&TestTask::new().description("stretch").per("1h".to_owned()));
+    let task = guard.read_tasks()?.remove(0);
+    let response = send_request(
+        Request::Snooze {
+            task_id: task.task_id[..8].to_owned(),
+            after: Duration::from_secs(600),
+        },
+        guard.addr(),
+    )?;
+    assert!(matches!(response, Response::AddSuccess));
+    let tasks = guard.read_tasks()?;
+    assert_eq!(tasks.len(), 2);
+    assert_eq!(tasks[1].description, "stretch");
+    assert_ne!(tasks[1].task_id, task.task_id);
+    if let ClockType::Once(moment) = tasks[1].clock_type {
+        let minutes = (moment - get_local_now()).whole_minutes();
+        assert!((9..=10).contains(&minutes));
+    } else {
+        return Err(anyhow!("wrong clock type"));
+    }
+
+    let response = send_request(
+        Request::Snooze {
+            task_id: "nope".to_owned(),
+            after: Duration::from_secs(600),
+        },
+        guard.addr(),
+    )?;
+    assert!(matches!(response, Response::Fail(_)));
+    Ok(())
+}
+
+#[test]
+fn acknowledge_firing() -> Result<()> {
+    let guard = spawn_test_daemon("acknowledge_firing")?;
+    let task = Task::new(
+        "water the plants".to_owned(),
+        ClockType::Period("1h".to_owned()),
+    );
+    let now = get_local_now();
+    let firing = Firing {
+        task: task.clone(),
+        stored: task.clone(),
+        scheduled_at: now,
+        fired_at: now,
+        takes_actions: true,
+    };
+    let history = History::new(guard.fmn_dir());
+    history.record(&FiringRecord::new(&firing))?;
+    let response = send_request(
+        Request::Acknowledge(task.task_id[..8].to_owned()),
+        guard.addr(),
+    )?;
+    assert!(matches!(response, Response::AcknowledgeSuccess));
+    assert!(history.latest(1)?[0].acknowledged_at.is_some());
+
+    let response = send_request(Request::Acknowledge("nope".to_owned()), guard.addr())?;
+    assert!(matches!(response, Response::Fail(_)));
+    Ok(())
+}
+
+#[test]
+fn add_task_with_notification_options() -> Result<()> {
+    let guard = spawn_test_daemon("add_task_with_notification_options")?;
+    fmn(&[
+        "add",
+        "stretch",
+        "--urgency",
+        "critical",
+        "--timeout",
+        "30s",
+        "--icon",
+        "alarm-clock",
+        "--summary",
+        "Break",
+        "--replace",
+        "per",
+        "1m",
+    ])
+    .assert()
+    .success();
+    fmn(&["add", "forever", "--timeout", "0s", "after", "1h"])
+        .assert()
+        .success();
+    let tasks = guard.read_tasks()?;
+    assert_eq!(tasks.len(), 2);
+    assert_eq!(
+        tasks[0].notification,
+        NotificationOptions {
+            urgency: Some(Urgency::Critical),
+            timeout_ms: Some(30_000),
+            icon: Some("alarm-clock".to_owned()),
+            summary: Some("Break".to_owned()),
+            replace: true,
+            ..Default::default()
+        }
+    );
+    assert_eq!(tasks[1].notification.timeout_ms, Some(0));
+    assert!(!tasks[1].notification.replace);
+    Ok(())
+}
+
+#[test]
+fn add_task_with_template() -> Result<()> {
+    let guard = spawn_test_daemon("add_task_with_template")?;
+    fmn(&["add", "water ({occurrence} of {count})", "per", "1h"])
+        .assert()
+        .success();
+    fmn(&["add", "water {ocurrence}", "per", "1h"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("unknown placeholder {ocurrence}"));
+    fmn(&["add", "water", "--summary", "{nope}", "per", "1h"])
+        .assert()
+        .failure();
+    let tasks = guard.read_tasks()?;
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].description, "water ({occurrence} of {count})");
+    Ok(())
+}
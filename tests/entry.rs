@@ -1,5 +1,11 @@
+mod auth;
 mod cli;
 mod fmn;
+mod http;
+mod notify;
+mod protocol;
+mod stand_in;
+mod template;
 
 #[cfg(test)]
 #[ctor::ctor]
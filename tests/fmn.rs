@@ -1,7 +1,12 @@
 use std::time::Duration;
 
 use anyhow::Result;
-use task_reminder::comm::{parse_at, parse_duration};
+use task_reminder::comm::{
+    get_local_utc_offset, parse_at, parse_date_with_default, parse_duration, validate_clock_type,
+};
+use task_reminder::task_manager::ClockType;
+use time::macros::{datetime, time};
+use time::UtcOffset;
 
 #[test]
 fn test_duration() -> Result<()> {
@@ -13,6 +18,18 @@ fn test_duration() -> Result<()> {
         ("1d", 3600 * 24),
         ("1h", 3600),
         ("1d1s", 3600 * 24 + 1),
+        ("2w", 3600 * 24 * 14),
+        ("1s1d", 3600 * 24 + 1),
+        ("90min", 90 * 60),
+        ("1 hour 30 minutes", 5400),
+        ("1 hour, 30 minutes and 5 seconds", 5405),
+        ("1.5h", 5400),
+        (".5m", 30),
+        ("2 Days", 3600 * 48),
+        ("PT1H30M", 5400),
+        ("P1W", 3600 * 24 * 7),
+        ("P1DT12H", 3600 * 36),
+        ("pt0.5m", 30),
     ];
 
     for (duration, expected_seconds) in test_cases {
@@ -24,13 +41,48 @@ fn test_duration() -> Result<()> {
 
 #[test]
 fn test_duration_err() {
-    let test_cases = vec!["1f", "abc", "@341", "1d2@3"];
+    let test_cases = vec![
+        "1f", "abc", "@341", "1d2@3", "", "10", "1h1h", "1.h", "P", "PT", "P1M", "P1H", "PT1D",
+    ];
     for duration in test_cases {
         //dbg!("testing {}", duration);
         assert!(parse_duration(duration).is_err());
     }
 }
 
+#[test]
+fn test_duration_err_position() {
+    let test_cases = vec![
+        ("1d2@3", 3),
+        ("abc", 0),
+        ("1h 30 mins 2x", 12),
+        ("1h1h", 3),
+        ("PT1H2X", 5),
+    ];
+    for (duration, position) in test_cases {
+        let err = parse_duration(duration).unwrap_err();
+        assert_eq!(err.position, position, "{duration}");
+    }
+}
+
+#[test]
+fn test_duration_fraction() -> Result<()> {
+    assert_eq!(parse_duration("1.25s")?, Duration::from_millis(1250));
+    assert_eq!(parse_duration("PT0,5S")?, Duration::from_millis(500));
+    Ok(())
+}
+
+#[test]
+fn test_period_minimum() {
+    for period in ["0s", "0.001s", "0.5s", "PT0,5S"] {
+        let err = validate_clock_type(&ClockType::Period(period.to_owned())).unwrap_err();
+        assert!(err.to_string().contains("at least 1s"), "{period}");
+    }
+    for period in ["1s", "1.5s", "1h"] {
+        assert!(validate_clock_type(&ClockType::Period(period.to_owned())).is_ok());
+    }
+}
+
 #[test]
 fn test_parse_at() -> Result<()> {
     // no support for seconds
@@ -58,3 +110,53 @@ fn test_parse_at_err() {
         assert!(parse_at(next_fire).is_err());
     }
 }
+
+#[test]
+fn test_parse_date() -> Result<()> {
+    let default_time = time!(9:00);
+    let local = get_local_utc_offset();
+    let test_cases = vec![
+        ("2999-11-02T14:00Z", datetime!(2999-11-02 14:00 UTC)),
+        ("2999-11-02T14:00:00+09:00", datetime!(2999-11-02 05:00 UTC)),
+        ("2999-11-02T14:00+09:00", datetime!(2999-11-02 05:00 UTC)),
+        (
+            "2999-11-02T14:00",
+            datetime!(2999-11-02 14:00).assume_offset(local),
+        ),
+        (
+            "2999-11-02",
+            datetime!(2999-11-02 9:00).assume_offset(local),
+        ),
+        ("2999-W44-6T14:00Z", datetime!(2999-11-02 14:00 UTC)),
+        ("2999-306", datetime!(2999-11-02 9:00).assume_offset(local)),
+    ];
+    for (date, expected) in test_cases {
+        let parsed = parse_date_with_default(date, default_time)?;
+        assert_eq!(parsed, expected, "{date}");
+        // always shown in local time
+        assert_eq!(parsed.offset(), local, "{date}");
+    }
+
+    let parsed = parse_date_with_default("2999-11-02", time!(18:30))?;
+    assert_eq!(parsed, datetime!(2999-11-02 18:30).assume_offset(local));
+    assert_eq!(parsed.to_offset(UtcOffset::UTC).year(), 2999);
+    Ok(())
+}
+
+#[test]
+fn test_parse_date_err() {
+    let test_cases = vec![
+        "2000-01-01",
+        "2000-01-01T10:00Z",
+        "2999-13-01",
+        "2999-11-02T25:00",
+        "tomorrow",
+        "",
+    ];
+    for date in test_cases {
+        assert!(
+            parse_date_with_default(date, time!(9:00)).is_err(),
+            "{date}"
+        );
+    }
+}
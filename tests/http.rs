@@ -0,0 +1,341 @@
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use task_reminder::auth::{Scope, Tokens};
+use task_reminder::daemon::ServerConfig;
+use task_reminder::http::serve_http;
+use task_reminder::scheduler::Scheduler;
+use task_reminder::task_manager::TaskManager;
+use tempfile::{tempdir, TempDir};
+
+// a daemon serving the REST API at a free port of localhost, with a token of
+// each scope
+struct Api {
+    base: String,
+    full: String,
+    read: String,
+    _fmn_dir: TempDir,
+}
+
+impl Api {
+    fn start() -> Result<Self> {
+        Self::start_with(ServerConfig::default().max_connections)
+    }
+
+    fn start_with(max_requests: usize) -> Result<Self> {
+        let fmn_dir = tempdir()?;
+        let mut tokens = Tokens::load(&fmn_dir)?;
+        let full = tokens.create("script", Scope::Full)?;
+        let read = tokens.create("dashboard", Scope::Read)?;
+        tokens.save()?;
+        let tm = Arc::new(Mutex::new(TaskManager::new(&fmn_dir, Scheduler::new())?));
+        let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let addr = format!("127.0.0.1:{port}");
+        serve_http(&addr, tm, max_requests, fmn_dir.path())?;
+        Ok(Self {
+            base: format!("http://{addr}"),
+            full,
+            read,
+            _fmn_dir: fmn_dir,
+        })
+    }
+
+    // the status and json body of the request, failed or not
+    fn call(&self, method: &str, path: &str, token: &str, body: Option<Value>) -> (u16, Value) {
+        let request = ureq::request(method, &format!("{}{path}", self.base))
+            .set("Authorization", &format!("Bearer {token}"));
+        let response = match body {
+            Some(body) => request
+                .set("Content-Type", "application/json")
+                .send_string(&body.to_string()),
+            None => request.call(),
+        };
+        let response = match response {
+            Ok(response) => response,
+            Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => panic!("fail to reach the api: {e}"),
+        };
+        let status = response.status();
+        let body = response.into_string().unwrap();
+        let body = if body.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_str(&body).unwrap()
+        };
+        (status, body)
+    }
+}
+
+#[test]
+fn tasks_are_managed_over_http() -> Result<()> {
+    let api = Api::start()?;
+    let (status, task) = api.call(
+        "POST",
+        "/tasks",
+        &api.full,
+        Some(json!({"description": "stretch", "per": "1h"})),
+    );
+    assert_eq!(status, 201);
+    assert_eq!(task["description"], "stretch");
+    assert_eq!(task["context"], "default");
+    assert_eq!(task["clock"], json!({"every": "1h"}));
+    let task_id = task["task_id"].as_str().unwrap().to_owned();
+
+    let (status, tasks) = api.call("GET", "/tasks", &api.read, None);
+    assert_eq!(status, 200);
+    assert_eq!(tasks, json!([task]));
+    let (status, found) = api.call("GET", &format!("/tasks/{task_id}"), &api.read, None);
+    assert_eq!((status, found), (200, task));
+
+    let (status, snoozed) = api.call(
+        "POST",
+        &format!("/tasks/{task_id}/snooze"),
+        &api.full,
+        Some(json!({"after": "10m"})),
+    );
+    assert_eq!(status, 201);
+    assert!(snoozed["clock"]["once"].is_string());
+
+    let (status, _) = api.call("DELETE", &format!("/tasks/{task_id}"), &api.full, None);
+    assert_eq!(status, 204);
+    let (status, error) = api.call("GET", &format!("/tasks/{task_id}"), &api.full, None);
+    assert_eq!(status, 404);
+    assert!(error["error"].as_str().unwrap().contains("no such task"));
+    let (_, tasks) = api.call("GET", "/tasks", &api.full, None);
+    assert_eq!(tasks.as_array().unwrap().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn tasks_are_changed_in_place_over_http() -> Result<()> {
+    let api = Api::start()?;
+    let (_, task) = api.call(
+        "POST",
+        "/tasks",
+        &api.full,
+        Some(json!({"description": "stretch", "per": "1h"})),
+    );
+    let task_id = task["task_id"].as_str().unwrap().to_owned();
+    let path = format!("/tasks/{task_id}");
+
+    let (status, changed) = api.call(
+        "PATCH",
+        &path,
+        &api.full,
+        Some(json!({"description": "walk", "per": "2h"})),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(changed["task_id"], task["task_id"]);
+    assert_eq!(changed["description"], "walk");
+    assert_eq!(changed["clock"], json!({"every": "2h"}));
+    let (_, tasks) = api.call("GET", "/tasks", &api.read, None);
+    assert_eq!(tasks, json!([changed]));
+
+    let (status, kept) = api.call("PATCH", &path, &api.full, Some(json!({"on": "2099-01-01"})));
+    assert_eq!(status, 200);
+    assert_eq!(kept["description"], "walk");
+    assert!(kept["clock"]["once"].is_string());
+
+    let cases = [
+        ("/tasks/nothing", json!({"description": "walk"}), 404),
+        (path.as_str(), json!({"exec": "true"}), 403),
+        (path.as_str(), json!({"per": "1h", "after": "1m"}), 400),
+        (path.as_str(), json!({"per": "0s"}), 400),
+    ];
+    for (path, body, expected) in cases {
+        let (status, error) = api.call("PATCH", path, &api.full, Some(body));
+        assert_eq!(status, expected, "{path}");
+        assert!(error["error"].is_string());
+    }
+    assert_eq!(api.call("PATCH", &path, &api.read, Some(json!({}))).0, 403);
+    Ok(())
+}
+
+#[test]
+fn invalid_requests_are_answered_with_json_errors() -> Result<()> {
+    let api = Api::start()?;
+    let cases = [
+        ("POST", "/tasks", Some(json!({"description": "stretch"}))),
+        (
+            "POST",
+            "/tasks",
+            Some(json!({"description": "stretch", "per": "0s"})),
+        ),
+        ("POST", "/tasks", Some(json!({"per": "1h"}))),
+        ("GET", "/history?limit=many", None),
+    ];
+    for (method, path, body) in cases {
+        let (status, error) = api.call(method, path, &api.full, body);
+        assert_eq!(status, 400, "{method} {path}");
+        assert!(error["error"].is_string());
+    }
+    assert_eq!(api.call("GET", "/nowhere", &api.full, None).0, 404);
+    assert_eq!(api.call("PUT", "/tasks", &api.full, None).0, 405);
+    Ok(())
+}
+
+#[test]
+fn http_clients_need_a_token() -> Result<()> {
+    let api = Api::start()?;
+    let (status, error) = api.call("GET", "/tasks", "guess", None);
+    assert_eq!(status, 401);
+    assert!(error["error"].as_str().unwrap().contains("unknown"));
+    let response = ureq::get(&format!("{}/tasks", api.base)).call();
+    assert!(matches!(response, Err(ureq::Error::Status(401, _))));
+
+    // a read-only token may look but not touch
+    let (status, error) = api.call(
+        "POST",
+        "/contexts",
+        &api.read,
+        Some(json!({"name": "work"})),
+    );
+    assert_eq!(status, 403);
+    assert!(error["error"].as_str().unwrap().contains("read-only"));
+
+    // nor may any token run commands
+    let (status, error) = api.call(
+        "POST",
+        "/tasks",
+        &api.full,
+        Some(json!({"description": "backup", "per": "1d", "exec": "~/bin/backup.sh"})),
+    );
+    assert_eq!(status, 403);
+    assert!(error["error"].as_str().unwrap().contains("unix socket"));
+    Ok(())
+}
+
+#[test]
+fn http_requests_beyond_the_limit_are_turned_away() -> Result<()> {
+    let api = Api::start_with(1)?;
+    // a request whose body never comes takes the only place; a short body
+    // would be read along with the headers
+    let mut stalled = TcpStream::connect(api.base.trim_start_matches("http://"))?;
+    write!(
+        stalled,
+        "POST /tasks HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\n\
+         Content-Length: 5000\r\n\r\n{{",
+        api.full
+    )?;
+    std::thread::sleep(Duration::from_millis(200));
+    let (status, error) = api.call("GET", "/tasks", &api.read, None);
+    assert_eq!(status, 503);
+    assert!(error["error"].as_str().unwrap().contains("too many"));
+
+    drop(stalled);
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(api.call("GET", "/tasks", &api.read, None).0, 200);
+    Ok(())
+}
+
+#[test]
+fn contexts_are_managed_over_http() -> Result<()> {
+    let api = Api::start()?;
+    let (status, context) = api.call(
+        "POST",
+        "/contexts",
+        &api.full,
+        Some(json!({"name": "work", "settings": {"urgency": "critical"}})),
+    );
+    assert_eq!(status, 201);
+    assert_eq!(context["current"], false);
+    assert_eq!(context["settings"]["urgency"], "critical");
+    let (status, _) = api.call(
+        "POST",
+        "/contexts",
+        &api.full,
+        Some(json!({"name": "work"})),
+    );
+    assert_eq!(status, 409);
+
+    let (status, context) = api.call(
+        "PATCH",
+        "/contexts/work",
+        &api.full,
+        Some(json!({"current": true, "settings": {"notifiers": ["desktop"]}})),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(context["current"], true);
+    assert_eq!(
+        context["settings"],
+        json!({
+            "image_path": null,
+            "sound_path": null,
+            "urgency": "critical",
+            "notifiers": ["desktop"],
+        })
+    );
+    let (_, contexts) = api.call("GET", "/contexts", &api.read, None);
+    let names: Vec<&str> = contexts
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["work", "default"]);
+
+    // tasks are added to the current context
+    let (_, task) = api.call(
+        "POST",
+        "/tasks",
+        &api.full,
+        Some(json!({"description": "review", "after": "1h"})),
+    );
+    assert_eq!(task["context"], "work");
+    // the settings of the context apply when it fires
+    assert_eq!(task["notification"]["urgency"], Value::Null);
+    let (status, error) = api.call(
+        "PATCH",
+        "/contexts/work",
+        &api.full,
+        Some(json!({"settings": {"notifiers": ["pager"]}})),
+    );
+    assert_eq!(status, 400);
+    assert!(error["error"]
+        .as_str()
+        .unwrap()
+        .contains("no notifier named pager"));
+
+    let (status, _) = api.call("DELETE", "/contexts/work", &api.full, None);
+    assert_eq!(status, 204);
+    assert_eq!(api.call("GET", "/contexts/work", &api.full, None).0, 404);
+    let (_, context) = api.call("GET", "/contexts/default", &api.full, None);
+    assert_eq!(context["current"], true);
+    Ok(())
+}
+
+#[test]
+fn openapi_document_describes_the_api() -> Result<()> {
+    let api = Api::start()?;
+    // without a token, so that tools can find their way in
+    let document = ureq::get(&format!("{}/openapi.json", api.base))
+        .call()?
+        .into_string()?;
+    let document: Value = serde_json::from_str(&document)?;
+    assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+    let paths = document["paths"].as_object().unwrap();
+    for path in [
+        "/tasks",
+        "/tasks/{id}",
+        "/contexts",
+        "/contexts/{name}",
+        "/history",
+    ] {
+        assert!(paths.contains_key(path), "{path} is missing");
+    }
+    assert!(paths["/contexts/{name}"]["patch"].is_object());
+    assert!(paths["/tasks/{id}"]["patch"].is_object());
+    let schemas = &document["components"]["schemas"];
+    assert!(schemas["TaskView"].is_object());
+    assert!(schemas["NewTask"].is_object());
+    assert!(schemas["TaskUpdate"].is_object());
+    assert_eq!(
+        document["components"]["securitySchemes"]["token"]["scheme"],
+        "bearer"
+    );
+    Ok(())
+}
@@ -0,0 +1,1318 @@
+use std::os::unix::net::UnixDatagram;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use task_reminder::comm::get_local_now;
+use task_reminder::config::Config;
+use task_reminder::daemon::forward_actions;
+use task_reminder::history::{FiringRecord, History, HookRun};
+use task_reminder::notify::{
+    action_receiver, play_sound, run_hook, DigestConfig, Firing, FiringPayload, HookConfig,
+    Notifier, NotifierConfig, NotifierRegistry, RetryPolicy, Routing, SoundConfig, TaskAction,
+};
+use task_reminder::scheduler::Scheduler;
+use task_reminder::task_manager::{ClockType, ContextSettings, Task, TaskManager, Urgency};
+use tempfile::tempdir;
+
+use crate::stand_in::{http_stand_in, mqtt_stand_in, smtp_stand_in};
+
+// a notifier remembering every firing it was given
+pub struct Recorder {
+    name: String,
+    fail: bool,
+    sender: Mutex<Sender<Firing>>,
+}
+
+impl Recorder {
+    pub fn new(name: &str, fail: bool) -> (Arc<Self>, Receiver<Firing>) {
+        let (sender, receiver) = channel();
+        let recorder = Self {
+            name: name.to_owned(),
+            fail,
+            sender: Mutex::new(sender),
+        };
+        (Arc::new(recorder), receiver)
+    }
+}
+
+impl Notifier for Recorder {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, firing: &Firing) -> Result<()> {
+        self.sender.lock().unwrap().send(firing.clone())?;
+        if self.fail {
+            return Err(anyhow!("{} is down", self.name));
+        }
+        Ok(())
+    }
+}
+
+// stands in for the desktop notifier, which comes with the sounds
+struct Screen;
+
+impl Notifier for Screen {
+    fn name(&self) -> &str {
+        "screen"
+    }
+
+    fn notify(&self, _firing: &Firing) -> Result<()> {
+        Ok(())
+    }
+
+    fn shows_on_desktop(&self) -> bool {
+        true
+    }
+}
+
+fn firing(description: &str) -> Firing {
+    let task = Task::new(description.to_owned(), ClockType::Period("1h".to_owned()));
+    Firing::new(task, get_local_now())
+}
+
+#[test]
+fn fan_out_to_every_notifier() -> Result<()> {
+    let (a, a_firings) = Recorder::new("a", false);
+    let (b, b_firings) = Recorder::new("b", true);
+    let (c, c_firings) = Recorder::new("c", false);
+    let mut registry = NotifierRegistry::new();
+    registry.register(a);
+    registry.register(b);
+    registry.register(c);
+    assert_eq!(registry.names(), vec!["a", "b", "c"]);
+
+    // one failing notifier doesn't fail the delivery
+    registry.notify(&firing("fan out"))?;
+    for firings in [a_firings, b_firings, c_firings] {
+        assert_eq!(firings.try_recv()?.task.description, "fan out");
+    }
+    Ok(())
+}
+
+#[test]
+fn registry_counts_occurrences() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let history = History::new(&fmn_dir);
+    let firing = firing("water #{occurrence}");
+    // fired once already before the daemon restarted
+    history.record(&FiringRecord::new(&firing))?;
+
+    let (a, a_firings) = Recorder::new("a", false);
+    let mut registry = NotifierRegistry::new().with_history(history);
+    registry.register(a);
+    registry.notify(&firing)?;
+    registry.notify(&firing)?;
+    assert_eq!(a_firings.try_recv()?.task.description, "water #2");
+    assert_eq!(a_firings.try_recv()?.task.description, "water #3");
+    Ok(())
+}
+
+#[test]
+fn fail_when_no_notifier_delivers() {
+    let (a, _a_firings) = Recorder::new("a", true);
+    let (b, _b_firings) = Recorder::new("b", true);
+    let mut registry = NotifierRegistry::new();
+    registry.register(a);
+    registry.register(b);
+    assert!(registry.notify(&firing("nobody")).is_err());
+}
+
+#[test]
+fn scheduler_delivers_to_registry() -> Result<()> {
+    let (recorder, firings) = Recorder::new("recorder", false);
+    let mut registry = NotifierRegistry::new();
+    registry.register(recorder);
+    let mut scheduler = Scheduler::with_notifiers(registry);
+    let next_fire = get_local_now() + Duration::from_secs(1);
+    let task = Task::new("scheduled".to_owned(), ClockType::Once(next_fire));
+    scheduler.add_task(task.clone())?;
+
+    let firing = firings.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(firing.task.task_id, task.task_id);
+    assert_eq!(firing.scheduled_at, next_fire);
+    assert!(firing.fired_at >= next_fire);
+    Ok(())
+}
+
+#[test]
+fn tasks_fire_with_the_current_context_settings() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let (recorder, firings) = Recorder::new("recorder", false);
+    let mut registry = NotifierRegistry::new();
+    registry.register(recorder);
+    let mut tm = TaskManager::new(&fmn_dir, Scheduler::with_notifiers(registry))?;
+    let critical = ContextSettings {
+        urgency: Some(Urgency::Critical),
+        ..Default::default()
+    };
+    tm.define_context("work".to_owned(), critical)?;
+    tm.switch_context("work".to_owned())?;
+    let next_fire = get_local_now() + Duration::from_secs(1);
+    let mut task = Task::new("standup".to_owned(), ClockType::Once(next_fire));
+    task.context = "work".to_owned();
+    tm.add_task(task)?;
+
+    // configured after the task was added
+    let notifiers = ContextSettings {
+        notifiers: vec!["recorder".to_owned()],
+        ..Default::default()
+    };
+    tm.configure_context("work".to_owned(), notifiers, false)?;
+    let firing = firings.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(firing.task.notification.urgency, Some(Urgency::Critical));
+    assert_eq!(firing.task.notification.notifiers, ["recorder"]);
+
+    let pager = ContextSettings {
+        notifiers: vec!["pager".to_owned()],
+        ..Default::default()
+    };
+    let error = tm
+        .configure_context("work".to_owned(), pager, false)
+        .unwrap_err();
+    assert!(
+        error.to_string().contains("no notifier named pager"),
+        "{error}"
+    );
+    let mut task = Task::new("retro".to_owned(), ClockType::Period("1h".to_owned()));
+    task.notification.notifiers = vec!["pager".to_owned()];
+    assert!(tm.add_task(task).is_err());
+    Ok(())
+}
+
+// the tasks stored in `fmn_dir` once there are `count` of them
+fn wait_for_tasks(fmn_dir: &std::path::Path, count: usize) -> Result<Vec<Task>> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let tasks = TaskManager::new(fmn_dir, Scheduler::new())?.get_tasks();
+        if tasks.len() >= count || Instant::now() > deadline {
+            return Ok(tasks);
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn snoozing_a_notification_saves_the_stored_task() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let mut tm = TaskManager::new(&fmn_dir, Scheduler::new())?;
+    let task = Task::new(
+        "Drink water ({occurrence})".to_owned(),
+        ClockType::Period("1h".to_owned()),
+    )
+    .with_context("default".to_owned());
+    tm.add_task(task.clone())?;
+    tm.refresh_after()?;
+    let (sender, actions) = channel();
+    forward_actions(actions, Arc::new(Mutex::new(tm)));
+
+    // as the notifiers got it, with the text rendered and the settings of
+    // the context filled in
+    let shown = |task: &Task| {
+        let mut firing = Firing::new(task.clone(), get_local_now());
+        firing.task.description = "Drink water (3)".to_owned();
+        firing.task.notification.urgency = Some(Urgency::Critical);
+        firing
+    };
+    let after = Duration::from_secs(600);
+    sender.send(TaskAction::Snooze(shown(&task), after))?;
+    let tasks = wait_for_tasks(fmn_dir.path(), 2)?;
+    assert_eq!(tasks.len(), 2);
+    let snoozed = tasks.iter().find(|t| t.task_id != task.task_id).unwrap();
+    assert_eq!(snoozed.description, "Drink water ({occurrence})");
+    assert_eq!(snoozed.notification.urgency, None);
+    assert!(matches!(snoozed.clock_type, ClockType::Once(_)));
+
+    // a once task is gone by the time it is snoozed
+    let gone = Task::new("call mum".to_owned(), ClockType::Once(get_local_now()))
+        .with_context("default".to_owned());
+    sender.send(TaskAction::Snooze(shown(&gone), after))?;
+    let tasks = wait_for_tasks(fmn_dir.path(), 3)?;
+    let snoozed = tasks.iter().find(|t| t.description == "call mum");
+    assert_eq!(snoozed.unwrap().notification.urgency, None);
+    Ok(())
+}
+
+#[test]
+fn periodic_task_survives_failed_delivery() -> Result<()> {
+    let (recorder, firings) = Recorder::new("down", true);
+    let mut registry = NotifierRegistry::new();
+    registry.register(recorder);
+    let mut scheduler = Scheduler::with_notifiers(registry);
+    let task = Task::new("keep going".to_owned(), ClockType::Period("1s".to_owned()));
+    scheduler.add_task(task.clone())?;
+
+    for _ in 0..2 {
+        let firing = firings.recv_timeout(Duration::from_secs(5))?;
+        assert_eq!(firing.task.task_id, task.task_id);
+    }
+    scheduler.cancel_task(task)?;
+    Ok(())
+}
+
+// a notifier failing its first `failures` firings
+struct Flaky {
+    failures: Mutex<u32>,
+    sender: Mutex<Sender<Firing>>,
+}
+
+impl Notifier for Flaky {
+    fn name(&self) -> &str {
+        "flaky"
+    }
+
+    fn notify(&self, firing: &Firing) -> Result<()> {
+        let mut failures = self.failures.lock().unwrap();
+        if *failures > 0 {
+            *failures -= 1;
+            return Err(anyhow!("notification server is restarting"));
+        }
+        self.sender.lock().unwrap().send(firing.clone())?;
+        Ok(())
+    }
+}
+
+#[test]
+fn registry_retries_undelivered_firings() -> Result<()> {
+    let (sender, firings) = channel();
+    let flaky = Flaky {
+        failures: Mutex::new(2),
+        sender: Mutex::new(sender),
+    };
+    let mut registry = NotifierRegistry::new().with_retry(RetryPolicy {
+        initial_secs: 0,
+        max_secs: 0,
+        max_attempts: 5,
+    });
+    registry.register(Arc::new(flaky));
+    // queued for a retry, so the firing isn't lost
+    registry.notify(&firing("try again"))?;
+    let firing = firings.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(firing.task.description, "try again");
+
+    let (down, _) = Recorder::new("down", true);
+    let mut registry = NotifierRegistry::new();
+    registry.register(down);
+    assert!(registry.notify(&firing).is_err());
+    Ok(())
+}
+
+#[test]
+fn any_notifier_retries() -> Result<()> {
+    let config: NotifierConfig =
+        serde_json::from_str(r#"{"kind": "log", "name": "logged", "retry": {"initial_secs": 5}}"#)?;
+    assert_eq!(config.retry.as_ref().map(|r| r.initial_secs), Some(5));
+    assert_eq!(config.build()?.name(), "logged");
+    Ok(())
+}
+
+#[test]
+fn registry_from_config() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    assert_eq!(
+        NotifierRegistry::from_config(&Config::load(&fmn_dir)?.notifiers)?.names(),
+        vec!["desktop"]
+    );
+
+    std::fs::write(
+        fmn_dir.path().join("config.json"),
+        r#"{"notifiers": [{"kind": "log"}, {"kind": "desktop", "name": "de"}]}"#,
+    )?;
+    let config = Config::load(&fmn_dir)?;
+    let registry = NotifierRegistry::from_config(&config.notifiers)?;
+    assert_eq!(registry.names(), vec!["log", "de"]);
+    Ok(())
+}
+
+#[test]
+fn desktop_snooze_config() -> Result<()> {
+    let config: NotifierConfig =
+        serde_json::from_str(r#"{"kind": "desktop", "snooze": "5m", "actions": false}"#)?;
+    assert_eq!(config.build()?.name(), "desktop");
+    let config: NotifierConfig =
+        serde_json::from_str(r#"{"kind": "desktop", "snooze": "5 parsecs"}"#)?;
+    assert!(config.build().is_err());
+    Ok(())
+}
+
+fn webhook(url: &str, extra: &str) -> Result<Arc<dyn Notifier>> {
+    let config: NotifierConfig =
+        serde_json::from_str(&format!(r#"{{"kind": "webhook", "url": "{url}" {extra}}}"#))?;
+    config.build()
+}
+
+#[test]
+fn webhook_posts_payload() -> Result<()> {
+    let (url, requests) = http_stand_in(vec![]);
+    let notifier = webhook(
+        &format!("{url}/hook"),
+        r#", "headers": {"X-Token": "secret"}"#,
+    )?;
+    let firing = firing("post me");
+    notifier.notify(&firing)?;
+
+    let request = requests.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/hook");
+    assert_eq!(request.headers["x-token"], "secret");
+    assert_eq!(request.headers["content-type"], "application/json");
+    let payload: FiringPayload = serde_json::from_str(&request.body)?;
+    assert_eq!(payload, FiringPayload::from(&firing));
+    Ok(())
+}
+
+#[test]
+fn webhook_posts_once() -> Result<()> {
+    let (url, requests) = http_stand_in(vec![500]);
+    assert!(webhook(&url, "")?.notify(&firing("once")).is_err());
+    assert_eq!(requests.try_iter().count(), 1);
+
+    let (url, requests) = http_stand_in(vec![404]);
+    assert!(webhook(&url, "")?.notify(&firing("not found")).is_err());
+    assert_eq!(requests.try_iter().count(), 1);
+    Ok(())
+}
+
+#[test]
+fn webhook_is_retried_in_the_background() -> Result<()> {
+    let (url, requests) = http_stand_in(vec![500, 503, 200]);
+    let configs: Vec<NotifierConfig> = serde_json::from_str(&format!(
+        r#"[{{"kind": "webhook", "url": "{url}", "retry": {{"initial_secs": 0, "max_secs": 0}}}}]"#
+    ))?;
+    NotifierRegistry::from_config(&configs)?.notify(&firing("retry"))?;
+    for _ in 0..3 {
+        let request = requests.recv_timeout(Duration::from_secs(5))?;
+        assert_eq!(request.path, "/");
+    }
+    assert!(requests.recv_timeout(Duration::from_millis(300)).is_err());
+    Ok(())
+}
+
+#[test]
+fn webhook_is_retried_when_others_delivered() -> Result<()> {
+    let (url, requests) = http_stand_in(vec![500, 200]);
+    let configs: Vec<NotifierConfig> =
+        serde_json::from_str(&format!(r#"[{{"kind": "webhook", "url": "{url}"}}]"#))?;
+    let mut registry = NotifierRegistry::from_config(&configs)?.with_retry(RetryPolicy {
+        initial_secs: 0,
+        max_secs: 0,
+        ..Default::default()
+    });
+    registry.register(Arc::new(Screen));
+    // shown on the desktop, while the webhook failed
+    registry.notify(&firing("retry"))?;
+    for _ in 0..2 {
+        requests.recv_timeout(Duration::from_secs(5))?;
+    }
+    assert!(requests.recv_timeout(Duration::from_millis(300)).is_err());
+    Ok(())
+}
+
+#[test]
+fn route_by_context_and_task() -> Result<()> {
+    let (url, requests) = http_stand_in(vec![]);
+    let configs: Vec<NotifierConfig> = serde_json::from_str(&format!(
+        r#"[{{"kind": "webhook", "url": "{url}", "contexts": ["work"], "tasks": ["abc"]}}]"#
+    ))?;
+    let registry = NotifierRegistry::from_config(&configs)?;
+
+    registry.notify(&firing("elsewhere"))?;
+    let mut work = firing("at work");
+    work.task = work.task.with_context("work".to_owned());
+    registry.notify(&work)?;
+    let mut task = firing("by task id");
+    task.task.task_id = "abcdef".to_owned();
+    registry.notify(&task)?;
+
+    let descriptions: Vec<String> = requests
+        .iter()
+        .take(2)
+        .map(|r| {
+            serde_json::from_str::<FiringPayload>(&r.body)
+                .unwrap()
+                .description
+        })
+        .collect();
+    assert_eq!(descriptions, vec!["at work", "by task id"]);
+    assert!(requests.try_recv().is_err());
+    Ok(())
+}
+
+fn email(port: u16, extra: &str) -> Result<Arc<dyn Notifier>> {
+    let config: NotifierConfig = serde_json::from_str(&format!(
+        r#"{{"kind": "email", "host": "127.0.0.1", "port": {port}, "security": "plain",
+            "from": "fmn <fmn@example.com>", "to": ["me@example.com"] {extra}}}"#
+    ))?;
+    config.build()
+}
+
+#[test]
+fn email_sends_rendered_message() -> Result<()> {
+    let (port, mails) = smtp_stand_in(0);
+    let credentials = tempfile::NamedTempFile::new()?;
+    std::fs::write(credentials.path(), "me\nhunter2\n")?;
+    let notifier = email(
+        port,
+        &format!(
+            r#", "credentials_file": {:?}, "subject": "due: {{description}}""#,
+            credentials.path()
+        ),
+    )?;
+    notifier.notify(&firing("renew passport"))?;
+
+    let mail = mails.recv_timeout(Duration::from_secs(5))?;
+    assert!(mail.auth.is_some());
+    assert_eq!(mail.from, "<fmn@example.com>");
+    assert_eq!(mail.to, vec!["<me@example.com>"]);
+    assert!(mail.data.contains("Subject: due: renew passport"));
+    assert!(mail.data.contains("every 1h"));
+    Ok(())
+}
+
+#[test]
+fn email_failure_is_an_error() -> Result<()> {
+    let (port, _mails) = smtp_stand_in(1);
+    assert!(email(port, "")?.notify(&firing("deadline")).is_err());
+    Ok(())
+}
+
+#[test]
+fn email_is_retried_after_the_fallbacks() -> Result<()> {
+    let (port, mails) = smtp_stand_in(2);
+    let tty = tempfile::NamedTempFile::new()?;
+    let configs: Vec<NotifierConfig> = serde_json::from_str(&format!(
+        r#"[{{"kind": "email", "host": "127.0.0.1", "port": {port}, "security": "plain",
+              "from": "fmn <fmn@example.com>", "to": ["me@example.com"],
+              "retry": {{"initial_secs": 0}}}},
+            {{"kind": "tty", "ttys": [{:?}], "fallback": true}}]"#,
+        tty.path()
+    ))?;
+    NotifierRegistry::from_config(&configs)?.notify(&firing("deadline"))?;
+
+    // the fallback shows it right away, and the mail follows
+    assert!(std::fs::read_to_string(tty.path())?.contains("deadline"));
+    let mail = mails.recv_timeout(Duration::from_secs(5))?;
+    assert!(mail.data.contains("deadline"));
+    Ok(())
+}
+
+// the history record of the latest firing, once `done` says it is complete
+fn wait_for_record<F>(history: &History, done: F) -> Result<FiringRecord>
+where
+    F: Fn(&FiringRecord) -> bool,
+{
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let record = history.latest(1)?.pop();
+        match record {
+            Some(record) if done(&record) => return Ok(record),
+            _ if Instant::now() > deadline => return Err(anyhow!("no record {:?}", record)),
+            _ => std::thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+#[test]
+fn history_keeps_the_latest_records() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let history = History::new(&fmn_dir).with_max_bytes(2000);
+    let firings: Vec<Firing> = (0..30).map(|i| firing(&format!("drink {i}"))).collect();
+    for firing in &firings {
+        history.record(&FiringRecord::new(firing))?;
+    }
+    let size = std::fs::metadata(fmn_dir.path().join("history.data"))?.len();
+    assert!(size <= 2000, "{size} bytes");
+    let records = history.latest(100)?;
+    assert!(records.len() < 30);
+    assert_eq!(records.last().unwrap().description, "drink 29");
+
+    // changing a record keeps the ones around it
+    let changed = &firings[27];
+    history.update(changed, |record| record.hooks.push(HookRun::default()))?;
+    history.acknowledge(&firings[29].task.task_id)?;
+    let after = history.latest(100)?;
+    assert_eq!(after.len(), records.len());
+    for (before, after) in records.iter().zip(&after) {
+        assert_eq!(before.description, after.description);
+    }
+    let [.., two_before, _, last] = after.as_slice() else {
+        panic!("too few records: {after:?}");
+    };
+    assert_eq!(two_before.hooks.len(), 1);
+    assert!(last.acknowledged_at.is_some());
+    assert!(history.update(&firings[0], |_| {}).is_err());
+    Ok(())
+}
+
+#[test]
+fn retry_outcome_is_recorded() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let history = History::new(&fmn_dir);
+    let (url, requests) = http_stand_in(vec![500, 500]);
+    let configs: Vec<NotifierConfig> = serde_json::from_str(&format!(
+        r#"[{{"kind": "webhook", "url": "{url}", "retry": {{"initial_secs": 0, "max_secs": 0}}}}]"#
+    ))?;
+    let registry = NotifierRegistry::from_config(&configs)?.with_history(history.clone());
+    registry.notify(&firing("retry"))?;
+    let record = wait_for_record(&history, |r| r.deliveries[0].error.is_none())?;
+    assert_eq!(record.deliveries[0].retries, 2);
+    assert_eq!(requests.try_iter().count(), 3);
+
+    // or how it gave up
+    let (url, _requests) = http_stand_in(vec![500, 500, 500]);
+    let configs: Vec<NotifierConfig> = serde_json::from_str(&format!(
+        r#"[{{"kind": "webhook", "url": "{url}",
+              "retry": {{"initial_secs": 0, "max_secs": 0, "max_attempts": 2}}}}]"#
+    ))?;
+    let registry = NotifierRegistry::from_config(&configs)?.with_history(history.clone());
+    registry.notify(&firing("give up"))?;
+    let record = wait_for_record(&history, |r| r.deliveries[0].retries > 0)?;
+    assert_eq!(record.deliveries[0].retries, 2);
+    assert!(record.deliveries[0]
+        .error
+        .as_ref()
+        .unwrap()
+        .starts_with("gave up"));
+    Ok(())
+}
+
+#[test]
+fn retries_end_with_the_task() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let history = History::new(&fmn_dir);
+    let (url, requests) = http_stand_in(vec![500; 10]);
+    let configs: Vec<NotifierConfig> = serde_json::from_str(&format!(
+        r#"[{{"kind": "webhook", "url": "{url}", "retry": {{"initial_secs": 2}}}}]"#
+    ))?;
+    let registry = NotifierRegistry::from_config(&configs)?.with_history(history.clone());
+
+    // acknowledged or cancelled
+    let forgotten = firing("forgotten");
+    registry.notify(&forgotten)?;
+    registry.forget(&forgotten.task.task_id);
+    // fired again meanwhile
+    let mut stale = firing("stale");
+    stale.task.clock_type = ClockType::Period("1s".to_owned());
+    registry.notify(&stale)?;
+
+    let record = wait_for_record(&history, |r| {
+        r.deliveries[0].retries == 0 && {
+            r.deliveries[0].error.as_deref() == Some("gave up: the task fired again")
+        }
+    })?;
+    assert_eq!(record.description, "stale");
+    std::thread::sleep(Duration::from_millis(500));
+    assert_eq!(requests.try_iter().count(), 2);
+    Ok(())
+}
+
+#[test]
+fn hooks_run_with_task_env() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let history = History::new(&fmn_dir);
+    let hook = HookConfig {
+        command: Some("echo global $FMN_TASK_ID".to_owned()),
+        timeout_secs: 5,
+    };
+    let registry = NotifierRegistry::new()
+        .with_hook(hook)
+        .with_history(history.clone());
+    let mut firing = firing("backup");
+    firing
+        .task
+        .add_exec("echo $FMN_DESCRIPTION; echo oops >&2; exit 3".to_owned());
+    registry.notify(&firing)?;
+
+    let record = wait_for_record(&history, |r| !r.hooks.is_empty())?;
+    assert_eq!(history.latest(10)?.len(), 1);
+    let hooks = &record.hooks;
+    assert_eq!(hooks.len(), 2);
+    assert_eq!(hooks[0].stdout, format!("global {}\n", firing.task.task_id));
+    assert_eq!(hooks[0].exit_status, Some(0));
+    assert_eq!(hooks[1].stdout, "backup\n");
+    assert_eq!(hooks[1].stderr, "oops\n");
+    assert_eq!(hooks[1].exit_status, Some(3));
+    Ok(())
+}
+
+#[test]
+fn hook_timeout() {
+    let start = Instant::now();
+    let run = run_hook(
+        "echo started; sleep 5",
+        &firing("slow"),
+        Duration::from_millis(200),
+    );
+    assert!(run.timed_out);
+    assert_eq!(run.exit_status, None);
+    assert_eq!(run.stdout, "started\n");
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn hook_returns_while_its_background_process_runs() {
+    let start = Instant::now();
+    // the background process holds on to the output of the hook
+    let run = run_hook(
+        "echo started; sleep 5 &",
+        &firing("detached"),
+        Duration::from_secs(3),
+    );
+    assert!(!run.timed_out);
+    assert_eq!(run.exit_status, Some(0));
+    assert_eq!(run.stdout, "started\n");
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn fallback_only_when_primaries_fail() -> Result<()> {
+    let (primary, _) = Recorder::new("primary", true);
+    let (broken, broken_firings) = Recorder::new("broken", true);
+    let (tty, tty_firings) = Recorder::new("tty", false);
+    let (bell, bell_firings) = Recorder::new("bell", false);
+    let mut registry = NotifierRegistry::new();
+    registry.register(primary);
+    registry.register_fallback(broken, Routing::default());
+    registry.register_fallback(tty, Routing::default());
+    registry.register_fallback(bell, Routing::default());
+
+    // fallbacks are tried in order until one delivers
+    registry.notify(&firing("over ssh"))?;
+    assert!(broken_firings.try_recv().is_ok());
+    assert!(tty_firings.try_recv().is_ok());
+    assert!(bell_firings.try_recv().is_err());
+
+    let (desktop, desktop_firings) = Recorder::new("desktop", false);
+    let (tty, tty_firings) = Recorder::new("tty", false);
+    let mut registry = NotifierRegistry::new();
+    registry.register(desktop);
+    registry.register_fallback(tty, Routing::default());
+    registry.notify(&firing("on desktop"))?;
+    assert!(desktop_firings.try_recv().is_ok());
+    assert!(tty_firings.try_recv().is_err());
+    Ok(())
+}
+
+#[test]
+fn tty_writes_reminder() -> Result<()> {
+    let tty = tempfile::NamedTempFile::new()?;
+    let config: NotifierConfig = serde_json::from_str(&format!(
+        r#"{{"kind": "tty", "ttys": [{:?}], "bell": true, "fallback": true}}"#,
+        tty.path()
+    ))?;
+    assert!(config.fallback);
+    config.build()?.notify(&firing("stand up"))?;
+    let written = std::fs::read_to_string(tty.path())?;
+    assert!(written.contains("\x07Reminder from forget-me-not"));
+    assert!(written.contains("    stand up\r\n"));
+
+    let config: NotifierConfig = serde_json::from_str(&format!(
+        r#"{{"kind": "bell", "ttys": [{:?}]}}"#,
+        tty.path()
+    ))?;
+    config.build()?.notify(&firing("ding"))?;
+    assert!(std::fs::read_to_string(tty.path())?.ends_with('\x07'));
+    Ok(())
+}
+
+#[test]
+fn tty_uses_task_summary() -> Result<()> {
+    let tty = tempfile::NamedTempFile::new()?;
+    let config: NotifierConfig =
+        serde_json::from_str(&format!(r#"{{"kind": "tty", "ttys": [{:?}]}}"#, tty.path()))?;
+    let mut firing = firing("drink water");
+    firing.task.notification.summary = Some("Hydrate".to_owned());
+    config.build()?.notify(&firing)?;
+    assert!(std::fs::read_to_string(tty.path())?.contains("Reminder from Hydrate"));
+    Ok(())
+}
+
+#[test]
+fn tty_escapes_control_characters() -> Result<()> {
+    let tty = tempfile::NamedTempFile::new()?;
+    let config: NotifierConfig =
+        serde_json::from_str(&format!(r#"{{"kind": "tty", "ttys": [{:?}]}}"#, tty.path()))?;
+    let mut firing = firing("\x1b]2;owned\x07\x1b[2J\nsecond line\r");
+    firing.task.notification.summary = Some("\x1b[31mred\u{9b}".to_owned());
+    config.build()?.notify(&firing)?;
+    let written = std::fs::read_to_string(tty.path())?;
+    assert!(!written.contains('\x1b'), "{written:?}");
+    assert!(written.contains("Reminder from ^[[31mred? ("));
+    assert!(written.contains("    ^[]2;owned^G^[[2J\r\n    second line^M\r\n"));
+    Ok(())
+}
+
+#[test]
+fn tmux_displays_on_clients() -> Result<()> {
+    let dir = tempdir()?;
+    let log = dir.path().join("tmux.log");
+    let tmux = dir.path().join("tmux");
+    std::fs::write(
+        &tmux,
+        format!(
+            "#!/bin/sh\necho \"$@\" >> {:?}\n[ \"$3\" = list-clients ] && echo /dev/pts/7\nexit 0\n",
+            log
+        ),
+    )?;
+    std::process::Command::new("chmod")
+        .arg("+x")
+        .arg(&tmux)
+        .status()?;
+    let config: NotifierConfig = serde_json::from_str(&format!(
+        r#"{{"kind": "tmux", "command": {:?}, "socket": "/tmp/fmn-tmux"}}"#,
+        tmux
+    ))?;
+    config.build()?.notify(&firing("issue #1"))?;
+    let calls = std::fs::read_to_string(&log)?;
+    assert_eq!(
+        calls,
+        "-S /tmp/fmn-tmux list-clients -F #{client_name}\n\
+         -S /tmp/fmn-tmux display-message -c /dev/pts/7 -d 5000 forget-me-not: issue ##1\n"
+    );
+
+    let config: NotifierConfig = serde_json::from_str(&format!(
+        r#"{{"kind": "tmux", "command": {:?}, "mode": "status"}}"#,
+        tmux
+    ))?;
+    config.build()?.notify(&firing("status"))?;
+    let calls = std::fs::read_to_string(&log)?;
+    assert!(calls.ends_with("set-option -g @fmn_reminder forget-me-not: status\n"));
+    Ok(())
+}
+
+#[test]
+fn sound_falls_back_through_players() -> Result<()> {
+    let dir = tempdir()?;
+    let log = dir.path().join("player.log");
+    let failing = dir.path().join("failing");
+    let player = dir.path().join("player");
+    std::fs::write(&failing, "#!/bin/sh\necho no device >&2\nexit 1\n")?;
+    std::fs::write(&player, format!("#!/bin/sh\necho \"$@\" >> {log:?}\n"))?;
+    std::process::Command::new("chmod")
+        .arg("+x")
+        .arg(&failing)
+        .arg(&player)
+        .status()?;
+    let sound = SoundConfig {
+        players: vec![
+            "/nonexistent/pw-play {path}".to_owned(),
+            format!("{} {{path}}", failing.display()),
+            format!("{} --volume={{volume}} {{path}}", player.display()),
+        ],
+        ..Default::default()
+    };
+    let mut task = Task::new("ring".to_owned(), ClockType::Period("1h".to_owned()));
+    assert!(play_sound(&sound, &task).is_none());
+
+    task.add_sound("/tmp/bell.wav".to_owned());
+    task.notification.volume = Some(40);
+    task.notification.repeat = Some(2);
+    let run = play_sound(&sound, &task).unwrap();
+    assert_eq!(run.player, Some(player.display().to_string()));
+    assert_eq!(run.plays, 2);
+    assert_eq!(
+        std::fs::read_to_string(&log)?,
+        "--volume=40 /tmp/bell.wav\n--volume=40 /tmp/bell.wav\n"
+    );
+
+    let sound = SoundConfig {
+        players: vec![format!("{} {{path}}", failing.display())],
+        ..Default::default()
+    };
+    let run = play_sound(&sound, &task).unwrap();
+    assert_eq!(run.player, None);
+    assert!(run.error.unwrap().contains("no device"));
+
+    // a player that hangs is killed
+    let hanging = dir.path().join("hanging");
+    std::fs::write(&hanging, "#!/bin/sh\nexec sleep 10\n")?;
+    std::process::Command::new("chmod")
+        .arg("+x")
+        .arg(&hanging)
+        .status()?;
+    let sound = SoundConfig {
+        players: vec![format!("{} {{path}}", hanging.display())],
+        timeout_secs: 1,
+    };
+    let start = Instant::now();
+    let run = play_sound(&sound, &task).unwrap();
+    assert!(run.error.unwrap().contains("killed"));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    Ok(())
+}
+
+#[test]
+fn sound_goes_with_desktop_notifications() -> Result<()> {
+    let dir = tempdir()?;
+    let log = dir.path().join("player.log");
+    let player = dir.path().join("player");
+    std::fs::write(&player, format!("#!/bin/sh\necho \"$@\" >> {log:?}\n"))?;
+    std::process::Command::new("chmod")
+        .arg("+x")
+        .arg(&player)
+        .status()?;
+    let sound = SoundConfig {
+        players: vec![format!("{} {{path}}", player.display())],
+        ..Default::default()
+    };
+    let ringing = |description: &str| {
+        let mut firing = firing(description);
+        firing.task.add_sound("/tmp/bell.wav".to_owned());
+        firing
+    };
+    let wait_for_players = || std::thread::sleep(Duration::from_millis(500));
+
+    // nothing to hear without a notification to see
+    let (tty, _tty_firings) = Recorder::new("tty", false);
+    let mut registry = NotifierRegistry::new().with_sound(sound.clone());
+    registry.register(tty);
+    registry.notify(&ringing("unseen"))?;
+    wait_for_players();
+    assert!(!log.exists());
+
+    // a digest rings once
+    let mut registry = NotifierRegistry::new()
+        .with_sound(sound)
+        .with_digest(DigestConfig {
+            window_ms: 300,
+            max_per_minute: None,
+        });
+    registry.register(Arc::new(Screen));
+    for description in ["one", "two", "three"] {
+        registry.notify(&ringing(description))?;
+    }
+    wait_for_players();
+    wait_for_players();
+    assert_eq!(std::fs::read_to_string(&log)?, "/tmp/bell.wav\n");
+    Ok(())
+}
+
+#[test]
+fn sound_failure_is_recorded() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let history = History::new(&fmn_dir);
+    let sound = SoundConfig {
+        players: vec!["/nonexistent/player {path}".to_owned()],
+        ..Default::default()
+    };
+    let mut registry = NotifierRegistry::new()
+        .with_sound(sound)
+        .with_history(history.clone());
+    registry.register(Arc::new(Screen));
+    let mut firing = firing("ring");
+    firing.task.add_sound("/tmp/bell.wav".to_owned());
+    registry.notify(&firing)?;
+
+    let record = wait_for_record(&history, |r| r.sound.is_some())?;
+    let sound = record.sound.as_ref().unwrap();
+    assert_eq!(sound.path, "/tmp/bell.wav");
+    assert!(sound.error.as_ref().unwrap().contains("is available"));
+    Ok(())
+}
+
+#[test]
+fn digest_coalesces_firings() -> Result<()> {
+    let (all, all_firings) = Recorder::new("all", false);
+    let (work, work_firings) = Recorder::new("work", false);
+    let mut registry = NotifierRegistry::new().with_digest(DigestConfig {
+        window_ms: 300,
+        max_per_minute: None,
+    });
+    registry.register(all);
+    registry.register_routed(
+        work,
+        Routing {
+            contexts: vec!["work".to_owned()],
+            tasks: vec![],
+        },
+    );
+    registry.notify(&firing("stretch"))?;
+    let mut standup = firing("standup");
+    standup.task = standup.task.with_context("work".to_owned());
+    registry.notify(&standup)?;
+    registry.notify(&firing("drink water"))?;
+
+    let digest = all_firings.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(
+        digest.task.notification.summary.as_deref(),
+        Some("3 reminders")
+    );
+    assert_eq!(
+        digest.task.description,
+        "- stretch\n- standup\n- drink water"
+    );
+    // there is no task to be done or snoozed behind it
+    assert!(!digest.takes_actions);
+    // a notifier routed to only one of them gets it as it is
+    let single = work_firings.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(single.task.description, "standup");
+    assert!(single.takes_actions);
+    assert!(all_firings
+        .recv_timeout(Duration::from_millis(500))
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn digest_limits_notifications_per_minute() -> Result<()> {
+    let (a, a_firings) = Recorder::new("a", false);
+    let mut registry = NotifierRegistry::new().with_digest(DigestConfig {
+        window_ms: 0,
+        max_per_minute: Some(2),
+    });
+    registry.register(a);
+    for description in ["one", "two", "three", "four"] {
+        registry.notify(&firing(description))?;
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(
+        a_firings
+            .recv_timeout(Duration::from_secs(5))?
+            .task
+            .description,
+        "one"
+    );
+    assert_eq!(
+        a_firings
+            .recv_timeout(Duration::from_secs(5))?
+            .task
+            .description,
+        "two"
+    );
+    // the rest waits for the next minute
+    assert!(a_firings.recv_timeout(Duration::from_millis(300)).is_err());
+    Ok(())
+}
+
+#[test]
+fn ntfy_publishes_json() -> Result<()> {
+    let (url, requests) = http_stand_in(vec![]);
+    let token = tempfile::NamedTempFile::new()?;
+    std::fs::write(token.path(), "tk_secret\n")?;
+    let config: NotifierConfig = serde_json::from_str(&format!(
+        r#"{{"kind": "ntfy", "server": "{url}/", "topic": "reminders", "tags": ["fmn"],
+            "token_file": {:?}}}"#,
+        token.path()
+    ))?;
+    let mut firing = firing("standup https://meet.example.com/abc");
+    firing.task = firing.task.with_context("work".to_owned());
+    firing.task.notification.urgency = Some(Urgency::Critical);
+    config.build()?.notify(&firing)?;
+
+    let request = requests.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.headers["authorization"], "Bearer tk_secret");
+    let message: serde_json::Value = serde_json::from_str(&request.body)?;
+    assert_eq!(message["topic"], "reminders");
+    assert_eq!(message["title"], "forget-me-not");
+    assert_eq!(message["message"], "standup https://meet.example.com/abc");
+    assert_eq!(message["priority"], 5);
+    assert_eq!(message["tags"], serde_json::json!(["fmn", "work"]));
+    assert_eq!(message["click"], "https://meet.example.com/abc");
+    Ok(())
+}
+
+#[test]
+fn ntfy_uploads_image() -> Result<()> {
+    let (url, requests) = http_stand_in(vec![]);
+    let dir = tempdir()?;
+    let image = dir.path().join("cat.png");
+    std::fs::write(&image, "pixels")?;
+    let config: NotifierConfig = serde_json::from_str(&format!(
+        r#"{{"kind": "ntfy", "server": "{url}", "topic": "reminders", "click": "https://example.com"}}"#
+    ))?;
+    let mut firing = firing("café");
+    firing.task.add_image(image.display().to_string());
+    config.build()?.notify(&firing)?;
+
+    let request = requests.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(request.method, "PUT");
+    assert_eq!(request.path, "/reminders");
+    assert_eq!(request.body, "pixels");
+    assert_eq!(request.headers["x-filename"], "cat.png");
+    assert_eq!(request.headers["x-priority"], "3");
+    assert_eq!(request.headers["x-message"], "=?UTF-8?B?Y2Fmw6k=?=");
+    assert_eq!(request.headers["x-click"], "https://example.com");
+    Ok(())
+}
+
+#[test]
+fn gotify_posts_message() -> Result<()> {
+    let (url, requests) = http_stand_in(vec![]);
+    let token = tempfile::NamedTempFile::new()?;
+    std::fs::write(token.path(), "AppToken")?;
+    let config: NotifierConfig = serde_json::from_str(&format!(
+        r#"{{"kind": "gotify", "server": "{url}", "token_file": {:?}}}"#,
+        token.path()
+    ))?;
+    let mut firing = firing("water the plants");
+    firing.task.notification.urgency = Some(Urgency::Low);
+    firing.task.notification.summary = Some("Garden".to_owned());
+    firing
+        .task
+        .add_image("https://example.com/plant.png".to_owned());
+    config.build()?.notify(&firing)?;
+
+    let request = requests.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(request.path, "/message");
+    assert_eq!(request.headers["x-gotify-key"], "AppToken");
+    let message: serde_json::Value = serde_json::from_str(&request.body)?;
+    assert_eq!(message["title"], "Garden");
+    assert_eq!(message["message"], "water the plants");
+    assert_eq!(message["priority"], 2);
+    assert_eq!(
+        message["extras"]["client::notification"]["bigImageUrl"],
+        "https://example.com/plant.png"
+    );
+    Ok(())
+}
+
+// the broker only accepts publishes once the client is connected
+fn notify_when_connected(notifier: &dyn Notifier, firing: &Firing) -> Result<()> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        match notifier.notify(firing) {
+            Err(e) if Instant::now() < deadline && e.to_string().contains("not connected") => {
+                std::thread::sleep(Duration::from_millis(50))
+            }
+            result => return result,
+        }
+    }
+}
+
+#[test]
+fn mqtt_publishes_firing() -> Result<()> {
+    let broker = mqtt_stand_in();
+    let config: NotifierConfig = serde_json::from_str(&format!(
+        r#"{{"kind": "mqtt", "host": "127.0.0.1", "port": {}, "retain": true}}"#,
+        broker.port
+    ))?;
+    let notifier = config.build()?;
+    let mut firing = firing("stand up");
+    firing.task = firing.task.with_context("work".to_owned());
+    notify_when_connected(notifier.as_ref(), &firing)?;
+
+    let message = broker.messages.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(message.topic, format!("fmn/work/{}", firing.task.task_id));
+    assert_eq!(message.qos, 1);
+    assert!(message.retain);
+    let payload: FiringPayload = serde_json::from_slice(&message.payload)?;
+    assert_eq!(payload, FiringPayload::from(&firing));
+    Ok(())
+}
+
+#[test]
+fn mqtt_ack_topic_acknowledges_tasks() -> Result<()> {
+    let actions = action_receiver().ok_or(anyhow!("actions are taken"))?;
+    let broker = mqtt_stand_in();
+    let config: NotifierConfig = serde_json::from_str(&format!(
+        r#"{{"kind": "mqtt", "host": "127.0.0.1", "port": {}, "qos": 0, "ack_topic": "fmn/ack"}}"#,
+        broker.port
+    ))?;
+    let _notifier = config.build()?;
+    let topic = broker.subscriptions.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(topic, "fmn/ack");
+
+    broker.publish("fmn/ack", b"task-1\n");
+    broker.publish("fmn/ack", br#"{"task_id": "task-2"}"#);
+    for expected in ["task-1", "task-2"] {
+        match actions.recv_timeout(Duration::from_secs(5))? {
+            TaskAction::Acknowledge(task_id) => assert_eq!(task_id, expected),
+            action => return Err(anyhow!("unexpected action {:?}", action)),
+        }
+    }
+    Ok(())
+}
+
+// the lines the fake engine logged, once there are `count` of them
+fn wait_for_lines(path: &std::path::Path, count: usize) -> Result<Vec<String>> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let content = std::fs::read_to_string(path).unwrap_or_default();
+        let lines: Vec<String> = content.lines().map(str::to_owned).collect();
+        if lines.len() >= count {
+            return Ok(lines);
+        }
+        if Instant::now() > deadline {
+            return Err(anyhow!("{} lines logged instead of {}", lines.len(), count));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn tts_speaks_rendered_text() -> Result<()> {
+    let dir = tempdir()?;
+    let log = dir.path().join("tts.log");
+    let engine = dir.path().join("engine");
+    // a slow engine, which mustn't hold up the delivery
+    std::fs::write(
+        &engine,
+        format!("#!/bin/sh\nsleep 1\nprintf '%s\\n' \"$@\" >> {log:?}\n"),
+    )?;
+    std::process::Command::new("chmod")
+        .arg("+x")
+        .arg(&engine)
+        .status()?;
+    let config: NotifierConfig = serde_json::from_value(serde_json::json!({
+        "kind": "tts",
+        "engine": format!("{} -v {{voice}} -s {{rate}} {{text}}", engine.display()),
+        "voice": "en-gb",
+        "rate": 140,
+        "text": "{summary} says {description}",
+    }))?;
+    let mut firing = firing("stretch your legs");
+    firing.task.notification.summary = Some("Break".to_owned());
+    let notifier = config.build()?;
+    let start = Instant::now();
+    notifier.notify(&firing)?;
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(
+        wait_for_lines(&log, 5)?,
+        ["-v", "en-gb", "-s", "140", "Break says stretch your legs"]
+    );
+
+    // quiet from an hour ago until an hour from now, even around midnight
+    let now = get_local_now();
+    let format = time::macros::format_description!("[hour]:[minute]");
+    let config: NotifierConfig = serde_json::from_value(serde_json::json!({
+        "kind": "tts",
+        "engine": format!("{} {{text}}", engine.display()),
+        "quiet_hours": {
+            "start": (now - time::Duration::HOUR).format(format)?,
+            "end": (now + time::Duration::HOUR).format(format)?,
+        },
+    }))?;
+    config.build()?.notify(&firing)?;
+    std::thread::sleep(Duration::from_millis(1500));
+    assert_eq!(std::fs::read_to_string(&log)?.lines().count(), 5);
+    Ok(())
+}
+
+#[test]
+fn tts_engine_failure_is_an_error() -> Result<()> {
+    let config: NotifierConfig = serde_json::from_value(serde_json::json!({
+        "kind": "tts",
+        "engine": "/nonexistent/espeak {text}",
+    }))?;
+    let error = config.build()?.notify(&firing("hush")).unwrap_err();
+    assert!(error.to_string().contains("/nonexistent/espeak"), "{error}");
+
+    // an engine failing later is only logged
+    let config: NotifierConfig = serde_json::from_value(serde_json::json!({
+        "kind": "tts",
+        "engine": "sh -c {text}",
+        "text": "exit 3",
+    }))?;
+    config.build()?.notify(&firing("hush"))?;
+
+    let config: NotifierConfig =
+        serde_json::from_value(serde_json::json!({ "kind": "tts", "engine": "piper" }))?;
+    assert!(config.build().is_err());
+    for text in ["{count}", "{nope}"] {
+        let config: NotifierConfig =
+            serde_json::from_value(serde_json::json!({ "kind": "tts", "text": text }))?;
+        assert!(config.build().is_err(), "{text}");
+    }
+    Ok(())
+}
+
+#[test]
+fn journal_entry_has_task_fields() -> Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("journal.socket");
+    let socket = UnixDatagram::bind(&path)?;
+    socket.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let config: NotifierConfig = serde_json::from_value(serde_json::json!({
+        "kind": "journal",
+        "socket": path,
+    }))?;
+    let mut firing = firing("rotate the logs\non db-1");
+    firing.task = firing.task.with_context("ops".to_owned());
+    firing.task.notification.urgency = Some(Urgency::Critical);
+    config.build()?.notify(&firing)?;
+
+    let mut buf = vec![0; 4096];
+    let n = socket.recv(&mut buf)?;
+    let entry = &buf[..n];
+    let text = String::from_utf8_lossy(entry);
+    assert!(text.contains("PRIORITY=2\n"), "{text}");
+    assert!(text.contains("SYSLOG_IDENTIFIER=fmn\n"), "{text}");
+    assert!(text.contains(&format!("FMN_TASK_ID={}\n", firing.task.task_id)));
+    assert!(text.contains("FMN_CONTEXT=ops\n"), "{text}");
+    // a multi-line value is sent with its length
+    let mut message = b"MESSAGE\n".to_vec();
+    let value = "forget-me-not: rotate the logs\non db-1";
+    message.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    message.extend_from_slice(value.as_bytes());
+    assert!(entry.windows(message.len()).any(|w| w == message));
+    Ok(())
+}
+
+#[test]
+fn syslog_message_follows_rfc_5424() -> Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("log");
+    let socket = UnixDatagram::bind(&path)?;
+    socket.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let config: NotifierConfig = serde_json::from_value(serde_json::json!({
+        "kind": "syslog",
+        "socket": path,
+        "facility": "local3",
+    }))?;
+    let mut firing = firing("check [backups]");
+    firing.task = firing.task.with_context("o\"ps".to_owned());
+    config.build()?.notify(&firing)?;
+
+    let mut buf = vec![0; 4096];
+    let n = socket.recv(&mut buf)?;
+    let message = String::from_utf8(buf[..n].to_vec())?;
+    // local3 is facility 19, a normal task is a notice
+    assert!(message.starts_with("<157>1 "), "{message}");
+    assert!(message.contains(&format!(
+        " fmn {} firing [fmn@32473 task_id=\"{}\" context=\"o\\\"ps\" clock=\"every 1h\"] forget-me-not: check [backups]",
+        std::process::id(),
+        firing.task.task_id
+    )), "{message}");
+
+    let config: NotifierConfig =
+        serde_json::from_value(serde_json::json!({ "kind": "syslog", "facility": "local9" }))?;
+    assert!(config.build().is_err());
+    Ok(())
+}
+
+#[test]
+fn task_notifiers_override_routing() -> Result<()> {
+    let (desktop, desktop_firings) = Recorder::new("desktop", false);
+    let (webhook, webhook_firings) = Recorder::new("webhook", false);
+    let (tty, tty_firings) = Recorder::new("tty", false);
+    let mut registry = NotifierRegistry::new();
+    registry.register(desktop);
+    registry.register_routed(
+        webhook,
+        Routing {
+            contexts: vec!["work".to_owned()],
+            tasks: vec![],
+        },
+    );
+    registry.register_fallback(tty, Routing::default());
+
+    // named notifiers take the task even if routed elsewhere
+    let mut report = firing("send the report");
+    report.task.notification.notifiers = vec!["webhook".to_owned()];
+    registry.notify(&report)?;
+    let delivered = webhook_firings.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(delivered.task.description, "send the report");
+    assert!(desktop_firings.try_recv().is_err());
+
+    // a name without a notifier leaves it to the fallbacks
+    let mut lost = firing("water the plants");
+    lost.task.notification.notifiers = vec!["pager".to_owned()];
+    registry.notify(&lost)?;
+    let delivered = tty_firings.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(delivered.task.description, "water the plants");
+    assert!(desktop_firings.try_recv().is_err());
+    Ok(())
+}
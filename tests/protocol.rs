@@ -0,0 +1,475 @@
+use std::io::{BufReader, Write};
+use std::net::TcpListener;
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Deserializer, Value};
+use task_reminder::auth::{Auth, Scope, Tokens};
+use task_reminder::client::{send_request, send_request_as, subscribe};
+use task_reminder::comm::{
+    get_local_now, AddRequest, ContextCommand, Hello, Request, Response, PROTOCOL_VERSION,
+};
+use task_reminder::daemon::{listen, spawn_serve, ServerConfig};
+use task_reminder::endpoint::Endpoint;
+use task_reminder::events::{Event, EventBus, EventKind, Subscription};
+use task_reminder::scheduler::Scheduler;
+use task_reminder::task_manager::{ClockType, TaskManager};
+use task_reminder::tls::Credentials;
+use tempfile::{tempdir, TempDir};
+
+// a client end of a connection served by a daemon of its own
+struct Connection {
+    stream: UnixStream,
+    reader: BufReader<UnixStream>,
+    _fmn_dir: Option<TempDir>,
+}
+
+impl Connection {
+    fn open() -> Result<Self> {
+        let fmn_dir = tempdir()?;
+        let tm = TaskManager::new(&fmn_dir, Scheduler::new())?;
+        let mut connection = Self::open_to(Arc::new(Mutex::new(tm)), ServerConfig::default())?;
+        connection._fmn_dir = Some(fmn_dir);
+        Ok(connection)
+    }
+
+    // another connection to the same daemon
+    fn open_to(tm: Arc<Mutex<TaskManager>>, config: ServerConfig) -> Result<Self> {
+        Self::open_as(tm, config, Auth::Trusted)
+    }
+
+    fn open_as(tm: Arc<Mutex<TaskManager>>, config: ServerConfig, auth: Auth) -> Result<Self> {
+        let (client, daemon) = UnixStream::pair()?;
+        client.set_read_timeout(Some(Duration::from_secs(10)))?;
+        spawn_serve(daemon, tm, config, auth);
+        Ok(Self {
+            reader: BufReader::new(client.try_clone()?),
+            stream: client,
+            _fmn_dir: None,
+        })
+    }
+
+    fn greet(&mut self) -> Result<()> {
+        self.send(&Hello::current())?;
+        let _: Hello = self.receive()?;
+        Ok(())
+    }
+
+    fn send<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        self.stream
+            .write_all(serde_json::to_string(message)?.as_bytes())?;
+        Ok(())
+    }
+
+    fn receive<T: DeserializeOwned>(&mut self) -> Result<T> {
+        let mut messages = Deserializer::from_reader(&mut self.reader).into_iter::<T>();
+        Ok(messages.next().expect("connection is closed")?)
+    }
+
+    fn is_closed(&mut self) -> bool {
+        let mut messages = Deserializer::from_reader(&mut self.reader).into_iter::<Value>();
+        messages.next().is_none()
+    }
+}
+
+#[test]
+fn handshake_precedes_requests() -> Result<()> {
+    let mut connection = Connection::open()?;
+    connection.send(&Hello::current())?;
+    let hello: Hello = connection.receive()?;
+    assert_eq!(hello, Hello::current());
+    connection.send(&Request::Show)?;
+    assert!(matches!(
+        connection.receive()?,
+        Response::GetTasks(tasks) if tasks.is_empty()
+    ));
+    Ok(())
+}
+
+#[test]
+fn mismatched_protocol_is_rejected() -> Result<()> {
+    let mut connection = Connection::open()?;
+    let future = Hello {
+        protocol: PROTOCOL_VERSION + 1,
+        version: "9.0.0".to_owned(),
+        token: None,
+    };
+    connection.send(&future)?;
+    // the daemon tells its version, then hangs up
+    let hello: Hello = connection.receive()?;
+    assert_eq!(hello.protocol, PROTOCOL_VERSION);
+    assert!(connection.is_closed());
+
+    let error = future.check("fmn", &hello, "fmn-daemon").unwrap_err();
+    assert!(error.to_string().starts_with(&format!(
+        "fmn-daemon {} speaks protocol version {PROTOCOL_VERSION}",
+        hello.version
+    )));
+    Ok(())
+}
+
+#[test]
+fn request_without_handshake_fails() -> Result<()> {
+    let mut connection = Connection::open()?;
+    connection.send(&Request::Show)?;
+    match connection.receive()? {
+        Response::Fail(e) => assert!(e.contains("expects a protocol handshake"), "{e}"),
+        response => panic!("unexpected response {response:?}"),
+    }
+    assert!(connection.is_closed());
+    Ok(())
+}
+
+#[test]
+fn unknown_requests_and_fields() -> Result<()> {
+    let mut connection = Connection::open()?;
+    connection.send(&json!({ "protocol": PROTOCOL_VERSION, "version": "0.1.0", "os": "linux" }))?;
+    let _: Hello = connection.receive()?;
+
+    // an unknown request fails without closing the connection
+    connection.send(&json!({ "Frobnicate": 1 }))?;
+    match connection.receive()? {
+        Response::Fail(e) => assert!(e.contains("can't read the request"), "{e}"),
+        response => panic!("unexpected response {response:?}"),
+    }
+    // unknown fields are ignored and optional ones may be left out
+    connection.send(&json!({
+        "Add": {
+            "description": "water",
+            "clock_type": { "Period": "1h" },
+            "color": "blue",
+        }
+    }))?;
+    assert!(matches!(connection.receive()?, Response::AddSuccess));
+    connection.send(&Request::Show)?;
+    assert!(matches!(
+        connection.receive()?,
+        Response::GetTasks(tasks) if tasks.len() == 1 && tasks[0].description == "water"
+    ));
+    Ok(())
+}
+
+#[test]
+fn only_the_user_of_the_daemon_sends_exec_hooks() -> Result<()> {
+    let uid = rustix::process::getuid().as_raw();
+    assert!(matches!(Auth::of_peer(uid), Auth::Trusted));
+    assert!(matches!(Auth::of_peer(uid + 1), Auth::Peer));
+
+    let fmn_dir = tempdir()?;
+    let tm = Arc::new(Mutex::new(TaskManager::new(&fmn_dir, Scheduler::new())?));
+    let mut hook = AddRequest::new("backup".to_owned(), ClockType::OncePerDay(2, 0));
+    hook.exec = Some("~/bin/backup.sh".to_owned());
+    let send = |auth: Auth, request: Request| -> Result<Response> {
+        let mut connection = Connection::open_as(tm.clone(), ServerConfig::default(), auth)?;
+        connection.greet()?;
+        connection.send(&request)?;
+        connection.receive()
+    };
+
+    // another allowed user may use the socket but not run commands as ours
+    let request = Request::Add(AddRequest::new(
+        "stretch".to_owned(),
+        ClockType::Period("1h".to_owned()),
+    ));
+    assert!(matches!(send(Auth::Peer, request)?, Response::AddSuccess));
+    assert!(matches!(
+        send(Auth::Peer, Request::Add(hook.clone()))?,
+        Response::Unauthorized(reason) if reason.contains("user of fmn-daemon")
+    ));
+    let mut task = tm.lock().unwrap().get_tasks().remove(0);
+    task.add_exec("~/bin/backup.sh".to_owned());
+    assert!(matches!(
+        send(Auth::Peer, Request::Replace(task))?,
+        Response::Unauthorized(_)
+    ));
+    assert!(matches!(
+        send(Auth::Trusted, Request::Add(hook))?,
+        Response::AddSuccess
+    ));
+    Ok(())
+}
+
+#[test]
+fn stalled_client_does_not_block_others() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let tm = Arc::new(Mutex::new(TaskManager::new(&fmn_dir, Scheduler::new())?));
+    let config = ServerConfig {
+        idle_timeout_secs: 60,
+        read_timeout_secs: 1,
+        ..ServerConfig::default()
+    };
+    let mut stalled = Connection::open_to(tm.clone(), config.clone())?;
+    stalled.greet()?;
+    stalled.stream.write_all(br#"{"Sh"#)?;
+
+    let mut other = Connection::open_to(tm, config)?;
+    other.greet()?;
+    let add = json!({ "Add": { "description": "stretch", "clock_type": { "Period": "1h" } } });
+    other.send(&add)?;
+    assert!(matches!(other.receive()?, Response::AddSuccess));
+
+    // half a request is given up on after the read timeout
+    let started = Instant::now();
+    assert!(stalled.is_closed());
+    assert!(started.elapsed() < Duration::from_secs(5));
+    Ok(())
+}
+
+#[test]
+fn trickling_client_is_closed_after_the_read_timeout() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let tm = Arc::new(Mutex::new(TaskManager::new(&fmn_dir, Scheduler::new())?));
+    let config = ServerConfig {
+        idle_timeout_secs: 60,
+        read_timeout_secs: 1,
+        ..ServerConfig::default()
+    };
+    let mut connection = Connection::open_to(tm, config)?;
+    connection.greet()?;
+    // a byte at a time, each well within the read timeout
+    let started = Instant::now();
+    for byte in br#"{"Snooze": {"task_id": "#.iter().cycle().take(50) {
+        if connection.stream.write_all(&[*byte]).is_err() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    assert!(connection.is_closed());
+    assert!(started.elapsed() < Duration::from_secs(4));
+    Ok(())
+}
+
+#[test]
+fn connections_beyond_the_limit_are_refused() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let tm = Arc::new(Mutex::new(TaskManager::new(&fmn_dir, Scheduler::new())?));
+    let socket = fmn_dir.path().join("fmn.sock");
+    let config = ServerConfig {
+        max_connections: 1,
+        ..ServerConfig::default()
+    };
+    listen(&Endpoint::Unix(socket.clone()), tm, config, fmn_dir.path())?;
+    let dest = format!("unix:{}", socket.display());
+    let first = UnixStream::connect(&socket)?;
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(send_request(Request::Show, &dest).is_err());
+
+    // the place is free again once the first one is gone
+    drop(first);
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(matches!(
+        send_request(Request::Show, &dest)?,
+        Response::GetTasks(_)
+    ));
+    Ok(())
+}
+
+#[test]
+fn idle_connection_is_closed() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let tm = Arc::new(Mutex::new(TaskManager::new(&fmn_dir, Scheduler::new())?));
+    let config = ServerConfig {
+        idle_timeout_secs: 1,
+        read_timeout_secs: 10,
+        ..ServerConfig::default()
+    };
+    let mut connection = Connection::open_to(tm, config)?;
+    connection.greet()?;
+    connection.send(&Request::Show)?;
+    assert!(matches!(connection.receive()?, Response::GetTasks(_)));
+    let started = Instant::now();
+    assert!(connection.is_closed());
+    assert!(started.elapsed() < Duration::from_secs(5));
+    Ok(())
+}
+
+#[test]
+fn endpoints_are_parsed() {
+    let parse = |s: &str| s.parse::<Endpoint>().ok();
+    let unix = Some(Endpoint::Unix("/run/user/1000/fmn.sock".into()));
+    assert_eq!(parse("unix:/run/user/1000/fmn.sock"), unix);
+    assert_eq!(parse("unix:///run/user/1000/fmn.sock"), unix);
+    assert_eq!(parse("/run/user/1000/fmn.sock"), unix);
+    let tcp = Some(Endpoint::Tcp("127.0.0.1:8082".to_owned()));
+    assert_eq!(
+        parse("tls:fmn.example.com:8083"),
+        Some(Endpoint::Tls("fmn.example.com:8083".to_owned()))
+    );
+    assert_eq!(parse("tcp:127.0.0.1:8082"), tcp);
+    assert_eq!(parse("tcp://127.0.0.1:8082"), tcp);
+    assert_eq!(parse("127.0.0.1:8082"), tcp);
+    assert_eq!(
+        parse("tcp:localhost:8082"),
+        Some(Endpoint::Tcp("localhost:8082".to_owned()))
+    );
+    for invalid in [
+        "unix:",
+        "tcp:127.0.0.1",
+        "tcp::8082",
+        "fmn.sock",
+        "http://x:80",
+    ] {
+        assert_eq!(parse(invalid), None, "{invalid}");
+    }
+    // a socket of the user's own rather than one in /tmp
+    let Endpoint::Unix(socket) = Endpoint::default() else {
+        panic!("the default endpoint isn't a unix socket");
+    };
+    assert_eq!(socket.file_name().unwrap(), "fmn.sock");
+    assert_ne!(socket.parent().unwrap(), std::path::Path::new("/tmp"));
+    assert_eq!(
+        parse("tcp://127.0.0.1:8082").unwrap().to_string(),
+        "tcp:127.0.0.1:8082"
+    );
+}
+
+#[test]
+fn daemon_listens_on_several_endpoints() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let tm = Arc::new(Mutex::new(TaskManager::new(&fmn_dir, Scheduler::new())?));
+    let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+    let endpoints = [
+        format!("unix:{}", fmn_dir.path().join("fmn.sock").display()),
+        format!("tcp:127.0.0.1:{port}"),
+    ];
+    let mut tokens = Tokens::load(&fmn_dir)?;
+    let credentials = Credentials {
+        token: Some(tokens.create("test", Scope::Read)?),
+        ..Credentials::default()
+    };
+    tokens.save()?;
+    for endpoint in endpoints.iter() {
+        listen(
+            &endpoint.parse()?,
+            tm.clone(),
+            ServerConfig::default(),
+            fmn_dir.path(),
+        )?;
+    }
+    for endpoint in endpoints.iter() {
+        assert!(matches!(
+            send_request_as(Request::Show, endpoint, &credentials)?,
+            Response::GetTasks(tasks) if tasks.is_empty()
+        ));
+    }
+
+    // the commands of tasks are only shown to clients that may run them
+    let mut add = AddRequest::new("backup".to_owned(), ClockType::OncePerDay(2, 0));
+    add.exec = Some("~/bin/backup.sh".to_owned());
+    send_request(Request::Add(add), &endpoints[0])?;
+    let exec = |endpoint: &str| -> Result<Option<String>> {
+        match send_request_as(Request::Show, endpoint, &credentials)? {
+            Response::GetTasks(tasks) => Ok(tasks[0].get_exec().cloned()),
+            response => panic!("unexpected response {response:?}"),
+        }
+    };
+    assert_eq!(exec(&endpoints[0])?.as_deref(), Some("~/bin/backup.sh"));
+    assert_eq!(exec(&endpoints[1])?, None);
+    Ok(())
+}
+
+// the events of a subscription, passed on by a thread of their own
+fn subscribe_in_background(subscription: Subscription, dest: &str) -> Result<Receiver<Event>> {
+    let events = subscribe(subscription, dest)?;
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        for event in events {
+            if sender.send(event.expect("invalid event")).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+#[test]
+fn subscribers_receive_events() -> Result<()> {
+    let fmn_dir = tempdir()?;
+    let tm = Arc::new(Mutex::new(TaskManager::new(&fmn_dir, Scheduler::new())?));
+    let dest = format!("unix:{}", fmn_dir.path().join("fmn.sock").display());
+    listen(&dest.parse()?, tm, ServerConfig::default(), fmn_dir.path())?;
+    let everything = subscribe_in_background(Subscription::default(), &dest)?;
+    let switches = subscribe_in_background(
+        Subscription {
+            kinds: vec![EventKind::ContextSwitched, EventKind::TaskRemoved],
+            context: None,
+        },
+        &dest,
+    )?;
+    let in_work = subscribe_in_background(
+        Subscription {
+            kinds: vec![],
+            context: Some("work".to_owned()),
+        },
+        &dest,
+    )?;
+    let next = |events: &Receiver<Event>| events.recv_timeout(Duration::from_secs(10));
+
+    let soon = get_local_now() + Duration::from_secs(1);
+    let mut add = AddRequest::new("stretch".to_owned(), ClockType::Once(soon));
+    add.exec = Some("true".to_owned());
+    assert!(matches!(
+        send_request(Request::Add(add), &dest)?,
+        Response::AddSuccess
+    ));
+    let Event::TaskAdded { task } = next(&everything)? else {
+        panic!("expect the task to be added first");
+    };
+    assert_eq!(task.description, "stretch");
+    // subscribers don't see the commands of tasks
+    assert_eq!(task.get_exec(), None);
+    assert!(matches!(
+        next(&everything)?,
+        Event::ReminderFired { firing } if firing.task_id == task.task_id
+    ));
+
+    let define = ContextCommand::Define {
+        context: "work".to_owned(),
+        settings: Default::default(),
+    };
+    send_request(Request::ContextRequest(define), &dest)?;
+    let set = ContextCommand::Set {
+        context: "work".to_owned(),
+    };
+    send_request(Request::ContextRequest(set), &dest)?;
+    let add = AddRequest::new("review".to_owned(), ClockType::Period("1h".to_owned()));
+    send_request(Request::Add(add), &dest)?;
+    assert!(matches!(
+        next(&everything)?,
+        Event::ContextSwitched { to, .. } if to == "work"
+    ));
+    assert!(matches!(next(&everything)?, Event::TaskAdded { .. }));
+
+    // only the kinds and the context subscribed to
+    assert!(matches!(next(&switches)?, Event::ContextSwitched { .. }));
+    assert!(matches!(next(&in_work)?, Event::ContextSwitched { .. }));
+    assert!(matches!(
+        next(&in_work)?,
+        Event::TaskAdded { task } if task.description == "review"
+    ));
+    assert!(switches.recv_timeout(Duration::from_millis(200)).is_err());
+    Ok(())
+}
+
+#[test]
+fn lagging_subscribers_are_dropped() {
+    let bus = EventBus::default();
+    let events = bus.subscribe();
+    let switch = || Event::ContextSwitched {
+        from: "default".to_owned(),
+        to: "work".to_owned(),
+    };
+    for _ in 0..1000 {
+        bus.publish(switch());
+    }
+    // what was queued is still delivered, then the stream ends
+    assert!(events.iter().count() < 1000);
+    let events = bus.subscribe();
+    bus.publish(switch());
+    assert!(events.try_recv().is_ok());
+}
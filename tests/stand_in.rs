@@ -0,0 +1,288 @@
+// local stand-ins for the servers the network notifiers talk to
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    // header names are lowercased
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+// answers requests with `statuses` in order, then with 200
+pub fn http_stand_in(statuses: Vec<u16>) -> (String, Receiver<HttpRequest>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("fail to bind http stand-in");
+    let addr = listener.local_addr().unwrap();
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        let mut statuses = statuses.into_iter();
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { return };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).is_err() {
+                continue;
+            }
+            let mut parts = request_line.split_whitespace();
+            let method = parts.next().unwrap_or_default().to_owned();
+            let path = parts.next().unwrap_or_default().to_owned();
+            let mut headers = HashMap::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+                }
+            }
+            let length = headers
+                .get("content-length")
+                .map(|l| l.parse().unwrap())
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let request = HttpRequest {
+                method,
+                path,
+                headers,
+                body: String::from_utf8(body).unwrap(),
+            };
+            // recorded before answering, so the client never sees a response
+            // of a request the test can't see yet
+            if sender.send(request).is_err() {
+                return;
+            }
+            let status = statuses.next().unwrap_or(200);
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {status} STAND-IN\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            );
+        }
+    });
+    (format!("http://{addr}"), receiver)
+}
+
+#[derive(Debug, Default)]
+pub struct SmtpMail {
+    pub auth: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    pub data: String,
+}
+
+// a plain-text smtp server; the first `fail_first` sessions are turned down
+// with a temporary error
+pub fn smtp_stand_in(fail_first: usize) -> (u16, Receiver<SmtpMail>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("fail to bind smtp stand-in");
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        for (session, stream) in listener.incoming().enumerate() {
+            let Ok(mut stream) = stream else { return };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut mail = SmtpMail::default();
+            let _ = write!(stream, "220 stand-in ESMTP\r\n");
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                    break;
+                }
+                let line = line.trim_end();
+                let command = line.to_uppercase();
+                let reply = if command.starts_with("EHLO") {
+                    "250-stand-in\r\n250 AUTH PLAIN LOGIN".to_owned()
+                } else if command.starts_with("AUTH") {
+                    mail.auth = Some(line.to_owned());
+                    "235 2.7.0 authenticated".to_owned()
+                } else if command.starts_with("MAIL FROM") {
+                    if session < fail_first {
+                        "451 4.3.0 try again later".to_owned()
+                    } else {
+                        mail.from = line[10..].to_owned();
+                        "250 ok".to_owned()
+                    }
+                } else if command.starts_with("RCPT TO") {
+                    mail.to.push(line[8..].to_owned());
+                    "250 ok".to_owned()
+                } else if command == "DATA" {
+                    let _ = write!(stream, "354 go ahead\r\n");
+                    loop {
+                        let mut data = String::new();
+                        if reader.read_line(&mut data).unwrap_or(0) == 0 || data == ".\r\n" {
+                            break;
+                        }
+                        mail.data.push_str(&data);
+                    }
+                    let _ = sender.send(std::mem::take(&mut mail));
+                    "250 queued".to_owned()
+                } else if command == "QUIT" {
+                    let _ = write!(stream, "221 bye\r\n");
+                    break;
+                } else {
+                    "250 ok".to_owned()
+                };
+                let _ = write!(stream, "{reply}\r\n");
+            }
+        }
+    });
+    (port, receiver)
+}
+
+#[derive(Debug)]
+pub struct MqttMessage {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub qos: u8,
+    pub retain: bool,
+}
+
+// a bare mqtt 3.1.1 broker: it records what clients publish, tells about
+// subscriptions and lets the test publish to subscribers
+pub struct MqttStandIn {
+    pub port: u16,
+    pub messages: Receiver<MqttMessage>,
+    pub subscriptions: Receiver<String>,
+    subscribers: Arc<Mutex<Vec<(String, TcpStream)>>>,
+}
+
+impl MqttStandIn {
+    // sent with qos 0 to the subscribers of exactly this topic
+    pub fn publish(&self, topic: &str, payload: &[u8]) {
+        let mut body = (topic.len() as u16).to_be_bytes().to_vec();
+        body.extend_from_slice(topic.as_bytes());
+        body.extend_from_slice(payload);
+        for (filter, stream) in self.subscribers.lock().unwrap().iter_mut() {
+            if filter == topic {
+                let _ = write_packet(stream, 0x30, &body);
+            }
+        }
+    }
+}
+
+pub fn mqtt_stand_in() -> MqttStandIn {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("fail to bind mqtt stand-in");
+    let port = listener.local_addr().unwrap().port();
+    let (message_sender, messages) = channel();
+    let (subscription_sender, subscriptions) = channel();
+    let subscribers = Arc::new(Mutex::new(vec![]));
+    let shared = subscribers.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { return };
+            let messages = message_sender.clone();
+            let subscriptions = subscription_sender.clone();
+            let subscribers = shared.clone();
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut stream = stream;
+                while let Some((header, body)) = read_packet(&mut reader) {
+                    let reply = match header >> 4 {
+                        // connect
+                        1 => write_packet(&mut stream, 0x20, &[0, 0]),
+                        // publish
+                        3 => {
+                            let qos = (header >> 1) & 3;
+                            let length = u16::from_be_bytes([body[0], body[1]]) as usize;
+                            let topic = String::from_utf8_lossy(&body[2..2 + length]).into_owned();
+                            let mut rest = &body[2 + length..];
+                            let id = if qos > 0 {
+                                let id = [rest[0], rest[1]];
+                                rest = &rest[2..];
+                                Some(id)
+                            } else {
+                                None
+                            };
+                            let _ = messages.send(MqttMessage {
+                                topic,
+                                payload: rest.to_vec(),
+                                qos,
+                                retain: header & 1 == 1,
+                            });
+                            match (qos, id) {
+                                (1, Some(id)) => write_packet(&mut stream, 0x40, &id),
+                                (2, Some(id)) => write_packet(&mut stream, 0x50, &id),
+                                _ => Ok(()),
+                            }
+                        }
+                        // pubrel
+                        6 => write_packet(&mut stream, 0x70, &body[..2]),
+                        // subscribe, granted with qos 0
+                        8 => {
+                            let mut rest = &body[2..];
+                            let mut granted = body[..2].to_vec();
+                            while rest.len() > 2 {
+                                let length = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+                                let filter =
+                                    String::from_utf8_lossy(&rest[2..2 + length]).into_owned();
+                                rest = &rest[3 + length..];
+                                granted.push(0);
+                                subscribers
+                                    .lock()
+                                    .unwrap()
+                                    .push((filter.clone(), stream.try_clone().unwrap()));
+                                let _ = subscriptions.send(filter);
+                            }
+                            write_packet(&mut stream, 0x90, &granted)
+                        }
+                        // pingreq
+                        12 => write_packet(&mut stream, 0xd0, &[]),
+                        _ => return,
+                    };
+                    if reply.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    MqttStandIn {
+        port,
+        messages,
+        subscriptions,
+        subscribers,
+    }
+}
+
+fn read_packet(reader: &mut impl Read) -> Option<(u8, Vec<u8>)> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte).ok()?;
+    let header = byte[0];
+    let (mut length, mut shift) = (0, 0);
+    loop {
+        reader.read_exact(&mut byte).ok()?;
+        length |= ((byte[0] & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    Some((header, body))
+}
+
+fn write_packet(stream: &mut TcpStream, header: u8, body: &[u8]) -> std::io::Result<()> {
+    let mut packet = vec![header];
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    stream.write_all(&packet)
+}
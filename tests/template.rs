@@ -0,0 +1,135 @@
+use anyhow::Result;
+use task_reminder::comm::get_local_now;
+use task_reminder::notify::Firing;
+use task_reminder::task_manager::{ClockType, Task};
+use task_reminder::template::{render_firing, Template};
+use time::macros::{datetime, offset};
+
+fn firing_at(description: &str, clock_type: ClockType) -> Firing {
+    let task = Task::new(description.to_owned(), clock_type);
+    let fired_at = datetime!(2023-11-12 22:00).assume_offset(offset!(+8));
+    Firing {
+        stored: task.clone(),
+        task,
+        scheduled_at: fired_at,
+        fired_at,
+        takes_actions: true,
+    }
+}
+
+#[test]
+fn template_renders_variables() -> Result<()> {
+    let firing = firing_at(
+        "Drink water ({occurrence} of {count} today)",
+        ClockType::Period("1h".to_owned()),
+    );
+    assert_eq!(
+        render_firing(&firing, 3).task.description,
+        "Drink water (3 of 5 today)"
+    );
+
+    let firing = firing_at(
+        "{weekday} {date} {time}: standup in {minutes_until:23:30}, next in {minutes_until}",
+        ClockType::Period("1.5h".to_owned()),
+    );
+    assert_eq!(
+        render_firing(&firing, 1).task.description,
+        "Sunday 2023-11-12 22:00: standup in 90, next in 90"
+    );
+
+    let firing = firing_at("{{literal}} {occurrence}", ClockType::OncePerDay(22, 0));
+    assert_eq!(render_firing(&firing, 1).task.description, "{literal} 1");
+    Ok(())
+}
+
+#[test]
+fn template_renders_created_ago() -> Result<()> {
+    let task = Task::new(
+        "Created {created_ago} ago".to_owned(),
+        ClockType::OncePerDay(9, 0),
+    );
+    let fired_at = get_local_now() + time::Duration::hours(76) + time::Duration::seconds(10);
+    let firing = Firing {
+        stored: task.clone(),
+        task,
+        scheduled_at: fired_at,
+        fired_at,
+        takes_actions: true,
+    };
+    assert_eq!(
+        render_firing(&firing, 1).task.description,
+        "Created 3d 4h ago"
+    );
+    Ok(())
+}
+
+#[test]
+fn template_renders_summary() -> Result<()> {
+    let mut firing = firing_at("stretch", ClockType::Period("1h".to_owned()));
+    firing.task.notification.summary = Some("Break #{occurrence}".to_owned());
+    let rendered = render_firing(&firing, 2);
+    assert_eq!(
+        rendered.task.notification.summary.as_deref(),
+        Some("Break #2")
+    );
+    assert_eq!(rendered.task.description, "stretch");
+    Ok(())
+}
+
+#[test]
+fn template_keeps_unparsable_text() {
+    let firing = firing_at("fn main() { }", ClockType::Period("1h".to_owned()));
+    assert_eq!(render_firing(&firing, 1).task.description, "fn main() { }");
+}
+
+#[test]
+fn template_err() {
+    for (input, position, reason) in [
+        ("hi {name}", 3, "unknown placeholder {name}"),
+        ("hi {occurrence", 3, "unclosed placeholder"),
+        ("hi }", 3, "unmatched }"),
+        ("{count:3}", 0, "placeholder {count} takes no argument"),
+        (
+            "in {minutes_until:25:00}",
+            3,
+            "\"25:00\" isn't a time of day like 10:00",
+        ),
+    ] {
+        let err = Template::parse(input).unwrap_err();
+        assert_eq!(err.position, position, "{input}");
+        assert_eq!(err.reason, reason, "{input}");
+    }
+    let err = Template::parse("hi {name}").unwrap_err().to_string();
+    assert!(err.contains("\n  hi {name}\n     ^\n"));
+}
+
+#[test]
+fn notifier_texts_know_the_rendered_firing() -> Result<()> {
+    let mut firing = firing_at("Stretch", ClockType::Period("1h".to_owned()));
+    firing.task.notification.summary = Some("Break".to_owned());
+    let template = Template::parse_notifier_text("{summary}: {description} at {fired_at}")?;
+    assert_eq!(
+        template.render(&firing, 0),
+        format!(
+            "Break: Stretch at {}",
+            firing
+                .fired_at
+                .to_offset(task_reminder::comm::get_local_utc_offset())
+                .format(time::macros::format_description!(
+                    "[year]-[month]-[day] [hour]:[minute]"
+                ))?
+        )
+    );
+
+    let err = Template::parse_notifier_text("{occurrence}").unwrap_err();
+    assert_eq!(
+        err.reason,
+        "{occurrence} is only known to descriptions and summaries"
+    );
+    let err = Template::parse("{description}").unwrap_err();
+    assert_eq!(
+        err.reason,
+        "{description} is only known to notifier templates"
+    );
+    Ok(())
+}